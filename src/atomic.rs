@@ -0,0 +1,168 @@
+use crate::Result;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// The suffix that is appended to the name of a backup when none is given.
+pub const DEFAULT_BACKUP_SUFFIX: &str = ".bak";
+
+/// Write the given data to the file at the given path without ever leaving a partially written
+/// file behind.
+///
+/// The data is first written to a temporary file in the same directory, which is flushed to disk
+/// and then renamed over the target, so the target holds either the old or the new content even
+/// if the process is interrupted or the disk runs full. When the target already exists, the new
+/// file gets its permissions and modification time, and the old file is kept next to it under a
+/// name with the given suffix if a backup suffix is given.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::write_atomic;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let path = std::env::temp_dir().join(format!("pmsg-doc-{}.png", std::process::id()));
+///     write_atomic(&path, b"old", None)?;
+///     write_atomic(&path, b"new", Some(".orig"))?;
+///
+///     let mut backup = path.clone().into_os_string();
+///     backup.push(".orig");
+///     assert_eq!(b"new".to_vec(), std::fs::read(&path)?);
+///     assert_eq!(b"old".to_vec(), std::fs::read(&backup)?);
+///     # std::fs::remove_file(&path)?;
+///     # std::fs::remove_file(&backup)?;
+///     Ok(())
+/// # }
+/// ```
+pub fn write_atomic(path: &Path, data: &[u8], backup_suffix: Option<&str>) -> Result<()> {
+    // replace the file that a symbolic link points to rather than the link
+    let path = &fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let metadata = fs::metadata(path).ok();
+    if let (Some(suffix), Some(_)) = (backup_suffix, &metadata) {
+        // the old file is never written to, so a hard link keeps its content and metadata
+        let backup = with_suffix(path, suffix);
+        if backup.exists() {
+            fs::remove_file(&backup)?;
+        }
+        if fs::hard_link(path, &backup).is_err() {
+            fs::copy(path, &backup)?;
+        }
+    }
+
+    let temp_path = with_suffix(
+        &path.with_file_name(format!(".{}", file_name(path).to_string_lossy())),
+        &format!(".{}.tmp", process::id()),
+    );
+    let result = (|| {
+        let mut temp = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        temp.write_all(data)?;
+        if let Some(metadata) = &metadata {
+            temp.set_permissions(metadata.permissions())?;
+            temp.set_modified(metadata.modified()?)?;
+        }
+        temp.sync_all()?;
+        fs::rename(&temp_path, path)
+    })();
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e.into());
+    }
+
+    // make the rename itself durable, which is not supported on every platform
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+/// Get the given path with the given suffix appended to its file name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name: OsString = file_name(path).to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Get the file name of the given path, or the whole path if it does not end with a file name.
+fn file_name(path: &Path) -> &std::ffi::OsStr {
+    path.file_name().unwrap_or(path.as_os_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn testing_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pmsg-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_write_new_file() {
+        let dir = testing_dir("atomic-new");
+        let path = dir.join("image.png");
+        write_atomic(&path, b"data", Some(DEFAULT_BACKUP_SUFFIX)).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"data");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_overwrite_keeps_metadata() {
+        let dir = testing_dir("atomic-metadata");
+        let path = dir.join("image.png");
+        fs::write(&path, b"old").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions).unwrap();
+
+        write_atomic(&path, b"new", Some(".orig")).unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(metadata.modified().unwrap(), modified);
+        assert!(metadata.permissions().readonly());
+
+        let backup = dir.join("image.png.orig");
+        assert_eq!(fs::read(&backup).unwrap(), b"old");
+        assert_eq!(fs::metadata(&backup).unwrap().modified().unwrap(), modified);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        let mut permissions = metadata.permissions();
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(&path, permissions.clone()).unwrap();
+        fs::set_permissions(&backup, permissions).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_write_keeps_original() {
+        let dir = testing_dir("atomic-failed");
+        let path = dir.join("missing").join("image.png");
+        assert!(write_atomic(&path, b"data", None).is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
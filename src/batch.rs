@@ -0,0 +1,425 @@
+use pmsg::*;
+
+use crate::printer::Printer;
+use crate::report;
+use rayon::prelude::*;
+use serde_json::{json, Value};
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A command that is run for every file of a batch, with the path of the file and the printer for
+/// its results.
+pub type Command = fn(&clap::ArgMatches, &str, &mut Printer) -> Result<()>;
+
+/// Run the given command for every file that is given on the command line and get the exit code.
+/// A single file is processed on its own, and its errors are returned. More files are processed
+/// in parallel, and their results are printed per file in the order in which the files were
+/// given, followed by a summary on the standard error. Unless `--continue-on-error` is given, no
+/// more files are started after a file fails. The exit code is the one of the first file that
+/// failed.
+pub fn run(matches: &clap::ArgMatches, command: Command) -> Result<i32> {
+    let paths = input_paths(matches)?;
+    if let [path] = paths.as_slice() {
+        let mut printer = Printer::new(matches);
+        command(matches, path, &mut printer)?;
+        printer.finish();
+        return Ok(0);
+    }
+    if matches.is_present("output") {
+        return Err(usage_error(
+            "an output path can not be given for more than one file".to_string(),
+        ));
+    }
+    if matches.is_present("raw") {
+        return Err(usage_error(
+            "raw data can not be written to the standard output for more than one file".to_string(),
+        ));
+    }
+    // every file would read the standard input on its own, and all but one would get nothing
+    if matches.is_present("stdin") {
+        return Err(usage_error(
+            "the standard input can not be read for more than one file".to_string(),
+        ));
+    }
+
+    let jobs = match matches.value_of("jobs") {
+        Some(jobs) => jobs
+            .parse()
+            .map_err(|_| usage_error(format!("invalid number of jobs '{}'", jobs)))?,
+        None => 0,
+    };
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build()
+        .map_err(|e| Error::IOError(io::Error::other(e)))?;
+
+    let continue_on_error = matches.is_present("continue_on_error");
+    let failed = AtomicBool::new(false);
+    let outcomes: Vec<Option<(Printer, Result<()>)>> = pool.install(|| {
+        paths
+            .par_iter()
+            .map(|path| {
+                if failed.load(Ordering::Relaxed) && !continue_on_error {
+                    return None;
+                }
+                let mut printer = Printer::buffered(matches);
+                let result = command(matches, path, &mut printer);
+                if result.is_err() {
+                    failed.store(true, Ordering::Relaxed);
+                }
+                Some((printer, result))
+            })
+            .collect()
+    });
+
+    let mut printer = Printer::new(matches);
+    let (mut succeeded, mut failed, mut skipped) = (0, 0, 0);
+    let mut exit_code = 0;
+    for (path, outcome) in paths.iter().zip(outcomes) {
+        let (file_printer, result) = match outcome {
+            Some(outcome) => outcome,
+            None => {
+                skipped += 1;
+                continue;
+            }
+        };
+
+        let (lines, records) = file_printer.into_results();
+        if !lines.is_empty() {
+            if succeeded + failed > 0 {
+                printer.text("");
+            }
+            printer.text(format!("{}:", path));
+            for line in lines {
+                printer.text(line);
+            }
+        }
+        for mut record in records {
+            if let Value::Object(fields) = &mut record {
+                fields.insert("path".to_string(), json!(path));
+            }
+            printer.record(record);
+        }
+
+        match result {
+            Ok(()) => succeeded += 1,
+            Err(e) => {
+                failed += 1;
+                let code = report::exit_code(&e);
+                if exit_code == 0 {
+                    exit_code = code;
+                }
+                report::report_file(path, &e);
+                printer.record(json!({ "path": path, "error": e.to_string(), "exit_code": code }));
+            }
+        }
+    }
+    printer.finish();
+
+    eprintln!(
+        "{} file(s): {} succeeded, {} failed, {} skipped",
+        paths.len(),
+        succeeded,
+        failed,
+        skipped
+    );
+    Ok(exit_code)
+}
+
+/// Get the paths of the files to process. These are the paths given on the command line, where
+/// glob patterns are expanded and directories are searched for PNG files with `--recursive`,
+/// followed by the paths listed in the file given with `--files-from`, one per line, which is read
+/// from the standard input if it is `-`. Paths that are given more than once are only kept once.
+pub fn input_paths(matches: &clap::ArgMatches) -> Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for value in matches.values_of("file").into_iter().flatten() {
+        expand_path(matches, value, &mut paths)?;
+    }
+    if let Some(list) = matches.value_of("files_from") {
+        let mut text = String::new();
+        if list == "-" && matches.is_present("stdin") {
+            return Err(usage_error(
+                "the standard input can not give both the files and the data to hide".to_string(),
+            ));
+        } else if list == "-" {
+            io::stdin().read_to_string(&mut text)?;
+        } else {
+            text = fs::read_to_string(list).map_err(|e| {
+                Error::IOError(io::Error::new(
+                    e.kind(),
+                    format!("can not read {}: {}", list, e),
+                ))
+            })?;
+        }
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            expand_path(matches, line, &mut paths)?;
+        }
+    }
+
+    let mut seen = HashSet::new();
+    paths.retain(|path| seen.insert(path.clone()));
+    if paths.is_empty() {
+        return Err(Error::IOError(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no PNG files were found",
+        )));
+    }
+    Ok(paths)
+}
+
+/// Add the files for the given path to the list. A directory is searched for PNG files with
+/// `--recursive`, and a glob pattern is expanded if no file has its name. Other paths are added
+/// as they are, so that missing files are reported when they are processed.
+fn expand_path(matches: &clap::ArgMatches, value: &str, paths: &mut Vec<String>) -> Result<()> {
+    let path = Path::new(value);
+    if path.is_dir() {
+        if !matches.is_present("recursive") {
+            return Err(usage_error(format!(
+                "{} is a directory, use --recursive to process the PNG files in it",
+                value
+            )));
+        }
+        return find_png_files(path, true, paths);
+    }
+
+    if !path.exists() && value.contains(&['*', '?', '['][..]) {
+        let entries = glob::glob(value)
+            .map_err(|e| usage_error(format!("invalid pattern '{}': {}", value, e)))?;
+        let count = paths.len();
+        for entry in entries {
+            let entry = entry.map_err(|e| Error::IOError(e.into()))?;
+            if !entry.is_dir() {
+                paths.push(entry.to_string_lossy().into_owned());
+            } else if matches.is_present("recursive") {
+                find_png_files(&entry, true, paths)?;
+            }
+        }
+        if paths.len() == count {
+            return Err(Error::IOError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no files match {}", value),
+            )));
+        }
+        return Ok(());
+    }
+
+    paths.push(value.to_string());
+    Ok(())
+}
+
+/// Add the files with the "png" extension in the given directory, and in its subdirectories if
+/// asked, to the list, in the order of their names. Symbolic links to directories are not
+/// followed.
+pub fn find_png_files(dir: &Path, recursive: bool, paths: &mut Vec<String>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)
+        .and_then(|entries| entries.collect::<io::Result<Vec<fs::DirEntry>>>())
+        .map_err(|e| {
+            Error::IOError(io::Error::new(
+                e.kind(),
+                format!("can not read {}: {}", dir.display(), e),
+            ))
+        })?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            if recursive {
+                find_png_files(&path, recursive, paths)?;
+            }
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+        {
+            paths.push(path.to_string_lossy().into_owned());
+        }
+    }
+    Ok(())
+}
+
+/// Create the error for an invalid combination of arguments on the command line.
+pub fn usage_error(message: String) -> Error {
+    Error::Usage(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{App, Arg};
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+
+    // the paths for which the testing commands were called
+    static CALLS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    fn testing_matches(args: &[&str]) -> clap::ArgMatches<'static> {
+        App::new("test")
+            .arg(Arg::with_name("file").multiple(true).index(1))
+            .arg(Arg::with_name("output").long("output").takes_value(true))
+            .arg(Arg::with_name("stdin").long("stdin"))
+            .arg(Arg::with_name("raw").long("raw"))
+            .args(&crate::batch_args())
+            .get_matches_from(std::iter::once("test").chain(args.iter().copied()))
+    }
+
+    fn testing_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pmsg-batch-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        for file in [
+            "b.png",
+            "a.PNG",
+            "notes.txt",
+            "sub/c.png",
+            "sub/deeper/d.png",
+        ]
+        .iter()
+        {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+        dir
+    }
+
+    fn path(dir: &Path, file: &str) -> String {
+        dir.join(file).to_string_lossy().into_owned()
+    }
+
+    fn fail_on_bad(_: &clap::ArgMatches, path: &str, printer: &mut Printer) -> Result<()> {
+        CALLS.lock().unwrap().push(path.to_string());
+        printer.text(path);
+        if path.contains("bad") {
+            return Err(Error::InvalidPNGFileHeader);
+        }
+        if path.contains("missing") {
+            return Err(Error::ChunkTypeNotFound);
+        }
+        Ok(())
+    }
+
+    fn is_usage_error(result: Result<Vec<String>>) -> bool {
+        matches!(result, Err(Error::Usage(_)))
+    }
+
+    #[test]
+    fn test_glob_pattern() {
+        let dir = testing_dir("glob");
+        let pattern = path(&dir, "*.png");
+        let paths = input_paths(&testing_matches(&[&pattern])).unwrap();
+        assert_eq!(paths, vec![path(&dir, "b.png")]);
+
+        let pattern = path(&dir, "**/*.png");
+        let paths = input_paths(&testing_matches(&[&pattern])).unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                path(&dir, "b.png"),
+                path(&dir, "sub/c.png"),
+                path(&dir, "sub/deeper/d.png")
+            ]
+        );
+
+        let pattern = path(&dir, "*.gif");
+        assert!(matches!(
+            input_paths(&testing_matches(&[&pattern])),
+            Err(Error::IOError(e)) if e.kind() == io::ErrorKind::NotFound
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_directory() {
+        let dir = testing_dir("directory");
+        let arg = dir.to_string_lossy().into_owned();
+        assert!(is_usage_error(input_paths(&testing_matches(&[&arg]))));
+
+        let paths = input_paths(&testing_matches(&[&arg, "-r"])).unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                path(&dir, "a.PNG"),
+                path(&dir, "b.png"),
+                path(&dir, "sub/c.png"),
+                path(&dir, "sub/deeper/d.png")
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_duplicates_and_files_from() {
+        let dir = testing_dir("files-from");
+        let list = path(&dir, "list.txt");
+        fs::write(
+            &list,
+            format!(
+                "{}\n\n  {}  \n",
+                path(&dir, "sub/c.png"),
+                path(&dir, "b.png")
+            ),
+        )
+        .unwrap();
+        let (b, pattern) = (path(&dir, "b.png"), path(&dir, "*.png"));
+        let paths =
+            input_paths(&testing_matches(&[&b, &pattern, &b, "--files-from", &list])).unwrap();
+        assert_eq!(paths, vec![b, path(&dir, "sub/c.png")]);
+
+        assert!(is_usage_error(input_paths(&testing_matches(&[
+            "--files-from",
+            "-",
+            "--stdin"
+        ]))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_first_failure_skips_the_rest() {
+        let matches =
+            testing_matches(&["one-ok", "one-bad", "one-missing", "one-later", "-j", "1"]);
+        assert_eq!(run(&matches, fail_on_bad).unwrap(), report::EXIT_NOT_PNG);
+        let calls = CALLS.lock().unwrap();
+        assert!(calls.contains(&"one-bad".to_string()));
+        assert!(!calls.contains(&"one-later".to_string()));
+    }
+
+    #[test]
+    fn test_continue_on_error() {
+        let matches = testing_matches(&[
+            "two-ok",
+            "two-missing",
+            "two-bad",
+            "two-later",
+            "--continue-on-error",
+        ]);
+        assert_eq!(run(&matches, fail_on_bad).unwrap(), report::EXIT_NOT_FOUND);
+        let calls = CALLS.lock().unwrap();
+        for path in ["two-ok", "two-missing", "two-bad", "two-later"].iter() {
+            assert!(calls.contains(&path.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_single_file_errors_are_returned() {
+        let matches = testing_matches(&["three-bad"]);
+        assert!(matches!(
+            run(&matches, fail_on_bad),
+            Err(Error::InvalidPNGFileHeader)
+        ));
+    }
+
+    #[test]
+    fn test_options_for_one_file_only() {
+        for option in [&["--output", "out.png"][..], &["--stdin"], &["--raw"]].iter() {
+            let mut args = vec!["four-a", "four-b"];
+            args.extend_from_slice(option);
+            let result = run(&testing_matches(&args), fail_on_bad);
+            assert!(matches!(result, Err(Error::Usage(_))));
+        }
+        let calls = CALLS.lock().unwrap();
+        assert!(!calls.iter().any(|path| path.starts_with("four")));
+    }
+}
@@ -1,8 +1,8 @@
 use crate::chunk_type::ChunkType;
-use crate::{Error, Result};
+use crate::{Error, RawChunk, Result};
 use crc::{crc32, Hasher32};
 use std::convert::TryInto;
-use std::io::{Cursor, Read};
+use std::ops::{Deref, DerefMut};
 
 /// Parse a chunk from bytes as described by the specifications of PNG files
 /// ([PNG Structure](http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html)).
@@ -41,7 +41,12 @@ use std::io::{Cursor, Read};
 ///     let chunk = Chunk::try_from(raw_chunk.as_ref())?;
 ///     Ok(())
 /// # }
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "ChunkFields")
+)]
 pub struct Chunk {
     length: u32, // NOTE: this must not exceed 2^31
     chunk_type: ChunkType,
@@ -50,6 +55,9 @@ pub struct Chunk {
 }
 
 impl Chunk {
+    /// The largest number of bytes that the data of a chunk can contain.
+    pub const MAX_LENGTH: u32 = 1 << 31;
+
     /// Create a new chunk from the given chunk type and chunk data
     ///
     /// # Examples
@@ -73,23 +81,81 @@ impl Chunk {
     /// # }
     /// ```
     pub fn new(chunk_type: ChunkType, chunk_data: Vec<u8>) -> Result<Self> {
-        if chunk_data.len() > 1 << 31 {
+        if chunk_data.len() > Self::MAX_LENGTH as usize {
             return Err(Error::InvalidChunkLength);
         }
 
-        // creating checksum from received data
-        let mut digest = crc32::Digest::new(crc32::IEEE);
-        digest.write(&chunk_type.bytes());
-        digest.write(&chunk_data);
-
         Ok(Self {
             length: chunk_data.len().try_into()?,
+            crc: Self::compute_crc(&chunk_type, &chunk_data),
             chunk_type,
             chunk_data,
-            crc: digest.sum32(),
         })
     }
 
+    /// Compute the CRC checksum of a chunk with the given chunk type and chunk data.
+    pub fn compute_crc(chunk_type: &ChunkType, chunk_data: &[u8]) -> u32 {
+        Self::compute_crc_bytes(&chunk_type.bytes(), chunk_data)
+    }
+
+    /// Compute the CRC checksum of a chunk with the given type bytes, which do not have to be a
+    /// valid type code, and chunk data.
+    pub(crate) fn compute_crc_bytes(chunk_type: &[u8; 4], chunk_data: &[u8]) -> u32 {
+        let mut digest = crc32::Digest::new(crc32::IEEE);
+        digest.write(chunk_type);
+        digest.write(chunk_data);
+        digest.sum32()
+    }
+
+    /// Replace the data of the chunk, the length and the checksum are updated to match.
+    pub fn set_data(&mut self, chunk_data: Vec<u8>) -> Result<()> {
+        if chunk_data.len() > Self::MAX_LENGTH as usize {
+            return Err(Error::InvalidChunkLength);
+        }
+        self.chunk_data = chunk_data;
+        self.update();
+        Ok(())
+    }
+
+    /// Get the data of the chunk for changing it in place. The length and the checksum are
+    /// updated when the returned guard is dropped. The data can not grow beyond
+    /// `Chunk::MAX_LENGTH` bytes, the methods of the guard that add bytes fail instead.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use pmsg::{Chunk, ChunkType};
+    /// # use std::str::FromStr;
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    ///     let mut chunk = Chunk::new(ChunkType::from_str("ruSt")?, b"THE".to_vec())?;
+    ///     chunk.data_mut().extend_from_slice(b" CHUNK DATA")?;
+    ///
+    ///     let expected = Chunk::new(ChunkType::from_str("ruSt")?, b"THE CHUNK DATA".to_vec())?;
+    ///     assert_eq!(14, chunk.length());
+    ///     assert_eq!(expected.crc(), chunk.crc());
+    ///     assert_eq!(expected.as_bytes(), chunk.as_bytes());
+    ///     Ok(())
+    /// # }
+    /// ```
+    pub fn data_mut(&mut self) -> ChunkDataMut<'_> {
+        ChunkDataMut { chunk: self }
+    }
+
+    /// Replace the type code of the chunk, the checksum is updated to match.
+    pub fn set_chunk_type(&mut self, chunk_type: ChunkType) {
+        self.chunk_type = chunk_type;
+        self.update();
+    }
+
+    /// Update the length and the checksum after the type code or the data have changed. The data
+    /// is never longer than `Chunk::MAX_LENGTH` bytes, so the length fits.
+    fn update(&mut self) {
+        self.length = self.chunk_data.len() as u32;
+        self.crc = Self::compute_crc(&self.chunk_type, &self.chunk_data);
+    }
+
     /// Get the length of the data contained in the chunk
     /// # Examples
     ///
@@ -284,6 +350,77 @@ impl Chunk {
     }
 }
 
+/// ChunkDataMut gives mutable access to the data of a chunk and updates the length and the
+/// checksum of the chunk when it is dropped. The bytes can be changed in place, and bytes can be
+/// added as long as the data stays within `Chunk::MAX_LENGTH` bytes.
+pub struct ChunkDataMut<'a> {
+    chunk: &'a mut Chunk,
+}
+
+impl ChunkDataMut<'_> {
+    /// Append the given bytes to the data, unless the data would become too long.
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) -> Result<()> {
+        self.check_length(bytes.len())?;
+        self.chunk.chunk_data.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Append the given byte to the data, unless the data would become too long.
+    pub fn push(&mut self, byte: u8) -> Result<()> {
+        self.check_length(1)?;
+        self.chunk.chunk_data.push(byte);
+        Ok(())
+    }
+
+    /// Resize the data to the given length, filling new bytes with the given value, unless the
+    /// length is too large.
+    pub fn resize(&mut self, length: usize, value: u8) -> Result<()> {
+        if length > Chunk::MAX_LENGTH as usize {
+            return Err(Error::InvalidChunkLength);
+        }
+        self.chunk.chunk_data.resize(length, value);
+        Ok(())
+    }
+
+    /// Shorten the data to the given length, nothing happens if it is already shorter.
+    pub fn truncate(&mut self, length: usize) {
+        self.chunk.chunk_data.truncate(length);
+    }
+
+    /// Remove all the data.
+    pub fn clear(&mut self) {
+        self.chunk.chunk_data.clear();
+    }
+
+    /// Check that the given number of bytes can be added to the data.
+    fn check_length(&self, additional: usize) -> Result<()> {
+        match self.chunk.chunk_data.len().checked_add(additional) {
+            Some(length) if length <= Chunk::MAX_LENGTH as usize => Ok(()),
+            _ => Err(Error::InvalidChunkLength),
+        }
+    }
+}
+
+impl Deref for ChunkDataMut<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.chunk.chunk_data
+    }
+}
+
+impl DerefMut for ChunkDataMut<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.chunk.chunk_data
+    }
+}
+
+impl Drop for ChunkDataMut<'_> {
+    fn drop(&mut self) {
+        self.chunk.update();
+    }
+}
+
 impl std::fmt::Display for Chunk {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
@@ -299,45 +436,61 @@ impl std::convert::TryFrom<&[u8]> for Chunk {
     type Error = Error;
 
     fn try_from(raw: &[u8]) -> Result<Self> {
-        let mut r = Cursor::new(raw);
-        let mut buf = [0u8; 4];
-
-        // parse chunk length
-        r.read_exact(&mut buf)?;
-        let length = u32::from_be_bytes(buf);
-        if length > 1 << 31 {
-            return Err(Self::Error::InvalidChunkLength);
-        }
-
-        // parse chunk type
-        r.read_exact(&mut buf)?;
-        let chunk_type = ChunkType::try_from(buf)?;
+        Self::try_from(RawChunk::try_from(raw)?)
+    }
+}
 
-        // parse chunk data
-        let mut chunk_data = vec![0; length.try_into()?];
-        r.read_exact(&mut chunk_data)?;
+impl std::convert::TryFrom<RawChunk> for Chunk {
+    type Error = Error;
 
-        // creating checksum from received data
-        let mut digest = crc32::Digest::new(crc32::IEEE);
-        digest.write(&buf);
-        digest.write(&chunk_data);
-
-        // parse and check chunk checksum
-        r.read_exact(&mut buf)?;
-        let crc = u32::from_be_bytes(buf);
-        if digest.sum32() != crc {
-            return Err(Self::Error::InvalidCRC);
+    /// Check the type code and the checksum of the raw chunk.
+    fn try_from(raw: RawChunk) -> Result<Self> {
+        let chunk_type = raw.chunk_type()?;
+        if raw.computed_crc() != raw.crc() {
+            return Err(Self::Error::InvalidCRC {
+                expected: raw.computed_crc(),
+                actual: raw.crc(),
+            });
         }
-
         Ok(Self {
-            length,
+            length: raw.length(),
+            crc: raw.crc(),
             chunk_type,
-            chunk_data,
-            crc,
+            chunk_data: raw.data().to_vec(),
         })
     }
 }
 
+/// The fields of a serialized chunk, which are checked against each other before the chunk is
+/// deserialized.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct ChunkFields {
+    length: u32,
+    chunk_type: ChunkType,
+    chunk_data: Vec<u8>,
+    crc: u32,
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<ChunkFields> for Chunk {
+    type Error = Error;
+
+    fn try_from(fields: ChunkFields) -> Result<Self> {
+        let chunk = Self::new(fields.chunk_type, fields.chunk_data)?;
+        if chunk.length != fields.length {
+            return Err(Error::InvalidChunkLength);
+        }
+        if chunk.crc != fields.crc {
+            return Err(Error::InvalidCRC {
+                expected: chunk.crc,
+                actual: fields.crc,
+            });
+        }
+        Ok(chunk)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -435,6 +588,70 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_set_data() {
+        let mut chunk = testing_chunk();
+        chunk.set_data(b"Another secret message".to_vec()).unwrap();
+        let expected = Chunk::new(
+            chunk.chunk_type().clone(),
+            b"Another secret message".to_vec(),
+        )
+        .unwrap();
+        assert_eq!(chunk, expected);
+        assert_eq!(chunk.length(), 22);
+        assert_eq!(
+            Chunk::try_from(chunk.as_bytes().as_ref()).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_data_mut() {
+        let mut chunk = testing_chunk();
+        {
+            let mut data = chunk.data_mut();
+            data.truncate(13);
+            data[0] = b't';
+        }
+        assert_eq!(chunk.data(), b"this is where");
+        assert_eq!(chunk.length(), 13);
+        assert_eq!(Chunk::try_from(chunk.as_bytes().as_ref()).unwrap(), chunk);
+
+        chunk.data_mut().clear();
+        assert_eq!(chunk.length(), 0);
+        assert_eq!(chunk.crc(), Chunk::compute_crc(chunk.chunk_type(), &[]));
+
+        // the data can not grow too long, and is left as it was
+        let mut data = chunk.data_mut();
+        data.push(b'x').unwrap();
+        assert!(data.resize(Chunk::MAX_LENGTH as usize + 1, 0).is_err());
+        assert!(data.extend_from_slice(&[0; 2]).is_ok());
+        drop(data);
+        assert_eq!(chunk.data(), b"x\0\0");
+        assert_eq!(chunk.length(), 3);
+    }
+
+    #[test]
+    fn test_set_chunk_type() {
+        let mut chunk = testing_chunk();
+        chunk.set_chunk_type(ChunkType::try_from(*b"ruSt").unwrap());
+        assert_eq!(chunk.chunk_type().to_string(), "ruSt");
+        assert_ne!(chunk.crc(), 2882656334);
+        assert_eq!(Chunk::try_from(chunk.as_bytes().as_ref()).unwrap(), chunk);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_chunk_serde() {
+        let chunk = testing_chunk();
+        let json = serde_json::to_string(&chunk).unwrap();
+        let parsed: Chunk = serde_json::from_str(&json).unwrap();
+        assert_eq!(chunk, parsed);
+
+        let tampered = json.replace(&chunk.crc().to_string(), "0");
+        assert!(serde_json::from_str::<Chunk>(&tampered).is_err());
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
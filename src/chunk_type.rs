@@ -27,7 +27,12 @@ type RawChunkType = [u8; 4];
 ///     Ok(())
 /// # }
 /// ```
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
 pub struct ChunkType(RawChunkType);
 
 impl ChunkType {
@@ -165,7 +170,10 @@ impl std::str::FromStr for ChunkType {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self> {
-        let raw: RawChunkType = s.as_bytes().try_into()?;
+        let raw: RawChunkType = s
+            .as_bytes()
+            .try_into()
+            .map_err(|_| Error::InvalidChunkTypeLength(s.len()))?;
         ChunkType::try_from(raw)
     }
 }
@@ -174,17 +182,34 @@ impl std::convert::TryFrom<RawChunkType> for ChunkType {
     type Error = Error;
 
     fn try_from(raw: RawChunkType) -> Result<Self> {
-        if !raw
+        if let Some(position) = raw
             .iter()
-            .all(|&b| b >= 65 && b <= 90 || b >= 97 && b <= 122)
+            .position(|&b| !(65..=90).contains(&b) && !(97..=122).contains(&b))
         {
-            return Err(Self::Error::InvalidChunkType);
+            return Err(Self::Error::InvalidChunkType {
+                byte: raw[position],
+                position,
+            });
         }
 
         Ok(Self(raw))
     }
 }
 
+impl std::convert::TryFrom<String> for ChunkType {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl std::convert::From<ChunkType> for String {
+    fn from(chunk_type: ChunkType) -> String {
+        chunk_type.to_string()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -269,6 +294,34 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_invalid_chunk_type_byte() {
+        let err = ChunkType::from_str("Ru1t").unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidChunkType {
+                byte: b'1',
+                position: 2
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "invalid chunk type, byte 2 is 0x31 instead of an ASCII letter"
+        );
+    }
+
+    #[test]
+    pub fn test_invalid_chunk_type_length() {
+        for s in ["", "abc", "RuStY"].iter() {
+            let err = ChunkType::from_str(s).unwrap_err();
+            assert!(matches!(err, Error::InvalidChunkTypeLength(length) if length == s.len()));
+        }
+        assert_eq!(
+            ChunkType::from_str("abc").unwrap_err().to_string(),
+            "invalid chunk type, it has 3 bytes instead of 4"
+        );
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
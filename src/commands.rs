@@ -1,103 +1,1130 @@
 use pmsg::*;
 
+use crate::printer::{add_data, change_record, chunk_record, envelope_record, Printer};
+use serde_json::{json, Value};
+
+use std::collections::HashSet;
 use std::convert::TryFrom;
-use std::fs::OpenOptions;
-use std::io::prelude::*;
-use std::path::PathBuf;
+use std::fs;
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Parse the PNG image at the given path and add a new chunk that has the given chunk type code
-/// before the "IEND" header. The data of the new chunk contain the message that is needed to be
-/// encoded. The PNG data is then saved to the output path, if it is given. Otherwise, the PNG data
-/// is saved back to the input path.
-pub fn encode(matches: &clap::ArgMatches) -> Result<()> {
-    let file_path = matches.value_of("file").unwrap();
-    let file_pathbuf = PathBuf::from(file_path);
-    let mut file = OpenOptions::new().read(true).open(&file_pathbuf)?;
-
-    let mut png_data = Vec::new();
-    file.read_to_end(&mut png_data)?;
-    let mut png = Png::try_from(png_data.as_ref())?;
-
-    let eof_chunk = png.remove_chunk("IEND")?;
+/// before the "IEND" header. The data of the new chunk contain the envelope of the message that is
+/// needed to be encoded, which carries a new id, the optional label, and the current time. The
+/// message is either given on the command line, read from a file, or read from the standard
+/// input, and is compressed if an algorithm is given. The envelope is split into multiple chunks
+/// if a fragment size is given or if it does not fit in a single chunk. In the "idat" mode, the
+/// envelope is hidden after the compressed image data instead of in new chunks, and in the
+/// "palette" mode, it is hidden in the order of the palette entries. The "comment", "xmp" and
+/// "exif" modes disguise the new chunks as standard metadata. The PNG data is then saved to the
+/// output path, if it is given. Otherwise, the PNG data is saved back to the input path.
+pub fn encode(matches: &clap::ArgMatches, file_path: &str, printer: &mut Printer) -> Result<()> {
+    let png_data = read_file(file_path)?;
+    let png = parse_png(matches, png_data.as_ref())?;
+
     let chunk_type = matches.value_of("chunk_type").unwrap();
     let chunk_type = ChunkType::from_str(chunk_type)?;
-    let message = matches.value_of("message").unwrap();
-    let message = message.as_bytes().to_vec();
+    let id = unused_id(&png);
+    let mut envelope = read_envelope(matches)?;
+    envelope.set_id(Some(id));
+    envelope.set_label(matches.value_of("label").map(String::from));
+    envelope.set_timestamp(current_timestamp());
 
-    png.append_chunk(Chunk::new(chunk_type, message)?);
-    png.append_chunk(eof_chunk);
+    let position = match matches.value_of("at") {
+        Some(at) => Some(ChunkPosition::from_str(at)?.index(&png)?),
+        None => None,
+    };
+    let pieces = build_pieces(matches, envelope, id)?;
+    let png = hide_pieces(
+        &png,
+        matches.value_of("mode"),
+        &chunk_type,
+        pieces,
+        position,
+    )?;
 
     let output = matches.value_of("output").unwrap_or(file_path);
-    let output = PathBuf::from(output);
-    let mut output = OpenOptions::new()
-        .create(true)
-        .truncate(true)
-        .write(true)
-        .open(output)?;
-    output.write_all(&png.as_bytes())?;
+    save_png(matches, printer, &png_data, output, &png)?;
+    Ok(())
+}
+
+/// Parse the PNG image at the given path and hide the message made from the template that is
+/// given on the command line in a new chunk with the given chunk type code, unless the image
+/// already has a chunk with that type code. In the template, "{name}", "{path}" and "{time}" are
+/// replaced by the file name, the path of the file and the current time. The PNG data is saved
+/// back to the input path, and the result is printed out with the time at which it happened.
+pub fn tag(matches: &clap::ArgMatches, file_path: &str, printer: &mut Printer) -> Result<()> {
+    let png_data = read_file(file_path)?;
+    let png = parse_png(matches, png_data.as_ref())?;
+
+    let chunk_type = matches.value_of("type").unwrap();
+    let time = format_timestamp(current_timestamp().unwrap_or_default());
+    if png.chunk_by_type(chunk_type).is_some() {
+        printer.emit(
+            format!(
+                "{}  skipped {}, it already has a {} chunk",
+                time, file_path, chunk_type
+            ),
+            json!({ "time": time, "path": file_path, "action": "skipped" }),
+        );
+        return Ok(());
+    }
+
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    let name = Path::new(file_path)
+        .file_name()
+        .map_or(String::new(), |name| name.to_string_lossy().into_owned());
+    let message = matches
+        .value_of("message_template")
+        .unwrap()
+        .replace("{name}", &name)
+        .replace("{path}", file_path)
+        .replace("{time}", &time);
+    let id = unused_id(&png);
+    let mut envelope = Envelope::from_text(&message);
+    envelope.set_id(Some(id));
+    envelope.set_label(matches.value_of("label").map(String::from));
+    envelope.set_timestamp(current_timestamp());
 
+    let pieces = build_pieces(matches, envelope, id)?;
+    let png = hide_pieces(&png, None, &chunk_type, pieces, None)?;
+    write_atomic(Path::new(file_path), &png.as_bytes(), None)?;
+    printer.emit(
+        format!("{}  tagged {} with message {:08x}", time, file_path, id),
+        json!({
+            "time": time,
+            "path": file_path,
+            "action": "tagged",
+            "id": format!("{:08x}", id),
+            "message": message,
+        }),
+    );
     Ok(())
 }
 
-/// Parse the PNG image at the given path and find all the chunk whose type code is equal to the
-/// given type code and print out the data they contain.
-pub fn decode(matches: &clap::ArgMatches) -> Result<()> {
-    let file_path = PathBuf::from(matches.value_of("file").unwrap());
-    let mut file = OpenOptions::new().read(true).open(&file_path)?;
+/// Compress the given envelope if an algorithm is given on the command line and turn it into the
+/// pieces that are hidden in the PNG file. The envelope is split into fragments with the given id
+/// if a fragment size is given or if it does not fit in a single chunk, and every piece is
+/// protected by error correction if an amount of redundancy is given.
+fn build_pieces(
+    matches: &clap::ArgMatches,
+    mut envelope: Envelope,
+    id: u32,
+) -> Result<Vec<Vec<u8>>> {
+    if let Some(compression) = matches.value_of("compress") {
+        let compression = Compression::from_str(compression)?;
+        let level = match matches.value_of("level") {
+            Some(level) => Some(level.parse().map_err(|_| invalid_input("level", level))?),
+            None => None,
+        };
+        envelope.compress(compression, level)?;
+    }
+    let envelope = envelope.as_bytes()?;
+
+    let ecc = match matches.value_of("ecc") {
+        Some(percent) => {
+            let percent = percent.parse().map_err(|_| invalid_input("ecc", percent))?;
+            Some(ReedSolomon::with_redundancy(percent)?)
+        }
+        None => None,
+    };
+
+    let max_piece_size = match ecc {
+        Some(ecc) => ecc.capacity(Chunk::MAX_LENGTH as usize),
+        None => Chunk::MAX_LENGTH as usize,
+    };
+    let max_fragment_size = max_piece_size - Fragment::HEADER_LENGTH;
+    let fragment_size = match matches.value_of("fragment_size") {
+        Some(size) => Some(parse_size(size)?.min(max_fragment_size)),
+        None if envelope.len() > max_piece_size => Some(max_fragment_size),
+        None => None,
+    };
+
+    let mut pieces = match fragment_size {
+        Some(fragment_size) => Fragment::split(id, &envelope, fragment_size)
+            .iter()
+            .map(|f| f.as_bytes())
+            .collect(),
+        None => vec![envelope],
+    };
+    if let Some(ecc) = ecc {
+        pieces = pieces.iter().map(|p| ecc.encode(p)).collect();
+    }
+    Ok(pieces)
+}
+
+/// Hide the given pieces labeled with the given chunk type code in the given PNG image with the
+/// given mode. New chunks are inserted at the given position, or before the "IEND" chunk if none
+/// is given, except for an "eXIf" chunk, which must come before the image data.
+fn hide_pieces(
+    png: &Png,
+    mode: Option<&str>,
+    chunk_type: &ChunkType,
+    mut pieces: Vec<Vec<u8>>,
+    position: Option<usize>,
+) -> Result<Png> {
+    let new_chunks = match mode {
+        Some("idat") => {
+            let payloads: Vec<IdatPayload> = pieces
+                .into_iter()
+                .map(|p| IdatPayload::new(chunk_type.clone(), p))
+                .collect();
+            return IdatPayload::append(png, &payloads);
+        }
+        Some("palette") => {
+            if pieces.len() > 1 {
+                return Err(Error::InsufficientCapacity {
+                    needed: pieces.iter().map(|p| p.len()).sum(),
+                    capacity: PalettePayload::capacity(png)?,
+                });
+            }
+            let piece = pieces.pop().unwrap_or_default();
+            return PalettePayload::new(chunk_type.clone(), piece).write(png);
+        }
+        Some("exif") => {
+            // a PNG file holds at most one "eXIf" chunk
+            if png.chunk_by_type("eXIf").is_some() {
+                return Err(Error::DuplicateChunk("eXIf".to_string()));
+            }
+            if pieces.len() > 1 {
+                return Err(Error::Usage(
+                    "the exif mode can not split the data into fragments".to_string(),
+                ));
+            }
+            vec![Disguise::Exif.wrap(chunk_type, &pieces.pop().unwrap_or_default())?]
+        }
+        Some(mode @ "comment") | Some(mode @ "xmp") => {
+            let disguise = Disguise::from_str(mode)?;
+            pieces
+                .iter()
+                .map(|p| disguise.wrap(chunk_type, p))
+                .collect::<Result<Vec<Chunk>>>()?
+        }
+        _ => pieces
+            .into_iter()
+            .map(|p| Chunk::new(chunk_type.clone(), p))
+            .collect::<Result<Vec<Chunk>>>()?,
+    };
+
+    let mut png = png.clone();
+    let data_type = ChunkType::try_from(Image::DATA_CHUNK_TYPE)?;
+    let anchor = match mode {
+        Some("exif") if png.chunks_by_type(&data_type).next().is_some() => data_type,
+        _ => ChunkType::try_from(Png::END_CHUNK_TYPE)?,
+    };
+    for (i, chunk) in new_chunks.into_iter().enumerate() {
+        match position {
+            Some(position) => png.insert_chunk(position + i, chunk)?,
+            None => png.insert_before(&anchor, chunk)?,
+        }
+    }
+    Ok(png)
+}
+
+/// Generate an id that is used neither by the messages nor by the fragments that are hidden in
+/// the given PNG image.
+fn unused_id(png: &Png) -> u32 {
+    let used_ids: Vec<u32> = hidden_pieces(png, None)
+        .iter()
+        .filter_map(|data| {
+            let (data, _) = remove_error_correction(data).ok()?;
+            if Fragment::is_fragment(&data) {
+                Fragment::try_from(data.as_ref()).ok().map(|f| f.id())
+            } else {
+                Envelope::try_from(data.as_ref()).ok()?.id()
+            }
+        })
+        .collect();
+    let mut id = Fragment::generate_id();
+    while used_ids.contains(&id) {
+        id = Fragment::generate_id();
+    }
+    id
+}
+
+/// Get the current time in seconds since the Unix epoch.
+fn current_timestamp() -> Option<u64> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+/// Format the given number of seconds since the Unix epoch as a UTC date and time.
+fn format_timestamp(timestamp: u64) -> String {
+    let (days, seconds) = (timestamp / 86400, timestamp % 86400);
+    // convert the days since the epoch to a civil date, with years starting in March
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Parse the hexadecimal id of a message given on the command line.
+fn parse_id(id: &str) -> Result<u32> {
+    u32::from_str_radix(id, 16).map_err(|_| invalid_input("id", id))
+}
+
+/// Print out that the given chunk is removed, with its type code and the length of its data.
+fn print_removed_chunk(printer: &mut Printer, chunk: &Chunk) {
+    printer.emit(
+        format!("Remove {} ({} bytes)", chunk.chunk_type(), chunk.length()),
+        json!({
+            "removed": "chunk",
+            "type": chunk.chunk_type().to_string(),
+            "length": chunk.length(),
+        }),
+    );
+}
+
+/// Find the message with the given id in the given PNG image.
+fn find_message(png: &Png, id: u32) -> Result<Message> {
+    Message::find_all(png)?
+        .into_iter()
+        .find(|m| m.id() == Some(id))
+        .ok_or(Error::MessageNotFound(id))
+}
+
+/// The largest size of a payload that is decompressed when no limit is given.
+const DEFAULT_MAX_SIZE: usize = 64 << 20;
+
+/// Parse a positive number of bytes given on the command line.
+fn parse_size(size: &str) -> Result<usize> {
+    match size.parse::<usize>() {
+        Ok(size) if size > 0 => Ok(size),
+        _ => Err(invalid_input("size", size)),
+    }
+}
+
+/// Decode the given chunk data if it is protected by error correction. The data is returned
+/// together with the number of corrupted bytes that were corrected.
+fn remove_error_correction(data: &[u8]) -> Result<(Vec<u8>, usize)> {
+    if ReedSolomon::is_encoded(data) {
+        ReedSolomon::decode(data)
+    } else {
+        Ok((data.to_vec(), 0))
+    }
+}
+
+/// Get the data of the chunks with the given type code together with the data that is hidden in
+/// disguised metadata, after the compressed image data, and in the order of the palette entries
+/// labeled with that type code. The data of every chunk and every hidden payload is returned if
+/// no type code is given. Raw chunks with a valid type code are included, so that damaged chunks
+/// that were kept with `--lenient` can be repaired by their error correction.
+fn hidden_pieces(png: &Png, chunk_type: Option<&ChunkType>) -> Vec<Vec<u8>> {
+    let matches = |t: &ChunkType| chunk_type.map_or(true, |chunk_type| t == chunk_type);
+    let mut pieces = Vec::new();
+    for (index, raw) in png.file_chunks() {
+        let chunk = match index {
+            Some(index) => &png.chunks()[index],
+            None => {
+                if raw.chunk_type().is_ok_and(|t| matches(&t)) {
+                    pieces.push(raw.data().to_vec());
+                }
+                continue;
+            }
+        };
+        if matches(chunk.chunk_type()) {
+            pieces.push(chunk.data().to_vec());
+        } else if let Some((label, data)) = Disguise::unwrap(chunk) {
+            if matches(&label) {
+                pieces.push(data);
+            }
+        }
+    }
+    // image data that is followed by anything else is treated as if nothing was hidden in it
+    for payload in IdatPayload::read_all(png).unwrap_or_default() {
+        if matches(payload.chunk_type()) {
+            pieces.push(payload.data().to_vec());
+        }
+    }
+    if let Ok(Some(payload)) = PalettePayload::read(png) {
+        if matches(payload.chunk_type()) {
+            pieces.push(payload.data().to_vec());
+        }
+    }
+    pieces
+}
+
+/// Save the given PNG image to the given path without risking the file that is replaced, which is
+/// kept as a backup if asked on the command line. In a dry run, the chunks that would be added to
+/// or removed from the original PNG data and the resulting size are printed out instead.
+fn save_png(
+    matches: &clap::ArgMatches,
+    printer: &mut Printer,
+    original: &[u8],
+    path: &str,
+    png: &Png,
+) -> Result<()> {
+    let data = png.as_bytes();
+    if matches.is_present("dry_run") {
+        for change in diff_chunks(&parse_png(matches, original)?, png) {
+            printer.emit(&change, change_record(&change));
+        }
+        printer.emit(
+            format!(
+                "{} -> {} bytes, nothing was written to {}",
+                original.len(),
+                data.len(),
+                path
+            ),
+            json!({ "path": path, "original_size": original.len(), "size": data.len(), "written": false }),
+        );
+        return Ok(());
+    }
+
+    let backup = if matches.is_present("backup") {
+        Some(matches.value_of("backup").unwrap_or(DEFAULT_BACKUP_SUFFIX))
+    } else {
+        None
+    };
+    write_atomic(Path::new(path), &data, backup)
+}
+
+/// Add the given suffix to the given file name, before its extension.
+fn with_suffix(name: &Path, suffix: &str) -> PathBuf {
+    let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+    match name.extension() {
+        Some(extension) => PathBuf::from(format!(
+            "{}-{}.{}",
+            stem,
+            suffix,
+            extension.to_string_lossy()
+        )),
+        None => PathBuf::from(format!("{}-{}", stem, suffix)),
+    }
+}
+
+/// Create the error for an invalid value given on the command line.
+fn invalid_input(name: &str, value: &str) -> Error {
+    Error::Usage(format!("invalid {} '{}'", name, value))
+}
+
+/// Read the whole file at the given path. Errors name the path, and keep the kind of the I/O error.
+fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>> {
+    let path = path.as_ref();
+    fs::read(path).map_err(|e| {
+        Error::IOError(io::Error::new(
+            e.kind(),
+            format!("can not read {}: {}", path.display(), e),
+        ))
+    })
+}
+
+/// Parse the given PNG data. With `--lenient`, chunks with a bad checksum or an invalid type code
+/// are kept as raw chunks instead of failing, so they are written back unchanged.
+fn parse_png(matches: &clap::ArgMatches, data: &[u8]) -> Result<Png> {
+    if matches.is_present("lenient") {
+        Png::from_bytes_lenient(data)
+    } else {
+        Png::try_from(data)
+    }
+}
+
+/// Build the envelope of the data that is needed to be encoded. The name of the input file is kept
+/// in the envelope so that the file can be restored when decoding.
+fn read_envelope(matches: &clap::ArgMatches) -> Result<Envelope> {
+    if let Some(input_path) = matches.value_of("input_file") {
+        let input_path = PathBuf::from(input_path);
+        let mut envelope = Envelope::new(read_file(&input_path)?);
+        envelope.set_filename(
+            input_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+        );
+        return Ok(envelope);
+    }
+
+    if matches.is_present("stdin") {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+        return Ok(Envelope::new(data));
+    }
 
-    let mut png_data = Vec::new();
-    file.read_to_end(&mut png_data)?;
-    let png = Png::try_from(png_data.as_ref())?;
+    let message = matches.value_of("message").unwrap();
+    Ok(Envelope::from_text(message))
+}
+
+/// Parse the PNG image at the given path and find all the chunk whose type code is equal to the
+/// given type code and print out the payload of the envelopes they contain. Chunks that are
+/// protected by error correction are repaired and the number of corrected bytes is reported.
+/// Payloads that are disguised as metadata, hidden after the compressed image data, or hidden in
+/// the order of the palette entries with the given type code are found as well. Payloads that were
+/// split across multiple chunks are reassembled first and compressed payloads are decompressed.
+/// Chunks that were written without an envelope are printed as plain text. It is an error if no
+/// payload is found.
+///
+/// If an output path is given, the exact hidden bytes are written to it instead. When the output
+/// path is a directory, every payload is restored under the file name that was kept in its
+/// envelope, with the chunk type code and its index added if that name was already used. Files
+/// are written atomically.
+pub fn decode(matches: &clap::ArgMatches, file_path: &str, printer: &mut Printer) -> Result<()> {
+    let png_data = read_file(file_path)?;
+    let png = parse_png(matches, png_data.as_ref())?;
 
     let chunk_type = matches.value_of("chunk_type").unwrap();
     let chunk_type = ChunkType::from_str(chunk_type)?;
-    let chunks = png
-        .chunks()
+    let mut protected = false;
+    let mut corrected = 0;
+    let data = hidden_pieces(&png, Some(&chunk_type))
+        .iter()
+        .map(|data| {
+            protected |= ReedSolomon::is_encoded(data);
+            let (data, count) = remove_error_correction(data)?;
+            corrected += count;
+            Ok(data)
+        })
+        .collect::<Result<Vec<Vec<u8>>>>()?;
+    let max_size = match matches.value_of("max_size") {
+        Some(size) => parse_size(size)?,
+        None => DEFAULT_MAX_SIZE,
+    };
+    let envelopes = Fragment::reassemble(&data)?
         .iter()
-        .filter(|c| *c.chunk_type() == chunk_type)
-        .collect::<Vec<&Chunk>>();
+        .map(|payload| {
+            let mut envelope = Envelope::decode(payload)?;
+            envelope.decompress(max_size)?;
+            Ok(envelope)
+        })
+        .collect::<Result<Vec<Envelope>>>()?;
+    if envelopes.is_empty() {
+        return Err(Error::NoMessageFound(chunk_type.to_string()));
+    }
+    // the raw data is the only thing that is written to the standard output
+    if protected && !matches.is_present("raw") {
+        printer.emit(
+            format!("Corrected {} corrupted byte(s)", corrected),
+            json!({ "type": chunk_type.to_string(), "corrected": corrected }),
+        );
+    }
 
-    for c in chunks {
-        println!("{}", c);
+    if let Some(output) = matches.value_of("output") {
+        let output = PathBuf::from(output);
+        if output.is_dir() {
+            let mut names = HashSet::new();
+            for (i, envelope) in envelopes.iter().enumerate() {
+                let suffix = format!("{}-{}", chunk_type, i);
+                let mut name = envelope
+                    .filename()
+                    .and_then(|name| Path::new(name).file_name())
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| PathBuf::from(format!("{}.bin", suffix)));
+                // a name that was already used gets the suffix, so that no payload is overwritten
+                if names.contains(&name) {
+                    name = with_suffix(&name, &suffix);
+                }
+                write_atomic(&output.join(&name), envelope.payload(), None)?;
+                names.insert(name);
+            }
+        } else {
+            if envelopes.len() > 1 {
+                return Err(Error::MultipleMessagesFound(chunk_type.to_string()));
+            }
+            write_atomic(&output, envelopes[0].payload(), None)?;
+        }
+        return Ok(());
     }
 
+    if matches.is_present("raw") {
+        for envelope in &envelopes {
+            printer.raw(envelope.payload())?;
+        }
+        return Ok(());
+    }
+
+    for envelope in envelopes {
+        printer.emit(
+            format!("{}\"{}\"", chunk_type, envelope),
+            envelope_record(&chunk_type, &envelope),
+        );
+    }
     Ok(())
 }
 
-/// Parse the PNG image at the given path and remove the every chunk whose type code is
-/// equal to the given type code. The PNG data is then saved back to the input path.
-pub fn remove(matches: &clap::ArgMatches) -> Result<()> {
-    let file_path = PathBuf::from(matches.value_of("file").unwrap());
-    let mut file = OpenOptions::new().read(true).open(&file_path)?;
+/// Parse the PNG image at the given path and remove the message with the given id, or every chunk
+/// and hidden payload with the given chunk type code. The PNG data is then saved to the output
+/// path, if it is given. Otherwise, the PNG data is saved back to the input path. What is removed
+/// is printed out, except in a dry run, where the changes are printed out when saving.
+pub fn remove(matches: &clap::ArgMatches, file_path: &str, printer: &mut Printer) -> Result<()> {
+    let png_data = read_file(file_path)?;
+    let mut png = parse_png(matches, png_data.as_ref())?;
+    let output = matches.value_of("output").unwrap_or(file_path);
+    let report = !matches.is_present("dry_run");
 
-    let mut png_data = Vec::new();
-    file.read_to_end(&mut png_data)?;
-    let mut png = Png::try_from(png_data.as_ref())?;
+    if let Some(id) = matches.value_of("id") {
+        let message = find_message(&png, parse_id(id)?)?;
+        for location in message.locations().iter().filter(|_| report) {
+            match location {
+                Location::Chunk(i) | Location::Disguised(i, _) => {
+                    print_removed_chunk(printer, &png.chunks()[*i])
+                }
+                Location::ImageData(_) => printer.emit(
+                    format!("Remove {} from the image data", message.chunk_type()),
+                    json!({ "removed": location.to_string(), "type": message.chunk_type().to_string() }),
+                ),
+                Location::Palette => printer.emit(
+                    format!("Remove {} from the palette order", message.chunk_type()),
+                    json!({ "removed": location.to_string(), "type": message.chunk_type().to_string() }),
+                ),
+            }
+        }
+        save_png(matches, printer, &png_data, output, &message.remove(&png)?)?;
+        return Ok(());
+    }
 
-    let chunk_type = matches.value_of("chunk_type").unwrap();
-    while let Ok(removed) = png.remove_chunk(chunk_type) {
-        println!("Remove {}", removed);
+    let chunk_type = ChunkType::from_str(matches.value_of("chunk_type").unwrap())?;
+    for removed in png.remove_all(&chunk_type)?.iter().filter(|_| report) {
+        print_removed_chunk(printer, removed);
+    }
+    png.retain(|c| {
+        let disguised = Disguise::unwrap(c).is_some_and(|(label, _)| label == chunk_type);
+        if disguised && report {
+            print_removed_chunk(printer, c);
+        }
+        !disguised
+    })?;
+    if IdatPayload::read_all(&png).is_ok() {
+        let (stripped, removed) = IdatPayload::remove(&png, &chunk_type)?;
+        for payload in removed.iter().filter(|_| report) {
+            printer.emit(
+                format!(
+                    "Remove {} bytes of {} from the image data",
+                    payload.data().len(),
+                    payload.chunk_type()
+                ),
+                json!({
+                    "removed": "idat",
+                    "type": payload.chunk_type().to_string(),
+                    "length": payload.data().len(),
+                }),
+            );
+        }
+        png = stripped;
+    }
+    if let Ok(Some(payload)) = PalettePayload::read(&png) {
+        if payload.chunk_type() == &chunk_type {
+            if report {
+                printer.emit(
+                    format!(
+                        "Remove {} bytes of {} from the palette order",
+                        payload.data().len(),
+                        payload.chunk_type()
+                    ),
+                    json!({
+                        "removed": "palette",
+                        "type": payload.chunk_type().to_string(),
+                        "length": payload.data().len(),
+                    }),
+                );
+            }
+            png = PalettePayload::clear(&png)?;
+        }
+    }
+    save_png(matches, printer, &png_data, output, &png)?;
+    Ok(())
+}
+
+/// Parse the PNG image at the given path and print out the messages that are hidden in it, one
+/// per line, with their id, chunk type code, the mode in which they were encoded, their size,
+/// whether they are encrypted, the time at which they were written, and their label.
+pub fn list(matches: &clap::ArgMatches, file_path: &str, printer: &mut Printer) -> Result<()> {
+    let png_data = read_file(file_path)?;
+    let png = parse_png(matches, png_data.as_ref())?;
+
+    let messages = Message::find_all(&png)?;
+    if messages.is_empty() {
+        printer.text("No messages found");
+    } else {
+        printer.text(format!(
+            "{:<8}  {:<4}  {:<7}  {:>10}  {:<9}  {:<19}  LABEL",
+            "ID", "TYPE", "MODE", "SIZE", "ENCRYPTED", "WRITTEN"
+        ));
+    }
+    for message in messages {
+        let envelope = message.envelope();
+        let line = format!(
+            "{:<8}  {:<4}  {:<7}  {:>10}  {:<9}  {:<19}  {}",
+            message
+                .id()
+                .map_or("-".to_string(), |id| format!("{:08x}", id)),
+            message.chunk_type(),
+            message.locations()[0].to_string(),
+            envelope.original_size(),
+            if envelope.is_encrypted() { "yes" } else { "no" },
+            envelope
+                .timestamp()
+                .map_or("-".to_string(), format_timestamp),
+            envelope
+                .label()
+                .or_else(|| envelope.filename())
+                .unwrap_or(""),
+        );
+        let mut record = envelope_record(message.chunk_type(), envelope);
+        record["mode"] = json!(message.locations()[0].to_string());
+        record["size"] = json!(envelope.original_size());
+        record["pieces"] = json!(message.locations().len());
+        printer.emit(line, record);
+    }
+    Ok(())
+}
+
+/// Parse the PNG image at the given path and replace the message with the given id with a new
+/// message, which is read the same way as for encoding. The new message keeps the id, the chunk
+/// type code, the label unless a new one is given, and the place and mode of the old message. The
+/// PNG data is then saved to the output path, if it is given. Otherwise, the PNG data is saved
+/// back to the input path.
+pub fn update(matches: &clap::ArgMatches, file_path: &str, printer: &mut Printer) -> Result<()> {
+    let png_data = read_file(file_path)?;
+    let png = parse_png(matches, png_data.as_ref())?;
+
+    let id = parse_id(matches.value_of("id").unwrap())?;
+    let message = find_message(&png, id)?;
+    let mut envelope = read_envelope(matches)?;
+    envelope.set_id(Some(id));
+    envelope.set_label(
+        matches
+            .value_of("label")
+            .or_else(|| message.envelope().label())
+            .map(String::from),
+    );
+    envelope.set_timestamp(current_timestamp());
+
+    // no chunk before the first piece of the old message is removed, so its index stays valid
+    let location = message.locations()[0];
+    let pieces = build_pieces(matches, envelope, id)?;
+    let png = message.remove(&png)?;
+    let png = hide_pieces(
+        &png,
+        Some(&location.to_string()),
+        message.chunk_type(),
+        pieces,
+        location.chunk_index(),
+    )?;
+
+    let output = matches.value_of("output").unwrap_or(file_path);
+    save_png(matches, printer, &png_data, output, &png)?;
+    Ok(())
+}
+
+/// Parse the PNG images at the given paths and print out the chunks that were added, removed,
+/// moved or modified in the new image, with the differences of the data of modified chunks, and
+/// whether both images decode to the same pixels.
+pub fn diff(matches: &clap::ArgMatches) -> Result<()> {
+    let old = parse_png(
+        matches,
+        read_file(matches.value_of("old").unwrap())?.as_ref(),
+    )?;
+    let new = parse_png(
+        matches,
+        read_file(matches.value_of("new").unwrap())?.as_ref(),
+    )?;
+    let limit = matches.value_of("limit").unwrap();
+    let limit: usize = limit.parse().map_err(|_| invalid_input("limit", limit))?;
+
+    let diff = PngDiff::new(&old, &new);
+    let mut printer = Printer::new(matches);
+    if diff.changes().is_empty() {
+        printer.text("Chunks identical");
     }
+    for change in diff.changes() {
+        // the records carry the whole difference of the data, only the text is cut short
+        printer.emit(change, change_record(change));
+        if let ChunkChange::Modified { old, new, .. } = change {
+            let lines = diff_data(old.data(), new.data());
+            for line in lines.iter().take(limit) {
+                printer.text(format!("    {}", line));
+            }
+            if lines.len() > limit {
+                printer.text(format!("    ... {} more line(s)", lines.len() - limit));
+            }
+        }
+    }
+    let identical = diff.image_data_identical();
+    printer.emit(
+        match identical {
+            Some(true) => "Image data identical",
+            Some(false) => "Image data different",
+            None => "Image data can not be decoded",
+        },
+        json!({ "image_data_identical": identical }),
+    );
+    printer.finish();
+    Ok(())
+}
+
+/// Parse the PNG image at the given path and write the data of the chunk with the given index or
+/// the first chunk with the given type code to the output path, or to the standard output if no
+/// output path is given. The length, the type code and the CRC are kept around the data if the
+/// framed bytes are asked for.
+pub fn extract(matches: &clap::ArgMatches, file_path: &str, printer: &mut Printer) -> Result<()> {
+    let png = parse_png(matches, read_file(file_path)?.as_ref())?;
+    let index = select_chunk(matches, &png)?.unwrap();
+    let chunk = &png.chunks()[index];
+    let data = if matches.is_present("framed") {
+        chunk.as_bytes()
+    } else {
+        chunk.data().to_vec()
+    };
 
-    let mut output = OpenOptions::new()
-        .create(true)
-        .truncate(true)
-        .write(true)
-        .open(file_path)?;
-    output.write_all(&png.as_bytes())?;
+    match matches.value_of("output") {
+        Some(output) => {
+            write_atomic(Path::new(output), &data, None)?;
+            printer.emit(
+                format!(
+                    "Extract {} byte(s) of chunk #{} {} to {}",
+                    data.len(),
+                    index,
+                    chunk.chunk_type(),
+                    output
+                ),
+                json!({
+                    "index": index,
+                    "type": chunk.chunk_type().to_string(),
+                    "length": data.len(),
+                    "path": output,
+                }),
+            );
+        }
+        None if printer.is_text() => printer.raw(&data)?,
+        None => {
+            let mut record = json!({ "index": index, "type": chunk.chunk_type().to_string() });
+            add_data(&mut record, &data);
+            printer.record(record);
+        }
+    }
+    Ok(())
+}
+
+/// Parse the PNG image at the given path and insert a new chunk with the given type code and the
+/// data of the given file at the given position. The PNG data is then saved to the output path, if
+/// it is given. Otherwise, the PNG data is saved back to the input path.
+pub fn insert(matches: &clap::ArgMatches, file_path: &str, printer: &mut Printer) -> Result<()> {
+    let png_data = read_file(file_path)?;
+    let mut png = parse_png(matches, png_data.as_ref())?;
+
+    let chunk_type = ChunkType::from_str(matches.value_of("type").unwrap())?;
+    let data = read_file(matches.value_of("data_file").unwrap())?;
+    let index = ChunkPosition::from_str(matches.value_of("at").unwrap())?.index(&png)?;
+    png.insert_chunk(index, Chunk::new(chunk_type, data)?)?;
+
+    let output = matches.value_of("output").unwrap_or(file_path);
+    save_png(matches, printer, &png_data, output, &png)?;
+    Ok(())
+}
+
+/// Print out the bytes of the PNG file at the given path in hex, with a heading for the signature,
+/// for every chunk and for any data after the "IEND" chunk. The file is parsed leniently, so raw
+/// chunks with a bad checksum or an invalid type code are printed as well. Only the chunk with the
+/// given index or the first chunk with the given type code is printed if one is given, and at most
+/// the given number of bytes are printed for every part.
+pub fn hexdump(matches: &clap::ArgMatches, file_path: &str, printer: &mut Printer) -> Result<()> {
+    let png_data = read_file(file_path)?;
+    let png = Png::from_bytes_lenient(png_data.as_ref())?;
+    let limit = match matches.value_of("limit") {
+        Some(limit) => limit.parse().map_err(|_| invalid_input("limit", limit))?,
+        None => usize::MAX,
+    };
+
+    // every part is given by its heading, its record and its range of bytes in the file
+    let mut parts = Vec::new();
+    let selected = select_chunk(matches, &png)?;
+    if selected.is_none() {
+        parts.push((
+            "signature".to_string(),
+            json!({ "part": "signature" }),
+            0..Png::STANDARD_HEADER.len(),
+        ));
+    }
+    let mut offset = Png::STANDARD_HEADER.len();
+    for (index, chunk) in png.file_chunks() {
+        let range = offset..offset + 12 + chunk.data().len();
+        offset = range.end;
+        if selected.is_some() && index != selected {
+            continue;
+        }
+        let name = match index {
+            Some(i) => format!("chunk #{}", i),
+            None => "raw chunk".to_string(),
+        };
+        parts.push((
+            format!(
+                "{} {}, {} byte(s) of data",
+                name,
+                chunk.type_name(),
+                chunk.length()
+            ),
+            json!({
+                "part": if index.is_some() { "chunk" } else { "raw_chunk" },
+                "index": index,
+                "type": chunk.type_name(),
+                "length": chunk.length(),
+            }),
+            range,
+        ));
+    }
+    let end = png.as_bytes().len();
+    if selected.is_none() && end < png_data.len() {
+        parts.push((
+            "data after the IEND chunk".to_string(),
+            json!({ "part": "trailing" }),
+            end..png_data.len(),
+        ));
+    }
+
+    for (heading, mut record, range) in parts {
+        let shown = range.start..range.end.min(range.start.saturating_add(limit));
+        let lines = hex_lines(&png_data[shown.clone()], shown.start);
+        printer.text(format!("# {:08x}: {}", range.start, heading));
+        for line in &lines {
+            printer.text(line);
+        }
+        if shown.end < range.end {
+            printer.text(format!("... {} more byte(s)", range.end - shown.end));
+        }
+        record["offset"] = json!(range.start);
+        record["size"] = json!(range.len());
+        record["lines"] = json!(lines);
+        printer.record(record);
+    }
+    Ok(())
+}
+
+/// Get the index of the chunk that is selected by its index or by its type code on the command
+/// line, `None` is returned if no chunk is selected.
+fn select_chunk(matches: &clap::ArgMatches, png: &Png) -> Result<Option<usize>> {
+    if let Some(index) = matches.value_of("index") {
+        let index: usize = index.parse().map_err(|_| invalid_input("index", index))?;
+        if index >= png.chunks().len() {
+            return Err(Error::ChunkIndexOutOfRange(index));
+        }
+        return Ok(Some(index));
+    }
+    match matches.value_of("type") {
+        Some(chunk_type) => {
+            let chunk_type = ChunkType::from_str(chunk_type)?;
+            png.chunks()
+                .iter()
+                .position(|c| c.chunk_type() == &chunk_type)
+                .map(Some)
+                .ok_or(Error::ChunkTypeNotFound)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Parse the PNG image at the given path and print out a table of its chunks with their offsets,
+/// lengths, property bits and checksum status, and a summary of the content of known chunks or a
+/// short preview of the data of other chunks. The file is parsed leniently, so raw chunks with a
+/// bad checksum or an invalid type code are listed without an index.
+pub fn inspect(matches: &clap::ArgMatches, file_path: &str, printer: &mut Printer) -> Result<()> {
+    let png = Png::from_bytes_lenient(read_file(file_path)?.as_ref())?;
+    let length = matches.value_of("preview").unwrap();
+    let length = length
+        .parse()
+        .map_err(|_| invalid_input("preview length", length))?;
+
+    printer.text(format!(
+        "{:>5}  {:>10}  {:>10}  {:<4}  {:<4}  {:<8}  CONTENT",
+        "INDEX", "OFFSET", "LENGTH", "TYPE", "BITS", "CRC"
+    ));
+    for info in pmsg::inspect(&png, length) {
+        let crc = if info.is_crc_valid() { "ok" } else { "bad" };
+        let mut problems = String::new();
+        if !info.is_type_valid() {
+            problems.push_str(" (invalid type)");
+        }
+        if !info.is_crc_valid() {
+            problems.push_str(" (bad CRC)");
+        }
+        printer.emit(
+            format!(
+                "{:>5}  {:>10}  {:>10}  {:<4}  {:<4}  {:08x}  {}{}",
+                info.index()
+                    .map_or_else(|| "-".to_string(), |i| i.to_string()),
+                info.offset(),
+                info.length(),
+                info.chunk_type(),
+                info.properties(),
+                info.crc(),
+                info.summary().unwrap_or_else(|| info.preview()),
+                problems,
+            ),
+            json!({
+                "index": info.index(),
+                "offset": info.offset(),
+                "length": info.length(),
+                "type": info.chunk_type(),
+                "type_valid": info.is_type_valid(),
+                "properties": info.properties(),
+                "crc": info.crc(),
+                "crc_status": crc,
+                "summary": info.summary(),
+                "preview": info.preview(),
+            }),
+        );
+    }
     Ok(())
 }
 
 /// Parse the PNG image at the given path and print out its data.
-pub fn print(matches: &clap::ArgMatches) -> Result<()> {
-    let file_path = PathBuf::from(matches.value_of("file").unwrap());
-    let mut file = OpenOptions::new().read(true).open(&file_path)?;
+pub fn print(matches: &clap::ArgMatches, file_path: &str, printer: &mut Printer) -> Result<()> {
+    let png_data = read_file(file_path)?;
+    let png = parse_png(matches, png_data.as_ref())?;
+
+    printer.text(&png);
+    for (i, chunk) in png.chunks().iter().enumerate() {
+        let mut record = json!({ "index": i, "offset": png.chunk_offset(i) });
+        if let (Some(record), Value::Object(fields)) = (record.as_object_mut(), chunk_record(chunk))
+        {
+            record.extend(fields);
+        }
+        printer.record(record);
+    }
+    Ok(())
+}
+
+/// Parse the PNG image at the given path, decode its pixels and overwrite the low bit planes of
+/// every sample with random bits or zeros. The image data is then encoded again and the PNG data
+/// is saved to the output path, if it is given. Otherwise, the PNG data is saved back to the input
+/// path.
+pub fn sanitize(matches: &clap::ArgMatches, file_path: &str, printer: &mut Printer) -> Result<()> {
+    let png_data = read_file(file_path)?;
+    let png = parse_png(matches, png_data.as_ref())?;
+
+    let depth = matches.value_of("depth").unwrap();
+    let depth = depth.parse().map_err(|_| invalid_input("depth", depth))?;
+    let fill = if matches.is_present("zero") {
+        BitPlaneFill::Zero
+    } else {
+        BitPlaneFill::Random
+    };
 
-    let mut png_data = Vec::new();
-    file.read_to_end(&mut png_data)?;
-    let png = Png::try_from(png_data.as_ref())?;
-    println!("{}", png);
+    let mut image = Image::from_png(&png)?;
+    sanitize_pixels(&mut image, depth, fill)?;
+    let sanitized = image.to_png(&png)?;
 
+    let output = matches.value_of("output").unwrap_or(file_path);
+    save_png(matches, printer, &png_data, output, &sanitized)?;
+    Ok(())
+}
+
+/// Scan the PNG image at the given path for signs of hidden data and print out a report with a
+/// risk score.
+pub fn scan(_matches: &clap::ArgMatches, file_path: &str, printer: &mut Printer) -> Result<()> {
+    let png_data = read_file(file_path)?;
+    let report = pmsg::scan(&png_data)?;
+
+    printer.text(format!("Risk: {}/100", report.risk()));
+    for finding in report.findings() {
+        printer.text(format!("  {}", finding));
+    }
+    if let Some(lsb) = report.lsb() {
+        printer.text(format!(
+            "  LSB: chi-square p = {:.3} over {:.0}% of the samples, RS estimate {:.1}%",
+            lsb.chi_square,
+            lsb.chi_square_extent * 100.0,
+            lsb.rs_rate * 100.0
+        ));
+    }
+
+    let findings: Vec<Value> = report
+        .findings()
+        .iter()
+        .map(|finding| {
+            json!({
+                "severity": finding.severity().to_string(),
+                "chunk": finding.chunk(),
+                "message": finding.message(),
+            })
+        })
+        .collect();
+    printer.record(json!({
+        "path": file_path,
+        "risk": report.risk(),
+        "findings": findings,
+        "lsb": report.lsb().map(|lsb| json!({
+            "chi_square": lsb.chi_square,
+            "chi_square_extent": lsb.chi_square_extent,
+            "rs_rate": lsb.rs_rate,
+        })),
+    }));
+    Ok(())
+}
+
+/// Parse the PNG image at the given path and rebuild it with only the chunks that are needed to
+/// display it and the chunks whose types are given to be kept. Data after the "IEND" chunk and
+/// after the end of the zlib stream of the image data is dropped, and the image data is
+/// re-compressed if asked. The PNG data is then saved to the output
+/// path, if it is given. Otherwise, the PNG data is saved back to the input path.
+pub fn strip(matches: &clap::ArgMatches, file_path: &str, printer: &mut Printer) -> Result<()> {
+    let png_data = read_file(file_path)?;
+    let png = parse_png(matches, png_data.as_ref())?;
+
+    let keep = match matches.values_of("keep") {
+        Some(types) => types
+            .map(ChunkType::from_str)
+            .collect::<Result<Vec<ChunkType>>>()?,
+        None => Vec::new(),
+    };
+    let mut stripped = pmsg::strip(&png, &keep);
+    let mut hidden = 0;
+    if matches.is_present("recompress") {
+        stripped = recompress(&stripped)?;
+    } else {
+        let (trimmed, trailing) = trim_image_data(&stripped)?;
+        stripped = trimmed;
+        hidden = trailing;
+    }
+
+    // in a dry run, the changes and the resulting size are printed out when saving
+    if !matches.is_present("dry_run") {
+        for chunk in png.chunks() {
+            if !stripped
+                .chunks()
+                .iter()
+                .any(|c| c.chunk_type() == chunk.chunk_type())
+            {
+                print_removed_chunk(printer, chunk);
+            }
+        }
+        if hidden > 0 {
+            printer.emit(
+                format!("Remove {} byte(s) after the end of the image data", hidden),
+                json!({ "removed": "idat", "length": hidden }),
+            );
+        }
+        let trailing = png_data.len() - png.as_bytes().len();
+        if trailing > 0 {
+            printer.emit(
+                format!("Remove {} byte(s) after the IEND chunk", trailing),
+                json!({ "removed": "trailing", "length": trailing }),
+            );
+        }
+        printer.text(format!(
+            "{} -> {} bytes",
+            png_data.len(),
+            stripped.as_bytes().len()
+        ));
+    }
+
+    let output = matches.value_of("output").unwrap_or(file_path);
+    save_png(matches, printer, &png_data, output, &stripped)?;
     Ok(())
 }
@@ -0,0 +1,215 @@
+use crate::{Error, Result};
+use std::io::{Read, Write};
+
+/// Compression algorithms that can be applied to a payload before it is hidden.
+///
+/// Decompression is always bounded by a size limit so that a small payload can not expand into an
+/// arbitrarily large amount of memory.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::Compression;
+/// # use std::str::FromStr;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let data = b"THE HIDDEN DATA THE HIDDEN DATA THE HIDDEN DATA".to_vec();
+///     let compression = Compression::from_str("zstd")?;
+///     let compressed = compression.compress(&data, None)?;
+///     assert!(compressed.len() < data.len());
+///     assert_eq!(data, compression.decompress(&compressed, data.len())?);
+///     assert!(compression.decompress(&compressed, data.len() - 1).is_err());
+///     Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Raw DEFLATE stream, levels from 0 to 9.
+    Deflate,
+    /// Zstandard frame, levels from 1 to 22.
+    Zstd,
+    /// Brotli stream, levels from 0 to 11.
+    Brotli,
+}
+
+impl Compression {
+    pub const NAMES: [&'static str; 3] = ["deflate", "zstd", "brotli"];
+
+    /// Get the compression algorithm that is identified by the given byte in an envelope.
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            1 => Ok(Self::Deflate),
+            2 => Ok(Self::Zstd),
+            3 => Ok(Self::Brotli),
+            _ => Err(Error::UnsupportedCompression(id.to_string())),
+        }
+    }
+
+    /// Get the byte that identifies the compression algorithm in an envelope.
+    pub fn id(self) -> u8 {
+        match self {
+            Self::Deflate => 1,
+            Self::Zstd => 2,
+            Self::Brotli => 3,
+        }
+    }
+
+    /// Get the level that is used when none is given.
+    pub fn default_level(self) -> u32 {
+        match self {
+            Self::Deflate => 6,
+            Self::Zstd => 3,
+            Self::Brotli => 9,
+        }
+    }
+
+    /// Get the range of the supported levels.
+    pub fn levels(self) -> std::ops::RangeInclusive<u32> {
+        match self {
+            Self::Deflate => 0..=9,
+            Self::Zstd => 1..=22,
+            Self::Brotli => 0..=11,
+        }
+    }
+
+    /// Compress the given data with the given level, or the default level if none is given.
+    pub fn compress(self, data: &[u8], level: Option<u32>) -> Result<Vec<u8>> {
+        let level = level.unwrap_or_else(|| self.default_level());
+        if !self.levels().contains(&level) {
+            return Err(Error::InvalidCompressionLevel(level));
+        }
+
+        match self {
+            Self::Deflate => {
+                let mut encoder =
+                    flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::new(level));
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            Self::Zstd => Ok(zstd::stream::encode_all(data, level as i32)?),
+            Self::Brotli => {
+                let mut compressed = Vec::new();
+                {
+                    let mut encoder =
+                        brotli::CompressorWriter::new(&mut compressed, 4096, level, 22);
+                    encoder.write_all(data)?;
+                }
+                Ok(compressed)
+            }
+        }
+    }
+
+    /// Decompress the given data, an error is returned if the decompressed data would be larger
+    /// than `limit` bytes.
+    pub fn decompress(self, data: &[u8], limit: usize) -> Result<Vec<u8>> {
+        let decoder: Box<dyn Read + '_> = match self {
+            Self::Deflate => Box::new(flate2::read::DeflateDecoder::new(data)),
+            Self::Zstd => Box::new(zstd::stream::read::Decoder::new(data)?),
+            Self::Brotli => Box::new(brotli::Decompressor::new(data, 4096)),
+        };
+
+        let mut decompressed = Vec::new();
+        decoder
+            .take(limit as u64 + 1)
+            .read_to_end(&mut decompressed)?;
+        if decompressed.len() > limit {
+            return Err(Error::DecompressionLimitExceeded(limit));
+        }
+        Ok(decompressed)
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Self::Deflate => Self::NAMES[0],
+            Self::Zstd => Self::NAMES[1],
+            Self::Brotli => Self::NAMES[2],
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for Compression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "deflate" => Ok(Self::Deflate),
+            "zstd" => Ok(Self::Zstd),
+            "brotli" => Ok(Self::Brotli),
+            _ => Err(Error::UnsupportedCompression(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_data() -> Vec<u8> {
+        b"This is where your secret message will be!"
+            .iter()
+            .cycle()
+            .take(4096)
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let data = testing_data();
+        for name in Compression::NAMES.iter() {
+            let compression = Compression::from_str(name).unwrap();
+            let compressed = compression.compress(&data, None).unwrap();
+            assert!(compressed.len() < data.len());
+            assert_eq!(
+                compression.decompress(&compressed, data.len()).unwrap(),
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn test_levels() {
+        let data = testing_data();
+        for name in Compression::NAMES.iter() {
+            let compression = Compression::from_str(name).unwrap();
+            for level in compression.levels() {
+                let compressed = compression.compress(&data, Some(level)).unwrap();
+                assert_eq!(
+                    compression.decompress(&compressed, data.len()).unwrap(),
+                    data
+                );
+            }
+            let max = *compression.levels().end();
+            assert!(compression.compress(&data, Some(max + 1)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_decompression_limit() {
+        let data = vec![0u8; 1 << 20];
+        for name in Compression::NAMES.iter() {
+            let compression = Compression::from_str(name).unwrap();
+            let compressed = compression.compress(&data, None).unwrap();
+            match compression.decompress(&compressed, 1024) {
+                Err(Error::DecompressionLimitExceeded(1024)) => {}
+                r => panic!("unexpected result {:?}", r.map(|d| d.len())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_ids() {
+        for name in Compression::NAMES.iter() {
+            let compression = Compression::from_str(name).unwrap();
+            assert_eq!(Compression::from_id(compression.id()).unwrap(), compression);
+            assert_eq!(&compression.to_string(), name);
+        }
+        assert!(Compression::from_id(0).is_err());
+        assert!(Compression::from_str("lzma").is_err());
+    }
+}
@@ -0,0 +1,455 @@
+use crate::{Chunk, Image, Png};
+
+/// ChunkChange is a difference between the chunks of two PNG images.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkChange {
+    /// The chunk was added at the given index of the new image.
+    Added { index: usize, chunk: Chunk },
+    /// The chunk was removed from the given index of the old image.
+    Removed { index: usize, chunk: Chunk },
+    /// The chunk was moved from the given index of the old image to the given index of the new
+    /// image.
+    Moved {
+        from: usize,
+        to: usize,
+        chunk: Chunk,
+    },
+    /// The chunk at the given index of the old image was replaced by a chunk of the same type with
+    /// other data at the given index of the new image.
+    Modified {
+        from: usize,
+        to: usize,
+        old: Chunk,
+        new: Chunk,
+    },
+}
+
+impl ChunkChange {
+    /// Get the index of the chunk in the new image, or in the old image for removed chunks.
+    pub fn index(&self) -> usize {
+        match self {
+            Self::Added { index, .. } | Self::Removed { index, .. } => *index,
+            Self::Moved { to, .. } | Self::Modified { to, .. } => *to,
+        }
+    }
+
+    /// Get the chunk that changed, which is the new chunk for modified chunks.
+    pub fn chunk(&self) -> &Chunk {
+        match self {
+            Self::Added { chunk, .. } | Self::Removed { chunk, .. } | Self::Moved { chunk, .. } => {
+                chunk
+            }
+            Self::Modified { new, .. } => new,
+        }
+    }
+}
+
+impl std::fmt::Display for ChunkChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let chunk_type = self.chunk().chunk_type();
+        match self {
+            Self::Added { index, chunk } => {
+                write!(f, "+ #{} {} ({} bytes)", index, chunk_type, chunk.length())
+            }
+            Self::Removed { index, chunk } => {
+                write!(f, "- #{} {} ({} bytes)", index, chunk_type, chunk.length())
+            }
+            Self::Moved { from, to, chunk } => write!(
+                f,
+                "> #{} -> #{} {} ({} bytes)",
+                from,
+                to,
+                chunk_type,
+                chunk.length()
+            ),
+            Self::Modified { from, to, old, new } => {
+                write!(f, "~ #{}", from)?;
+                if from != to {
+                    write!(f, " -> #{}", to)?;
+                }
+                write!(
+                    f,
+                    " {} ({} -> {} bytes)",
+                    chunk_type,
+                    old.length(),
+                    new.length()
+                )
+            }
+        }
+    }
+}
+
+/// PngDiff holds the differences between the chunks of two PNG images together with the verdict
+/// on whether they display the same pixels.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::{Chunk, ChunkType, Ihdr, Image, Png, PngDiff};
+/// # use std::convert::TryFrom;
+/// # use std::str::FromStr;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let ihdr = Chunk::new(ChunkType::from_str("IHDR")?, vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0])?;
+///     let idat = Image::new(Ihdr::try_from(&ihdr)?, vec![0])?.encode()?;
+///     let idat = Chunk::new(ChunkType::from_str("IDAT")?, idat)?;
+///     let text = Chunk::new(ChunkType::from_str("tEXt")?, b"Comment\0hello".to_vec())?;
+///     let iend = Chunk::new(ChunkType::from_str("IEND")?, Vec::new())?;
+///
+///     let old = Png::from_chunks(vec![ihdr.clone(), text.clone(), idat.clone(), iend.clone()]);
+///     let new = Png::from_chunks(vec![ihdr, idat, text, iend]);
+///     let diff = PngDiff::new(&old, &new);
+///     assert_eq!(1, diff.changes().len());
+///     assert_eq!("> #1 -> #2 tEXt (13 bytes)", diff.changes()[0].to_string());
+///     assert_eq!(Some(true), diff.image_data_identical());
+///     Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PngDiff {
+    changes: Vec<ChunkChange>,
+    image_data_identical: Option<bool>,
+}
+
+impl PngDiff {
+    /// Align the chunks of the old and the new image and find the chunks that were added,
+    /// removed, moved or modified.
+    ///
+    /// Chunks that were removed and added again unchanged are reported as moved, and the
+    /// remaining removed and added chunks of the same type are paired up in order and reported as
+    /// modified. The decoded image data of both images is compared, so recompressing the image
+    /// data does not change the verdict.
+    pub fn new(old: &Png, new: &Png) -> Self {
+        let mut removed = Vec::new();
+        let mut added = Vec::new();
+        for change in diff_chunks(old, new) {
+            match change {
+                ChunkChange::Removed { index, chunk } => removed.push(Some((index, chunk))),
+                ChunkChange::Added { index, chunk } => added.push(Some((index, chunk))),
+                _ => {}
+            }
+        }
+
+        let mut changes = Vec::new();
+        // pair up removed and added chunks, first the equal ones and then the ones of the same
+        // type
+        let same_chunk = |a: &Chunk, b: &Chunk| a == b;
+        let same_type = |a: &Chunk, b: &Chunk| a.chunk_type() == b.chunk_type();
+        for (pass, same) in [same_chunk, same_type].iter().enumerate() {
+            for r in removed.iter_mut() {
+                let old_chunk = match r {
+                    Some((_, chunk)) => chunk.clone(),
+                    None => continue,
+                };
+                let found = added
+                    .iter_mut()
+                    .find(|a| matches!(a, Some((_, chunk)) if same(&old_chunk, chunk)));
+                if let Some(a) = found {
+                    let (from, old) = r.take().unwrap();
+                    let (to, new) = a.take().unwrap();
+                    changes.push(if pass == 0 {
+                        ChunkChange::Moved {
+                            from,
+                            to,
+                            chunk: new,
+                        }
+                    } else {
+                        ChunkChange::Modified { from, to, old, new }
+                    });
+                }
+            }
+        }
+        changes.extend(
+            removed
+                .into_iter()
+                .flatten()
+                .map(|(index, chunk)| ChunkChange::Removed { index, chunk }),
+        );
+        changes.extend(
+            added
+                .into_iter()
+                .flatten()
+                .map(|(index, chunk)| ChunkChange::Added { index, chunk }),
+        );
+        changes.sort_by_key(|c| match c {
+            ChunkChange::Removed { index, .. } => (*index, 0),
+            ChunkChange::Moved { from, .. } | ChunkChange::Modified { from, .. } => (*from, 1),
+            ChunkChange::Added { index, .. } => (*index, 2),
+        });
+
+        let image_data_identical = match (Image::from_png(old), Image::from_png(new)) {
+            (Ok(old), Ok(new)) => Some(old == new),
+            _ => None,
+        };
+        Self {
+            changes,
+            image_data_identical,
+        }
+    }
+
+    /// Get the differences between the chunks, ordered by their index in the old image, with
+    /// added chunks after the others at the same index.
+    pub fn changes(&self) -> &[ChunkChange] {
+        &self.changes
+    }
+
+    /// Check whether both images decode to the same header and pixels, `None` is returned if the
+    /// image data of either image can not be decoded.
+    pub fn image_data_identical(&self) -> Option<bool> {
+        self.image_data_identical
+    }
+}
+
+/// Find the smallest list of chunks that are removed from the old image and added to the new one
+/// to turn the chunks of the old image into the chunks of the new image, using the longest common
+/// subsequence of the two lists of chunks. Chunks are equal only if both their types and data are
+/// equal, so a chunk whose data changed is removed and added again.
+///
+/// The changes are returned in the order in which they appear when walking both images together.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::{diff_chunks, Chunk, ChunkChange, ChunkType, Png};
+/// # use std::str::FromStr;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let chunk = |t: &str, data: &[u8]| Chunk::new(ChunkType::from_str(t).unwrap(), data.to_vec());
+///     let (ihdr, iend) = (chunk("IHDR", b"")?, chunk("IEND", b"")?);
+///     let hidden = chunk("ruSt", b"old")?;
+///     let old = Png::from_chunks(vec![ihdr.clone(), hidden.clone(), iend.clone()]);
+///     let new = Png::from_chunks(vec![ihdr, iend]);
+///
+///     let changes = diff_chunks(&old, &new);
+///     assert_eq!(vec![ChunkChange::Removed { index: 1, chunk: hidden }], changes);
+///     assert_eq!("- #1 ruSt (3 bytes)", changes[0].to_string());
+///     Ok(())
+/// # }
+/// ```
+pub fn diff_chunks(old: &Png, new: &Png) -> Vec<ChunkChange> {
+    let (old, new) = (old.chunks(), new.chunks());
+    longest_common_subsequence(old, new)
+        .into_iter()
+        .filter_map(|edit| match edit {
+            Edit::Keep => None,
+            Edit::Remove(i) => Some(ChunkChange::Removed {
+                index: i,
+                chunk: old[i].clone(),
+            }),
+            Edit::Add(j) => Some(ChunkChange::Added {
+                index: j,
+                chunk: new[j].clone(),
+            }),
+        })
+        .collect()
+}
+
+/// Describe the differences between the old and the new data of a chunk, one line per difference.
+/// Lines that are removed start with `-` and lines that are added start with `+`.
+///
+/// Data that is text on both sides is compared line by line, while other data is compared in rows
+/// of 16 bytes that are printed in hexadecimal together with their offset.
+///
+/// # Examples
+///
+/// ```rust
+/// # use pmsg::diff_data;
+/// assert_eq!(vec!["-old", "+new"], diff_data(b"same\nold", b"same\nnew"));
+/// assert_eq!(
+///     vec!["-00000000: 00 01", "+00000000: 00 02"],
+///     diff_data(&[0, 1], &[0, 2])
+/// );
+/// ```
+pub fn diff_data(old: &[u8], new: &[u8]) -> Vec<String> {
+    if let (Some(old), Some(new)) = (as_text(old), as_text(new)) {
+        let (old, new): (Vec<&str>, Vec<&str>) = (old.lines().collect(), new.lines().collect());
+        return longest_common_subsequence(&old, &new)
+            .into_iter()
+            .filter_map(|edit| match edit {
+                Edit::Keep => None,
+                Edit::Remove(i) => Some(format!("-{}", old[i].replace('\0', "\\0"))),
+                Edit::Add(j) => Some(format!("+{}", new[j].replace('\0', "\\0"))),
+            })
+            .collect();
+    }
+
+    let mut lines = Vec::new();
+    let (old_rows, new_rows): (Vec<&[u8]>, Vec<&[u8]>) =
+        (old.chunks(16).collect(), new.chunks(16).collect());
+    for row in 0..old_rows.len().max(new_rows.len()) {
+        let (old_row, new_row) = (old_rows.get(row), new_rows.get(row));
+        if old_row == new_row {
+            continue;
+        }
+        for (sign, bytes) in [('-', old_row), ('+', new_row)].iter() {
+            if let Some(bytes) = bytes {
+                let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                lines.push(format!("{}{:08x}: {}", sign, row * 16, hex.join(" ")));
+            }
+        }
+    }
+    lines
+}
+
+/// Get the given data as text if it is valid UTF-8 without control characters other than line
+/// breaks, tabs and the null separators of text chunks.
+fn as_text(data: &[u8]) -> Option<&str> {
+    let text = std::str::from_utf8(data).ok()?;
+    if text
+        .chars()
+        .all(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t' | '\0'))
+    {
+        Some(text)
+    } else {
+        None
+    }
+}
+
+/// A step that turns one list into another one.
+enum Edit {
+    /// The items at the current positions of both lists are equal.
+    Keep,
+    /// The item at the given index of the old list is removed.
+    Remove(usize),
+    /// The item at the given index of the new list is added.
+    Add(usize),
+}
+
+/// Find the shortest list of steps that turns the old list into the new list, by walking the
+/// longest common subsequence of both lists.
+fn longest_common_subsequence<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    // lengths[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            edits.push(Edit::Keep);
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            edits.push(Edit::Remove(i));
+            i += 1;
+        } else {
+            edits.push(Edit::Add(j));
+            j += 1;
+        }
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::tests::chunk;
+    use std::convert::TryFrom;
+
+    fn testing_png(chunks: &[(&str, &[u8])]) -> Png {
+        Png::from_chunks(chunks.iter().map(|(t, d)| chunk(t, d.to_vec())).collect())
+    }
+
+    #[test]
+    fn test_png_diff() {
+        let png = Png::try_from(&crate::png::tests::PNG_FILE[..]).unwrap();
+        let mut chunks = png.chunks().to_vec();
+        // move the "RuSt" chunk to the front, modify a chunk and recompress the image data
+        let moved = chunks.remove(5);
+        chunks.insert(1, moved);
+        let modified = chunks[3].chunk_type().to_string();
+        chunks[3] = chunk(&modified, b"other data".to_vec());
+        let new = crate::recompress(&Png::from_chunks(chunks)).unwrap();
+
+        let diff = PngDiff::new(&png, &new);
+        assert_eq!(diff.image_data_identical(), Some(true));
+        let modified_chunks: Vec<&ChunkChange> = diff
+            .changes()
+            .iter()
+            .filter(|c| matches!(c, ChunkChange::Modified { .. }))
+            .collect();
+        assert!(modified_chunks
+            .iter()
+            .any(|c| c.chunk().chunk_type().to_string() == modified));
+        assert!(diff.changes().iter().any(|c| matches!(
+            c,
+            ChunkChange::Moved { from: 5, chunk, .. } if chunk.chunk_type().to_string() == "RuSt"
+        )));
+        assert!(!diff
+            .changes()
+            .iter()
+            .any(|c| matches!(c, ChunkChange::Added { .. } | ChunkChange::Removed { .. })));
+    }
+
+    #[test]
+    fn test_image_data_different() {
+        let png = Png::try_from(&crate::png::tests::PNG_FILE[..]).unwrap();
+        let mut image = Image::from_png(&png).unwrap();
+        image.data_mut()[0] ^= 1;
+        let new = image.to_png(&png).unwrap();
+        assert_eq!(PngDiff::new(&png, &new).image_data_identical(), Some(false));
+    }
+
+    #[test]
+    fn test_diff_data() {
+        assert_eq!(diff_data(b"a\nb\nc", b"a\nc\nd"), vec!["-b", "+d"]);
+        assert_eq!(
+            diff_data(b"key\0old", b"key\0new"),
+            vec!["-key\\0old", "+key\\0new"]
+        );
+
+        let old: Vec<u8> = (0..40).collect();
+        let mut new = old.clone();
+        new[20] = 0xff;
+        let lines = diff_data(&old, &new);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("-00000010: 10 11 12 13 14"));
+        assert!(lines[1].starts_with("+00000010: 10 11 12 13 ff"));
+    }
+
+    #[test]
+    fn test_identical() {
+        let png = testing_png(&[("IHDR", b""), ("ruSt", b"data"), ("IEND", b"")]);
+        assert!(diff_chunks(&png, &png).is_empty());
+    }
+
+    #[test]
+    fn test_added_and_removed() {
+        let old = testing_png(&[("IHDR", b""), ("abCd", b"x"), ("IEND", b"")]);
+        let new = testing_png(&[("IHDR", b""), ("ruSt", b"1"), ("ruSt", b"2"), ("IEND", b"")]);
+        let changes: Vec<String> = diff_chunks(&old, &new)
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                "- #1 abCd (1 bytes)",
+                "+ #1 ruSt (1 bytes)",
+                "+ #2 ruSt (1 bytes)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_modified_chunk() {
+        let old = testing_png(&[("IHDR", b""), ("IDAT", b"old"), ("IEND", b"")]);
+        let new = testing_png(&[("IHDR", b""), ("IDAT", b"new!"), ("IEND", b"")]);
+        let changes = diff_chunks(&old, &new);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].index(), 1);
+        assert_eq!(changes[0].chunk().data(), b"old");
+        assert_eq!(changes[1].index(), 1);
+        assert_eq!(changes[1].chunk().data(), b"new!");
+    }
+}
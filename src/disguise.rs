@@ -0,0 +1,363 @@
+use crate::{Chunk, ChunkType, Envelope, Error, Fragment, ReedSolomon, Result};
+use base64::Engine;
+use std::convert::TryFrom;
+
+/// Standard metadata chunks that hidden data can be disguised as, so that the PNG file does not
+/// contain any private chunk.
+///
+/// The hidden data is prefixed with the chunk type code that labels it and is stored as follows:
+///
+/// - `Comment`: a "tEXt" chunk with the `Comment` keyword whose text is the data in base64.
+/// - `Xmp`: an "iTXt" chunk with the `XML:com.adobe.xmp` keyword holding an XMP packet, where the
+///   data is the base64 encoded thumbnail image (`xmpGImg:image`).
+/// - `Exif`: an "eXIf" chunk holding a TIFF structure, where the data is the maker note of the
+///   Exif sub-IFD.
+///
+/// Only data that starts with the magic bytes of an envelope, a fragment, or error correction is
+/// recognized when the chunks are read back, so regular metadata is never mistaken for hidden
+/// data.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::{ChunkType, Disguise, Envelope};
+/// # use std::str::FromStr;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let label = ChunkType::from_str("ruSt")?;
+///     let data = Envelope::from_text("THE HIDDEN DATA").as_bytes()?;
+///     let chunk = Disguise::Xmp.wrap(&label, &data)?;
+///     assert!(chunk.chunk_type().is_public());
+///     assert_eq!(Some((label, data)), Disguise::unwrap(&chunk));
+///     Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disguise {
+    /// A "tEXt" chunk with the `Comment` keyword.
+    Comment,
+    /// An "iTXt" chunk with an XMP packet.
+    Xmp,
+    /// An "eXIf" chunk with a maker note.
+    Exif,
+}
+
+impl Disguise {
+    pub const NAMES: [&'static str; 3] = ["comment", "xmp", "exif"];
+    pub const TEXT_CHUNK_TYPE: [u8; 4] = *b"tEXt";
+    pub const INTERNATIONAL_TEXT_CHUNK_TYPE: [u8; 4] = *b"iTXt";
+    pub const EXIF_CHUNK_TYPE: [u8; 4] = *b"eXIf";
+
+    const COMMENT_KEYWORD: &'static str = "Comment";
+    const XMP_KEYWORD: &'static str = "XML:com.adobe.xmp";
+    const XMP_IMAGE_START: &'static str = "<xmpGImg:image>";
+    const XMP_IMAGE_END: &'static str = "</xmpGImg:image>";
+
+    const EXIF_IFD_POINTER_TAG: u16 = 0x8769;
+    const MAKER_NOTE_TAG: u16 = 0x927c;
+
+    /// Get the kind of metadata that is stored in chunks with the given type code.
+    pub fn from_chunk_type(chunk_type: &ChunkType) -> Option<Self> {
+        match chunk_type.bytes() {
+            Self::TEXT_CHUNK_TYPE => Some(Self::Comment),
+            Self::INTERNATIONAL_TEXT_CHUNK_TYPE => Some(Self::Xmp),
+            Self::EXIF_CHUNK_TYPE => Some(Self::Exif),
+            _ => None,
+        }
+    }
+
+    /// Get the chunk that hides the given data labeled with the given chunk type code.
+    pub fn wrap(self, label: &ChunkType, data: &[u8]) -> Result<Chunk> {
+        let mut labeled = label.bytes().to_vec();
+        labeled.extend_from_slice(data);
+
+        let (chunk_type, chunk_data) = match self {
+            Self::Comment => {
+                let mut chunk_data = Self::COMMENT_KEYWORD.as_bytes().to_vec();
+                chunk_data.push(0);
+                chunk_data.extend_from_slice(encode_base64(&labeled).as_bytes());
+                (Self::TEXT_CHUNK_TYPE, chunk_data)
+            }
+            Self::Xmp => {
+                let mut chunk_data = Self::XMP_KEYWORD.as_bytes().to_vec();
+                // null separator, no compression, empty language tag and translated keyword
+                chunk_data.extend_from_slice(&[0, 0, 0, 0, 0]);
+                chunk_data.extend_from_slice(xmp_packet(&labeled).as_bytes());
+                (Self::INTERNATIONAL_TEXT_CHUNK_TYPE, chunk_data)
+            }
+            Self::Exif => (Self::EXIF_CHUNK_TYPE, exif_maker_note(&labeled)?),
+        };
+        Chunk::new(ChunkType::try_from(chunk_type)?, chunk_data)
+    }
+
+    /// Find the data that is hidden in the given chunk together with the chunk type code that
+    /// labels it, `None` is returned if the chunk does not hide any data.
+    pub fn unwrap(chunk: &Chunk) -> Option<(ChunkType, Vec<u8>)> {
+        let data = chunk.data();
+        let labeled = match chunk.chunk_type().bytes() {
+            Self::TEXT_CHUNK_TYPE => {
+                let text = data.strip_prefix(Self::COMMENT_KEYWORD.as_bytes())?;
+                let text = text.strip_prefix(&[0])?;
+                decode_base64(std::str::from_utf8(text).ok()?)?
+            }
+            Self::INTERNATIONAL_TEXT_CHUNK_TYPE => {
+                let text = data.strip_prefix(Self::XMP_KEYWORD.as_bytes())?;
+                let text = text.strip_prefix(&[0, 0, 0, 0, 0])?;
+                let text = std::str::from_utf8(text).ok()?;
+                let start = text.find(Self::XMP_IMAGE_START)? + Self::XMP_IMAGE_START.len();
+                let end = start + text[start..].find(Self::XMP_IMAGE_END)?;
+                decode_base64(&text[start..end].replace("&#xA;", ""))?
+            }
+            Self::EXIF_CHUNK_TYPE => read_exif_maker_note(data)?,
+            _ => return None,
+        };
+
+        if labeled.len() < 4 {
+            return None;
+        }
+        let (label, data) = labeled.split_at(4);
+        let label = ChunkType::try_from([label[0], label[1], label[2], label[3]]).ok()?;
+        if !(Envelope::is_envelope(data)
+            || Fragment::is_fragment(data)
+            || ReedSolomon::is_encoded(data))
+        {
+            return None;
+        }
+        Some((label, data.to_vec()))
+    }
+}
+
+impl std::fmt::Display for Disguise {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Self::Comment => Self::NAMES[0],
+            Self::Xmp => Self::NAMES[1],
+            Self::Exif => Self::NAMES[2],
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for Disguise {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "comment" => Ok(Self::Comment),
+            "xmp" => Ok(Self::Xmp),
+            "exif" => Ok(Self::Exif),
+            _ => Err(Error::UnsupportedDisguise(s.to_string())),
+        }
+    }
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(data)
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    base64::engine::general_purpose::STANDARD
+        .decode(text.trim())
+        .ok()
+}
+
+/// Build an XMP packet that has the given data as the thumbnail image, with lines of 76
+/// characters as written by Adobe applications.
+fn xmp_packet(data: &[u8]) -> String {
+    let encoded = encode_base64(data);
+    let lines: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|l| std::str::from_utf8(l).unwrap())
+        .collect();
+    format!(
+        concat!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\" x:xmptk=\"Adobe XMP Core 5.6-c140\">\n",
+            " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+            "  <rdf:Description rdf:about=\"\"\n",
+            "    xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n",
+            "    xmlns:xmpGImg=\"http://ns.adobe.com/xap/1.0/g/img/\">\n",
+            "   <xmp:CreatorTool>Adobe Photoshop CC 2015 (Windows)</xmp:CreatorTool>\n",
+            "   <xmp:Thumbnails>\n",
+            "    <rdf:Alt>\n",
+            "     <rdf:li rdf:parseType=\"Resource\">\n",
+            "      <xmpGImg:format>JPEG</xmpGImg:format>\n",
+            "      {}{}{}\n",
+            "     </rdf:li>\n",
+            "    </rdf:Alt>\n",
+            "   </xmp:Thumbnails>\n",
+            "  </rdf:Description>\n",
+            " </rdf:RDF>\n",
+            "</x:xmpmeta>\n",
+            "<?xpacket end=\"w\"?>"
+        ),
+        Disguise::XMP_IMAGE_START,
+        lines.join("&#xA;"),
+        Disguise::XMP_IMAGE_END
+    )
+}
+
+/// Build a big-endian TIFF structure whose first IFD points to an Exif IFD that has the given
+/// data as its maker note.
+fn exif_maker_note(data: &[u8]) -> Result<Vec<u8>> {
+    const IFD0_OFFSET: u32 = 8;
+    // header, IFD0 with one entry, Exif IFD with one entry
+    const EXIF_IFD_OFFSET: u32 = IFD0_OFFSET + 2 + 12 + 4;
+    const MAKER_NOTE_OFFSET: u32 = EXIF_IFD_OFFSET + 2 + 12 + 4;
+    const TYPE_LONG: u16 = 4;
+    const TYPE_UNDEFINED: u16 = 7;
+
+    let count = u32::try_from(data.len())?;
+    let mut tiff = b"MM\0\x2a".to_vec();
+    tiff.extend_from_slice(&IFD0_OFFSET.to_be_bytes());
+    for &(tag, kind, count, value) in [
+        (
+            Disguise::EXIF_IFD_POINTER_TAG,
+            TYPE_LONG,
+            1,
+            EXIF_IFD_OFFSET,
+        ),
+        (
+            Disguise::MAKER_NOTE_TAG,
+            TYPE_UNDEFINED,
+            count,
+            MAKER_NOTE_OFFSET,
+        ),
+    ]
+    .iter()
+    {
+        tiff.extend_from_slice(&1u16.to_be_bytes());
+        tiff.extend_from_slice(&tag.to_be_bytes());
+        tiff.extend_from_slice(&kind.to_be_bytes());
+        tiff.extend_from_slice(&count.to_be_bytes());
+        tiff.extend_from_slice(&value.to_be_bytes());
+        // no next IFD
+        tiff.extend_from_slice(&0u32.to_be_bytes());
+    }
+    tiff.extend_from_slice(data);
+    Ok(tiff)
+}
+
+/// Read the maker note of the Exif IFD of the given TIFF structure.
+fn read_exif_maker_note(tiff: &[u8]) -> Option<Vec<u8>> {
+    let big_endian = match tiff.get(..4)? {
+        b"MM\0\x2a" => true,
+        b"II\x2a\0" => false,
+        _ => return None,
+    };
+    let u16_at = |pos: usize| -> Option<u16> {
+        let b = tiff.get(pos..pos + 2)?;
+        Some(if big_endian {
+            u16::from_be_bytes([b[0], b[1]])
+        } else {
+            u16::from_le_bytes([b[0], b[1]])
+        })
+    };
+    let u32_at = |pos: usize| -> Option<u32> {
+        let b = tiff.get(pos..pos + 4)?;
+        Some(if big_endian {
+            u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+        } else {
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+        })
+    };
+    // find the entry with the given tag in the IFD at the given offset
+    let find_entry = |ifd: usize, tag: u16| -> Option<usize> {
+        let count = u16_at(ifd)? as usize;
+        (0..count)
+            .map(|i| ifd + 2 + i * 12)
+            .find(|&entry| u16_at(entry) == Some(tag))
+    };
+
+    let ifd0 = u32_at(4)? as usize;
+    let exif_ifd = u32_at(find_entry(ifd0, Disguise::EXIF_IFD_POINTER_TAG)? + 8)? as usize;
+    let maker_note = find_entry(exif_ifd, Disguise::MAKER_NOTE_TAG)?;
+    let count = u32_at(maker_note + 4)? as usize;
+    let data = if count <= 4 {
+        tiff.get(maker_note + 8..maker_note + 8 + count)?
+    } else {
+        let offset = u32_at(maker_note + 8)? as usize;
+        tiff.get(offset..offset.checked_add(count)?)?
+    };
+    Some(data.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_label() -> ChunkType {
+        ChunkType::from_str("ruSt").unwrap()
+    }
+
+    fn testing_data() -> Vec<u8> {
+        Envelope::from_text("This is where your secret message will be!")
+            .as_bytes()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for name in Disguise::NAMES.iter() {
+            let disguise = Disguise::from_str(name).unwrap();
+            let chunk = disguise.wrap(&testing_label(), &testing_data()).unwrap();
+            assert!(chunk.chunk_type().is_public());
+            assert_eq!(
+                Disguise::unwrap(&chunk),
+                Some((testing_label(), testing_data()))
+            );
+            assert_eq!(&disguise.to_string(), name);
+        }
+    }
+
+    #[test]
+    fn test_comment_chunk() {
+        let chunk = Disguise::Comment
+            .wrap(&testing_label(), &testing_data())
+            .unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "tEXt");
+        assert!(chunk.data().starts_with(b"Comment\0cnVTdFBNU0"));
+    }
+
+    #[test]
+    fn test_xmp_lines() {
+        let data = Envelope::new(vec![7u8; 1000]).as_bytes().unwrap();
+        let chunk = Disguise::Xmp.wrap(&testing_label(), &data).unwrap();
+        let text = String::from_utf8(chunk.data().to_vec()).unwrap();
+        assert!(text.contains("&#xA;"));
+        assert_eq!(Disguise::unwrap(&chunk), Some((testing_label(), data)));
+    }
+
+    #[test]
+    fn test_little_endian_exif() {
+        let mut tiff = b"II\x2a\0\x08\0\0\0".to_vec();
+        tiff.extend_from_slice(&[1, 0, 0x69, 0x87, 4, 0, 1, 0, 0, 0, 26, 0, 0, 0, 0, 0, 0, 0]);
+        tiff.extend_from_slice(&[1, 0, 0x7c, 0x92, 7, 0, 3, 0, 0, 0, b'a', b'b', b'c', 0]);
+        tiff.extend_from_slice(&[0, 0, 0, 0]);
+        assert_eq!(read_exif_maker_note(&tiff), Some(b"abc".to_vec()));
+    }
+
+    #[test]
+    fn test_regular_metadata() {
+        let chunks = [
+            Chunk::new(
+                ChunkType::from_str("tEXt").unwrap(),
+                b"Comment\0Created with GIMP".to_vec(),
+            )
+            .unwrap(),
+            Chunk::new(
+                ChunkType::from_str("tEXt").unwrap(),
+                b"Comment\0aGVsbG8gd29ybGQ=".to_vec(),
+            )
+            .unwrap(),
+            Chunk::new(ChunkType::from_str("eXIf").unwrap(), b"MM\0\x2a".to_vec()).unwrap(),
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), testing_data()).unwrap(),
+        ];
+        for chunk in chunks.iter() {
+            assert_eq!(Disguise::unwrap(chunk), None);
+        }
+    }
+}
@@ -0,0 +1,432 @@
+use crate::{Error, Result};
+use std::convert::TryInto;
+
+/// Reed-Solomon forward error correction for the bytes that are hidden inside a PNG file.
+///
+/// The data is prefixed with its length (4 bytes, big-endian) and split into blocks of at most
+/// `255 - parity` bytes. Every block is extended with `parity` bytes computed over GF(2^8), which
+/// allows up to `parity / 2` corrupted bytes to be corrected in each block. The encoded data is
+/// laid out as follows:
+///
+/// | Field   | Size     | Description                                     |
+/// |---------|----------|-------------------------------------------------|
+/// | magic   | 4        | always `PMSR`                                   |
+/// | version | 1        | version of the format, currently `1`            |
+/// | parity  | 1        | number of parity bytes in each block            |
+/// | blocks  | variable | blocks of at most 255 bytes, data then parity   |
+///
+/// The header is not protected, only the blocks are.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::ReedSolomon;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let rs = ReedSolomon::new(16)?;
+///     let mut encoded = rs.encode(b"THE HIDDEN DATA");
+///     encoded[10] ^= 0xff;
+///     encoded[20] ^= 0x01;
+///
+///     let (data, corrected) = ReedSolomon::decode(&encoded)?;
+///     assert_eq!(b"THE HIDDEN DATA", data.as_slice());
+///     assert_eq!(2, corrected);
+///     Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReedSolomon {
+    parity: usize,
+}
+
+impl ReedSolomon {
+    pub const MAGIC: [u8; 4] = *b"PMSR";
+    pub const VERSION: u8 = 1;
+    pub const HEADER_LENGTH: usize = 6;
+    pub const BLOCK_LENGTH: usize = 255;
+
+    /// Create a new codec that adds the given number of parity bytes to each block. The number of
+    /// parity bytes must be between 2 and 254.
+    pub fn new(parity: u8) -> Result<Self> {
+        if !(2..=254).contains(&parity) {
+            return Err(Error::InvalidErrorCorrection(parity.into()));
+        }
+        Ok(Self {
+            parity: parity.into(),
+        })
+    }
+
+    /// Create a new codec that adds roughly the given percentage of redundancy to the data, i.e.,
+    /// `100` doubles the size of the data and allows about a quarter of the bytes in every block
+    /// to be corrupted.
+    pub fn with_redundancy(percent: u32) -> Result<Self> {
+        if !(1..=1000).contains(&percent) {
+            return Err(Error::InvalidErrorCorrection(percent));
+        }
+        let block = Self::BLOCK_LENGTH as u32;
+        let parity = (block * percent).div_ceil(100 + percent).max(2);
+        Self::new(parity.try_into()?)
+    }
+
+    /// Get the number of parity bytes in each block.
+    pub fn parity(&self) -> usize {
+        self.parity
+    }
+
+    /// Get the largest number of bytes that can be encoded into at most `encoded_length` bytes.
+    pub fn capacity(&self, encoded_length: usize) -> usize {
+        let blocks = encoded_length.saturating_sub(Self::HEADER_LENGTH);
+        let full = blocks / Self::BLOCK_LENGTH * (Self::BLOCK_LENGTH - self.parity);
+        let partial = (blocks % Self::BLOCK_LENGTH).saturating_sub(self.parity);
+        (full + partial).saturating_sub(4)
+    }
+
+    /// Check whether the given data starts with the magic bytes of error-corrected data.
+    pub fn is_encoded(data: &[u8]) -> bool {
+        data.starts_with(&Self::MAGIC)
+    }
+
+    /// Encode the given data.
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let generator = generator_poly(self.parity);
+        let message: Vec<u8> = (data.len() as u32)
+            .to_be_bytes()
+            .iter()
+            .chain(data.iter())
+            .copied()
+            .collect();
+
+        let mut encoded = Vec::with_capacity(Self::HEADER_LENGTH + message.len() * 2);
+        encoded.extend_from_slice(&Self::MAGIC);
+        encoded.push(Self::VERSION);
+        encoded.push(self.parity as u8);
+        for block in message.chunks(Self::BLOCK_LENGTH - self.parity) {
+            encoded.extend_from_slice(block);
+            encoded.extend_from_slice(&parity_bytes(block, &generator));
+        }
+        encoded
+    }
+
+    /// Decode the given data, correcting corrupted bytes where possible. The decoded data is
+    /// returned together with the number of bytes that were corrected.
+    pub fn decode(encoded: &[u8]) -> Result<(Vec<u8>, usize)> {
+        if encoded.len() < Self::HEADER_LENGTH
+            || !Self::is_encoded(encoded)
+            || encoded[4] != Self::VERSION
+        {
+            return Err(Error::InvalidErrorCorrectionHeader);
+        }
+        let codec = Self::new(encoded[5]).map_err(|_| Error::InvalidErrorCorrectionHeader)?;
+
+        let mut message = Vec::new();
+        let mut corrected = 0;
+        for block in encoded[Self::HEADER_LENGTH..].chunks(Self::BLOCK_LENGTH) {
+            if block.len() <= codec.parity {
+                return Err(Error::UncorrectableErrors);
+            }
+            let (data, count) = correct_block(block, codec.parity)?;
+            message.extend_from_slice(&data);
+            corrected += count;
+        }
+
+        if message.len() < 4 {
+            return Err(Error::UncorrectableErrors);
+        }
+        let length = u32::from_be_bytes(message[..4].try_into()?) as usize;
+        if message.len() - 4 < length {
+            return Err(Error::UncorrectableErrors);
+        }
+        message.truncate(length + 4);
+        message.drain(..4);
+        Ok((message, corrected))
+    }
+}
+
+/// Arithmetic tables for GF(2^8) with the primitive polynomial x^8 + x^4 + x^3 + x^2 + 1.
+struct Tables {
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+const TABLES: Tables = build_tables();
+
+const fn build_tables() -> Tables {
+    let mut exp = [0u8; 512];
+    let mut log = [0u8; 256];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= 0x11d;
+        }
+        i += 1;
+    }
+    while i < 512 {
+        exp[i] = exp[i - 255];
+        i += 1;
+    }
+    Tables { exp, log }
+}
+
+fn gf_mul(x: u8, y: u8) -> u8 {
+    if x == 0 || y == 0 {
+        return 0;
+    }
+    TABLES.exp[TABLES.log[x as usize] as usize + TABLES.log[y as usize] as usize]
+}
+
+fn gf_div(x: u8, y: u8) -> u8 {
+    if x == 0 {
+        return 0;
+    }
+    TABLES.exp[(TABLES.log[x as usize] as usize + 255 - TABLES.log[y as usize] as usize) % 255]
+}
+
+fn gf_pow(x: u8, power: i32) -> u8 {
+    let exponent = (TABLES.log[x as usize] as i32 * power).rem_euclid(255);
+    TABLES.exp[exponent as usize]
+}
+
+fn gf_inverse(x: u8) -> u8 {
+    TABLES.exp[255 - TABLES.log[x as usize] as usize]
+}
+
+/// Polynomials are stored with the coefficient of the highest degree first.
+fn poly_scale(p: &[u8], x: u8) -> Vec<u8> {
+    p.iter().map(|&c| gf_mul(c, x)).collect()
+}
+
+fn poly_add(p: &[u8], q: &[u8]) -> Vec<u8> {
+    let len = p.len().max(q.len());
+    let mut r = vec![0u8; len];
+    for (i, &c) in p.iter().enumerate() {
+        r[i + len - p.len()] = c;
+    }
+    for (i, &c) in q.iter().enumerate() {
+        r[i + len - q.len()] ^= c;
+    }
+    r
+}
+
+fn poly_mul(p: &[u8], q: &[u8]) -> Vec<u8> {
+    let mut r = vec![0u8; p.len() + q.len() - 1];
+    for (j, &qc) in q.iter().enumerate() {
+        for (i, &pc) in p.iter().enumerate() {
+            r[i + j] ^= gf_mul(pc, qc);
+        }
+    }
+    r
+}
+
+fn poly_eval(p: &[u8], x: u8) -> u8 {
+    p.iter().skip(1).fold(p[0], |y, &c| gf_mul(y, x) ^ c)
+}
+
+fn generator_poly(parity: usize) -> Vec<u8> {
+    (0..parity).fold(vec![1], |g, i| poly_mul(&g, &[1, gf_pow(2, i as i32)]))
+}
+
+/// Compute the parity bytes of the given block as the remainder of the division by the generator
+/// polynomial.
+fn parity_bytes(block: &[u8], generator: &[u8]) -> Vec<u8> {
+    let mut remainder: Vec<u8> = block.to_vec();
+    remainder.resize(block.len() + generator.len() - 1, 0);
+    for i in 0..block.len() {
+        let coef = remainder[i];
+        if coef != 0 {
+            for (j, &g) in generator.iter().enumerate().skip(1) {
+                remainder[i + j] ^= gf_mul(g, coef);
+            }
+        }
+    }
+    remainder.split_off(block.len())
+}
+
+fn syndromes(block: &[u8], parity: usize) -> Vec<u8> {
+    std::iter::once(0)
+        .chain((0..parity).map(|i| poly_eval(block, gf_pow(2, i as i32))))
+        .collect()
+}
+
+/// Find the error locator polynomial with the Berlekamp-Massey algorithm.
+fn error_locator(synd: &[u8], parity: usize) -> Result<Vec<u8>> {
+    let mut err_loc = vec![1u8];
+    let mut old_loc = vec![1u8];
+    let shift = synd.len() - parity;
+    for i in 0..parity {
+        let k = i + shift;
+        let mut delta = synd[k];
+        for j in 1..err_loc.len() {
+            delta ^= gf_mul(err_loc[err_loc.len() - 1 - j], synd[k - j]);
+        }
+        old_loc.push(0);
+        if delta != 0 {
+            if old_loc.len() > err_loc.len() {
+                let new_loc = poly_scale(&old_loc, delta);
+                old_loc = poly_scale(&err_loc, gf_inverse(delta));
+                err_loc = new_loc;
+            }
+            err_loc = poly_add(&err_loc, &poly_scale(&old_loc, delta));
+        }
+    }
+
+    let leading = err_loc.iter().take_while(|&&c| c == 0).count();
+    err_loc.drain(..leading);
+    if (err_loc.len() - 1) * 2 > parity {
+        return Err(Error::UncorrectableErrors);
+    }
+    Ok(err_loc)
+}
+
+/// Find the positions of the errors with a Chien search.
+fn error_positions(err_loc: &[u8], len: usize) -> Result<Vec<usize>> {
+    let reversed: Vec<u8> = err_loc.iter().rev().copied().collect();
+    let positions: Vec<usize> = (0..len)
+        .filter(|&i| poly_eval(&reversed, gf_pow(2, i as i32)) == 0)
+        .map(|i| len - 1 - i)
+        .collect();
+    if positions.len() != err_loc.len() - 1 {
+        return Err(Error::UncorrectableErrors);
+    }
+    Ok(positions)
+}
+
+/// Correct the errors at the given positions with the Forney algorithm.
+fn correct_errata(block: &[u8], synd: &[u8], positions: &[usize]) -> Result<Vec<u8>> {
+    let coef_pos: Vec<usize> = positions.iter().map(|p| block.len() - 1 - p).collect();
+    let err_loc = coef_pos.iter().fold(vec![1u8], |loc, &p| {
+        poly_mul(&loc, &poly_add(&[1], &[gf_pow(2, p as i32), 0]))
+    });
+
+    // error evaluator: (S(x) * L(x)) mod x^(n + 1)
+    let synd_rev: Vec<u8> = synd.iter().rev().copied().collect();
+    let product = poly_mul(&synd_rev, &err_loc);
+    let keep = err_loc.len();
+    let err_eval: Vec<u8> = product[product.len() - keep..].to_vec();
+
+    let xs: Vec<u8> = coef_pos
+        .iter()
+        .map(|&p| gf_pow(2, -(255 - p as i32)))
+        .collect();
+
+    let mut magnitudes = vec![0u8; block.len()];
+    for (i, &xi) in xs.iter().enumerate() {
+        let xi_inv = gf_inverse(xi);
+        let err_loc_prime = xs
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .fold(1u8, |acc, (_, &xj)| gf_mul(acc, 1 ^ gf_mul(xi_inv, xj)));
+        if err_loc_prime == 0 {
+            return Err(Error::UncorrectableErrors);
+        }
+        let y = gf_mul(xi, poly_eval(&err_eval, xi_inv));
+        magnitudes[positions[i]] = gf_div(y, err_loc_prime);
+    }
+    Ok(poly_add(block, &magnitudes))
+}
+
+/// Correct the given block and return its data together with the number of corrected bytes.
+fn correct_block(block: &[u8], parity: usize) -> Result<(Vec<u8>, usize)> {
+    let data_len = block.len() - parity;
+    let synd = syndromes(block, parity);
+    if synd.iter().all(|&s| s == 0) {
+        return Ok((block[..data_len].to_vec(), 0));
+    }
+
+    let err_loc = error_locator(&synd, parity)?;
+    let positions = error_positions(&err_loc, block.len())?;
+    let corrected = correct_errata(block, &synd, &positions)?;
+    if syndromes(&corrected, parity).iter().any(|&s| s != 0) {
+        return Err(Error::UncorrectableErrors);
+    }
+    Ok((corrected[..data_len].to_vec(), positions.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_data() -> Vec<u8> {
+        b"This is where your secret message will be!"
+            .iter()
+            .cycle()
+            .take(1000)
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let rs = ReedSolomon::new(10).unwrap();
+        let encoded = rs.encode(&testing_data());
+        assert_eq!(encoded.len(), 6 + 1004 + 5 * 10);
+        assert_eq!(ReedSolomon::decode(&encoded).unwrap(), (testing_data(), 0));
+    }
+
+    #[test]
+    fn test_roundtrip_empty() {
+        let rs = ReedSolomon::new(2).unwrap();
+        let encoded = rs.encode(&[]);
+        assert_eq!(ReedSolomon::decode(&encoded).unwrap(), (Vec::new(), 0));
+    }
+
+    #[test]
+    fn test_correct_errors() {
+        let rs = ReedSolomon::new(16).unwrap();
+        let mut encoded = rs.encode(&testing_data());
+        // eight errors in every block, including the last shortened block
+        for block in 0..5 {
+            for i in 0..8 {
+                let pos = ReedSolomon::HEADER_LENGTH + block * 255 + i * 7;
+                encoded[pos] ^= 0x5a;
+            }
+        }
+        let (data, corrected) = ReedSolomon::decode(&encoded).unwrap();
+        assert_eq!(data, testing_data());
+        assert_eq!(corrected, 40);
+    }
+
+    #[test]
+    fn test_too_many_errors() {
+        let rs = ReedSolomon::new(4).unwrap();
+        let mut encoded = rs.encode(&testing_data());
+        for i in 0..3 {
+            encoded[ReedSolomon::HEADER_LENGTH + 7 * i + 1] ^= 0xff;
+        }
+        assert!(ReedSolomon::decode(&encoded).is_err());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let rs = ReedSolomon::new(10).unwrap();
+        for len in [0, 1, 240, 241, 242, 1000].iter() {
+            let encoded = rs.encode(&vec![0; *len]);
+            assert_eq!(rs.capacity(encoded.len()), *len);
+        }
+    }
+
+    #[test]
+    fn test_with_redundancy() {
+        assert_eq!(ReedSolomon::with_redundancy(100).unwrap().parity(), 128);
+        assert_eq!(ReedSolomon::with_redundancy(1).unwrap().parity(), 3);
+        assert!(ReedSolomon::with_redundancy(0).is_err());
+        assert!(ReedSolomon::new(1).is_err());
+        assert!(ReedSolomon::new(255).is_err());
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let encoded = ReedSolomon::new(4).unwrap().encode(b"data");
+        let mut bad = encoded.clone();
+        bad[0] = b'X';
+        assert!(ReedSolomon::decode(&bad).is_err());
+        let mut bad = encoded;
+        bad[5] = 0;
+        assert!(ReedSolomon::decode(&bad).is_err());
+    }
+}
@@ -0,0 +1,680 @@
+use crate::{Compression, Error, Result};
+use crc::crc32;
+use std::convert::{TryFrom, TryInto};
+use std::io::{Cursor, Read};
+
+/// Tags of the known header fields of an envelope.
+const FIELD_MIME_TYPE: u8 = 1;
+const FIELD_FILENAME: u8 = 2;
+const FIELD_COMPRESSION: u8 = 3;
+const FIELD_ID: u8 = 4;
+const FIELD_LABEL: u8 = 5;
+const FIELD_TIMESTAMP: u8 = 6;
+
+/// Maximum number of bytes that is stored for a text field in the envelope header.
+const MAX_TEXT_FIELD_LENGTH: usize = 4096;
+
+/// Envelope wraps the bytes that are hidden inside a PNG file together with the metadata needed to
+/// recognize and restore them.
+///
+/// All integers are stored in big-endian order and the envelope is laid out as follows:
+///
+/// | Field          | Size     | Description                                             |
+/// |----------------|----------|---------------------------------------------------------|
+/// | magic          | 4        | always `PMSG`                                           |
+/// | version        | 1        | version of the format, currently `1`                    |
+/// | flags          | 1        | bit 0: compressed, bit 1: encrypted, bit 2: signed      |
+/// | fields length  | 2        | total size of the header fields that follow             |
+/// | fields         | variable | list of `tag (1) | length (2) | value (length)` entries |
+/// | payload length | 4        | size of the payload                                     |
+/// | checksum       | 4        | CRC32 of the payload as it is stored                    |
+/// | payload        | variable | the hidden bytes                                        |
+///
+/// The known header fields are the MIME type (tag `1`) and the original file name (tag `2`), both
+/// UTF-8 strings, and the compression (tag `3`), which holds the [`Compression`] id of the
+/// algorithm (1 byte) followed by the size of the payload before it was compressed (4 bytes). The
+/// compression field must be present if and only if the compressed flag is set. Messages are
+/// identified by the optional id (tag `4`, 4 bytes), label (tag `5`, a UTF-8 string) and time at
+/// which they were written (tag `6`, seconds since the Unix epoch in 8 bytes). Fields with unknown
+/// tags are skipped when parsing so that newer writers can add metadata without breaking older
+/// readers. Bits of the flags that are not listed are reserved and must be zero.
+///
+/// Data that does not start with the magic bytes was written before envelopes existed, such data
+/// can still be read with [`Envelope::decode`], which treats it as plain text.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::Envelope;
+/// # use std::convert::TryFrom;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let mut envelope = Envelope::new(b"THE HIDDEN DATA".to_vec());
+///     envelope.set_filename(Some("data.bin".to_string()));
+///
+///     let bytes = envelope.as_bytes()?;
+///     let parsed = Envelope::try_from(bytes.as_ref())?;
+///     assert_eq!(b"THE HIDDEN DATA", parsed.payload());
+///     assert_eq!(Some("data.bin"), parsed.filename());
+///     Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    flags: u8,
+    mime_type: String,
+    filename: Option<String>,
+    compression: Option<(Compression, u32)>,
+    id: Option<u32>,
+    label: Option<String>,
+    timestamp: Option<u64>,
+    payload: Vec<u8>,
+}
+
+impl Envelope {
+    pub const MAGIC: [u8; 4] = *b"PMSG";
+    pub const VERSION: u8 = 1;
+
+    pub const FLAG_COMPRESSED: u8 = 1;
+    pub const FLAG_ENCRYPTED: u8 = 1 << 1;
+    pub const FLAG_SIGNED: u8 = 1 << 2;
+    const KNOWN_FLAGS: u8 = Self::FLAG_COMPRESSED | Self::FLAG_ENCRYPTED | Self::FLAG_SIGNED;
+
+    pub const DEFAULT_MIME_TYPE: &'static str = "application/octet-stream";
+    pub const TEXT_MIME_TYPE: &'static str = "text/plain; charset=utf-8";
+
+    /// Create a new envelope that holds the given binary payload.
+    pub fn new(payload: Vec<u8>) -> Self {
+        Self {
+            flags: 0,
+            mime_type: Self::DEFAULT_MIME_TYPE.to_string(),
+            filename: None,
+            compression: None,
+            id: None,
+            label: None,
+            timestamp: None,
+            payload,
+        }
+    }
+
+    /// Create a new envelope that holds the given text message.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use pmsg::Envelope;
+    /// let envelope = Envelope::from_text("THE HIDDEN MESSAGE");
+    /// assert_eq!(Envelope::TEXT_MIME_TYPE, envelope.mime_type());
+    /// assert_eq!(b"THE HIDDEN MESSAGE", envelope.payload());
+    /// ```
+    pub fn from_text(text: &str) -> Self {
+        let mut envelope = Self::new(text.as_bytes().to_vec());
+        envelope.set_mime_type(Self::TEXT_MIME_TYPE);
+        envelope
+    }
+
+    /// Parse the given data as an envelope if it starts with the magic bytes, otherwise, the data
+    /// is treated as a plain text message that was written without an envelope.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use pmsg::Envelope;
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    ///     let legacy = Envelope::decode(b"THE HIDDEN MESSAGE")?;
+    ///     assert_eq!(Envelope::TEXT_MIME_TYPE, legacy.mime_type());
+    ///
+    ///     let bytes = Envelope::new(b"THE HIDDEN DATA".to_vec()).as_bytes()?;
+    ///     let envelope = Envelope::decode(&bytes)?;
+    ///     assert_eq!(Envelope::DEFAULT_MIME_TYPE, envelope.mime_type());
+    ///     Ok(())
+    /// # }
+    /// ```
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if Self::is_envelope(data) {
+            Self::try_from(data)
+        } else {
+            let mut envelope = Self::new(data.to_vec());
+            envelope.set_mime_type(Self::TEXT_MIME_TYPE);
+            Ok(envelope)
+        }
+    }
+
+    /// Check whether the given data starts with the magic bytes of an envelope.
+    pub fn is_envelope(data: &[u8]) -> bool {
+        data.starts_with(&Self::MAGIC)
+    }
+
+    /// Get the flags of the envelope.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Set the flags of the envelope, reserved bits and the compressed flag are ignored. The
+    /// compressed flag is managed by [`Envelope::compress`] and [`Envelope::decompress`].
+    pub fn set_flags(&mut self, flags: u8) {
+        self.flags = (self.flags & Self::FLAG_COMPRESSED)
+            | (flags & Self::KNOWN_FLAGS & !Self::FLAG_COMPRESSED);
+    }
+
+    /// Check whether the payload is compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.flags & Self::FLAG_COMPRESSED != 0
+    }
+
+    /// Check whether the payload is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & Self::FLAG_ENCRYPTED != 0
+    }
+
+    /// Check whether the payload is signed.
+    pub fn is_signed(&self) -> bool {
+        self.flags & Self::FLAG_SIGNED != 0
+    }
+
+    /// Get the MIME type of the payload.
+    pub fn mime_type(&self) -> &str {
+        &self.mime_type
+    }
+
+    /// Set the MIME type of the payload.
+    pub fn set_mime_type(&mut self, mime_type: &str) {
+        self.mime_type = mime_type.to_string();
+    }
+
+    /// Get the name of the file from which the payload was read.
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// Set the name of the file from which the payload was read.
+    pub fn set_filename(&mut self, filename: Option<String>) {
+        self.filename = filename;
+    }
+
+    /// Get the id that identifies the message among the others in the same PNG file.
+    pub fn id(&self) -> Option<u32> {
+        self.id
+    }
+
+    /// Set the id that identifies the message among the others in the same PNG file.
+    pub fn set_id(&mut self, id: Option<u32>) {
+        self.id = id;
+    }
+
+    /// Get the label that describes the message.
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// Set the label that describes the message.
+    pub fn set_label(&mut self, label: Option<String>) {
+        self.label = label;
+    }
+
+    /// Get the time at which the message was written, in seconds since the Unix epoch.
+    pub fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    /// Set the time at which the message was written, in seconds since the Unix epoch.
+    pub fn set_timestamp(&mut self, timestamp: Option<u64>) {
+        self.timestamp = timestamp;
+    }
+
+    /// Get the algorithm that was used to compress the payload.
+    pub fn compression(&self) -> Option<Compression> {
+        self.compression.map(|(compression, _)| compression)
+    }
+
+    /// Get the size of the payload before it was compressed.
+    pub fn original_size(&self) -> usize {
+        match self.compression {
+            Some((_, size)) => size as usize,
+            None => self.payload.len(),
+        }
+    }
+
+    /// Compress the payload with the given algorithm and level, or the default level of the
+    /// algorithm if none is given. Nothing is done if the payload is already compressed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use pmsg::{Compression, Envelope};
+    /// # use std::convert::TryFrom;
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    ///     let mut envelope = Envelope::new(vec![0; 4096]);
+    ///     envelope.compress(Compression::Deflate, Some(9))?;
+    ///     assert!(envelope.is_compressed());
+    ///     assert!(envelope.payload().len() < 4096);
+    ///
+    ///     let mut parsed = Envelope::try_from(envelope.as_bytes()?.as_ref())?;
+    ///     parsed.decompress(1 << 20)?;
+    ///     assert!(!parsed.is_compressed());
+    ///     assert_eq!(vec![0; 4096], parsed.payload());
+    ///     Ok(())
+    /// # }
+    /// ```
+    pub fn compress(&mut self, compression: Compression, level: Option<u32>) -> Result<()> {
+        if self.is_compressed() {
+            return Ok(());
+        }
+        let original_size = u32::try_from(self.payload.len())?;
+        self.payload = compression.compress(&self.payload, level)?;
+        self.compression = Some((compression, original_size));
+        self.flags |= Self::FLAG_COMPRESSED;
+        Ok(())
+    }
+
+    /// Decompress the payload, nothing is done if the payload is not compressed. An error is
+    /// returned if the payload would be larger than `limit` bytes once decompressed.
+    pub fn decompress(&mut self, limit: usize) -> Result<()> {
+        let (compression, original_size) = match self.compression {
+            Some(compression) => compression,
+            None => return Ok(()),
+        };
+        if original_size as usize > limit {
+            return Err(Error::DecompressionLimitExceeded(limit));
+        }
+
+        let payload = compression.decompress(&self.payload, original_size as usize)?;
+        if payload.len() != original_size as usize {
+            return Err(Error::DecompressedSizeMismatch {
+                expected: original_size as usize,
+                actual: payload.len(),
+            });
+        }
+        self.payload = payload;
+        self.compression = None;
+        self.flags &= !Self::FLAG_COMPRESSED;
+        Ok(())
+    }
+
+    /// Get the hidden bytes.
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Consume the envelope and return the hidden bytes.
+    pub fn into_payload(self) -> Vec<u8> {
+        self.payload
+    }
+
+    /// Get the CRC32 checksum of the payload.
+    pub fn checksum(&self) -> u32 {
+        crc32::checksum_ieee(&self.payload)
+    }
+
+    /// Get the whole envelope in bytes. An error is returned if the header fields or the payload
+    /// are too long for their length fields.
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        let mut fields = Vec::new();
+        write_text_field(&mut fields, FIELD_MIME_TYPE, &self.mime_type)?;
+        if let Some(filename) = &self.filename {
+            write_text_field(&mut fields, FIELD_FILENAME, filename)?;
+        }
+        if let Some((compression, original_size)) = self.compression {
+            let mut value = vec![compression.id()];
+            value.extend_from_slice(&original_size.to_be_bytes());
+            write_field(&mut fields, FIELD_COMPRESSION, &value)?;
+        }
+        if let Some(id) = self.id {
+            write_field(&mut fields, FIELD_ID, &id.to_be_bytes())?;
+        }
+        if let Some(label) = &self.label {
+            write_text_field(&mut fields, FIELD_LABEL, label)?;
+        }
+        if let Some(timestamp) = self.timestamp {
+            write_field(&mut fields, FIELD_TIMESTAMP, &timestamp.to_be_bytes())?;
+        }
+        let fields_length =
+            u16::try_from(fields.len()).map_err(|_| Error::InvalidEnvelopeHeader)?;
+        let payload_length =
+            u32::try_from(self.payload.len()).map_err(|_| Error::InvalidEnvelopeHeader)?;
+
+        let mut bytes = Vec::with_capacity(16 + fields.len() + self.payload.len());
+        bytes.extend_from_slice(&Self::MAGIC);
+        bytes.push(Self::VERSION);
+        bytes.push(self.flags);
+        bytes.extend_from_slice(&fields_length.to_be_bytes());
+        bytes.extend_from_slice(&fields);
+        bytes.extend_from_slice(&payload_length.to_be_bytes());
+        bytes.extend_from_slice(&self.checksum().to_be_bytes());
+        bytes.extend_from_slice(&self.payload);
+        Ok(bytes)
+    }
+}
+
+/// Append a text header field to the given buffer, values that are longer than
+/// `MAX_TEXT_FIELD_LENGTH` bytes are truncated at a character boundary.
+fn write_text_field(buf: &mut Vec<u8>, tag: u8, value: &str) -> Result<()> {
+    let mut end = value.len().min(MAX_TEXT_FIELD_LENGTH);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    write_field(buf, tag, &value.as_bytes()[..end])
+}
+
+/// Append a header field to the given buffer.
+fn write_field(buf: &mut Vec<u8>, tag: u8, value: &[u8]) -> Result<()> {
+    let length = u16::try_from(value.len()).map_err(|_| Error::InvalidEnvelopeHeader)?;
+    buf.push(tag);
+    buf.extend_from_slice(&length.to_be_bytes());
+    buf.extend_from_slice(value);
+    Ok(())
+}
+
+/// Check that the given number of bytes is left after the current position of the given reader,
+/// before anything is allocated for them.
+fn check_remaining(r: &Cursor<&[u8]>, length: usize) -> Result<()> {
+    let remaining = r.get_ref().len().saturating_sub(r.position() as usize);
+    if length > remaining {
+        return Err(Error::InvalidEnvelopeHeader);
+    }
+    Ok(())
+}
+
+impl std::fmt::Display for Envelope {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.payload))
+    }
+}
+
+impl std::convert::TryFrom<&[u8]> for Envelope {
+    type Error = Error;
+
+    fn try_from(raw: &[u8]) -> Result<Self> {
+        let mut r = Cursor::new(raw);
+
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if magic != Self::MAGIC {
+            return Err(Self::Error::InvalidEnvelopeHeader);
+        }
+
+        let mut buf = [0u8; 2];
+        r.read_exact(&mut buf)?;
+        let (version, flags) = (buf[0], buf[1]);
+        if version != Self::VERSION {
+            return Err(Self::Error::UnsupportedEnvelopeVersion(version));
+        }
+        if flags & !Self::KNOWN_FLAGS != 0 {
+            return Err(Self::Error::InvalidEnvelopeHeader);
+        }
+
+        // parse header fields
+        r.read_exact(&mut buf)?;
+        let fields_length = u16::from_be_bytes(buf).into();
+        check_remaining(&r, fields_length)?;
+        let mut fields = vec![0u8; fields_length];
+        r.read_exact(&mut fields)?;
+
+        let mut mime_type = None;
+        let mut filename = None;
+        let mut compression = None;
+        let mut id = None;
+        let mut label = None;
+        let mut timestamp = None;
+        let mut fr = Cursor::new(fields.as_slice());
+        while fr.position() < fields.len().try_into()? {
+            let mut tag = [0u8; 1];
+            fr.read_exact(&mut tag)?;
+            fr.read_exact(&mut buf)?;
+            let value_length = u16::from_be_bytes(buf).into();
+            check_remaining(&fr, value_length)?;
+            let mut value = vec![0u8; value_length];
+            fr.read_exact(&mut value)?;
+            match tag[0] {
+                FIELD_MIME_TYPE => mime_type = Some(String::from_utf8(value)?),
+                FIELD_FILENAME => filename = Some(String::from_utf8(value)?),
+                FIELD_COMPRESSION => {
+                    if value.len() != 5 {
+                        return Err(Self::Error::InvalidEnvelopeHeader);
+                    }
+                    let original_size = u32::from_be_bytes(value[1..].try_into()?);
+                    compression = Some((Compression::from_id(value[0])?, original_size));
+                }
+                FIELD_ID => {
+                    if value.len() != 4 {
+                        return Err(Self::Error::InvalidEnvelopeHeader);
+                    }
+                    id = Some(u32::from_be_bytes(value.as_slice().try_into()?));
+                }
+                FIELD_LABEL => label = Some(String::from_utf8(value)?),
+                FIELD_TIMESTAMP => {
+                    if value.len() != 8 {
+                        return Err(Self::Error::InvalidEnvelopeHeader);
+                    }
+                    timestamp = Some(u64::from_be_bytes(value.as_slice().try_into()?));
+                }
+                _ => {}
+            }
+        }
+        if compression.is_some() != (flags & Self::FLAG_COMPRESSED != 0) {
+            return Err(Self::Error::InvalidEnvelopeHeader);
+        }
+
+        // parse and check payload
+        let mut buf = [0u8; 4];
+        r.read_exact(&mut buf)?;
+        let payload_length = u32::from_be_bytes(buf).try_into()?;
+        r.read_exact(&mut buf)?;
+        let checksum = u32::from_be_bytes(buf);
+        check_remaining(&r, payload_length)?;
+        let mut payload = vec![0u8; payload_length];
+        r.read_exact(&mut payload)?;
+        if crc32::checksum_ieee(&payload) != checksum {
+            return Err(Self::Error::InvalidEnvelopeChecksum);
+        }
+
+        Ok(Self {
+            flags,
+            mime_type: mime_type.unwrap_or_else(|| Self::DEFAULT_MIME_TYPE.to_string()),
+            filename,
+            compression,
+            id,
+            label,
+            timestamp,
+            payload,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    fn testing_envelope() -> Envelope {
+        let mut envelope = Envelope::from_text("This is where your secret message will be!");
+        envelope.set_filename(Some("secret.txt".to_string()));
+        envelope
+    }
+
+    #[test]
+    fn test_envelope_roundtrip() {
+        let envelope = testing_envelope();
+        let parsed = Envelope::try_from(envelope.as_bytes().unwrap().as_ref()).unwrap();
+        assert_eq!(envelope, parsed);
+    }
+
+    #[test]
+    fn test_envelope_layout() {
+        let bytes = Envelope::new(b"data".to_vec()).as_bytes().unwrap();
+        assert_eq!(&bytes[..4], b"PMSG");
+        assert_eq!(bytes[4], Envelope::VERSION);
+        assert_eq!(bytes[5], 0);
+        assert_eq!(&bytes[bytes.len() - 4..], b"data");
+    }
+
+    #[test]
+    fn test_envelope_flags() {
+        let mut envelope = testing_envelope();
+        envelope.set_flags(Envelope::FLAG_ENCRYPTED | Envelope::FLAG_SIGNED | 0x80);
+        assert!(!envelope.is_compressed());
+        assert!(envelope.is_encrypted());
+        assert!(envelope.is_signed());
+
+        let parsed = Envelope::try_from(envelope.as_bytes().unwrap().as_ref()).unwrap();
+        assert_eq!(
+            parsed.flags(),
+            Envelope::FLAG_ENCRYPTED | Envelope::FLAG_SIGNED
+        );
+    }
+
+    #[test]
+    fn test_envelope_unknown_field_is_skipped() {
+        let mut bytes = testing_envelope().as_bytes().unwrap();
+        let fields_len = u16::from_be_bytes([bytes[6], bytes[7]]);
+        bytes[6..8].copy_from_slice(&(fields_len + 4).to_be_bytes());
+        for (i, b) in [200, 0, 1, 42].iter().enumerate() {
+            bytes.insert(8 + i, *b);
+        }
+
+        let parsed = Envelope::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(testing_envelope(), parsed);
+    }
+
+    #[test]
+    fn test_envelope_message_fields() {
+        let mut envelope = testing_envelope();
+        envelope.set_id(Some(0xdead_beef));
+        envelope.set_label(Some("note".to_string()));
+        envelope.set_timestamp(Some(1_700_000_000));
+
+        let parsed = Envelope::try_from(envelope.as_bytes().unwrap().as_ref()).unwrap();
+        assert_eq!(parsed.id(), Some(0xdead_beef));
+        assert_eq!(parsed.label(), Some("note"));
+        assert_eq!(parsed.timestamp(), Some(1_700_000_000));
+        assert_eq!(envelope, parsed);
+    }
+
+    #[test]
+    fn test_envelope_compression() {
+        let mut envelope = testing_envelope();
+        envelope.compress(Compression::Brotli, None).unwrap();
+        assert_eq!(envelope.compression(), Some(Compression::Brotli));
+        assert_eq!(envelope.original_size(), 42);
+
+        let mut parsed = Envelope::try_from(envelope.as_bytes().unwrap().as_ref()).unwrap();
+        assert_eq!(envelope, parsed);
+        assert!(parsed.decompress(41).is_err());
+        parsed.decompress(42).unwrap();
+        assert_eq!(testing_envelope(), parsed);
+    }
+
+    #[test]
+    fn test_envelope_decompressed_size_mismatch() {
+        let mut envelope = testing_envelope();
+        envelope.compress(Compression::Deflate, None).unwrap();
+        envelope.compression = Some((Compression::Deflate, 50));
+        let err = envelope.decompress(100).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::DecompressedSizeMismatch {
+                expected: 50,
+                actual: 42
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "decompressed data has 42 bytes instead of 50"
+        );
+    }
+
+    #[test]
+    fn test_envelope_compressed_flag_without_field() {
+        let mut bytes = testing_envelope().as_bytes().unwrap();
+        bytes[5] = Envelope::FLAG_COMPRESSED;
+        assert!(Envelope::try_from(bytes.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_envelope_invalid_checksum() {
+        let mut bytes = testing_envelope().as_bytes().unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(Envelope::try_from(bytes.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_envelope_unsupported_version() {
+        let mut bytes = testing_envelope().as_bytes().unwrap();
+        bytes[4] = 2;
+        assert!(Envelope::try_from(bytes.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_envelope_truncated() {
+        let bytes = testing_envelope().as_bytes().unwrap();
+        assert!(Envelope::try_from(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_envelope_oversized_lengths() {
+        let bytes = testing_envelope().as_bytes().unwrap();
+        let fields_length = u16::from_be_bytes([bytes[6], bytes[7]]) as usize;
+        let payload_length = 8 + fields_length;
+
+        // a payload length larger than the data is rejected before anything is allocated
+        let mut oversized = bytes.clone();
+        oversized[payload_length..payload_length + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            Envelope::try_from(oversized.as_ref()),
+            Err(Error::InvalidEnvelopeHeader)
+        ));
+
+        let mut oversized = bytes[..8].to_vec();
+        oversized[6..8].copy_from_slice(&u16::MAX.to_be_bytes());
+        assert!(matches!(
+            Envelope::try_from(oversized.as_ref()),
+            Err(Error::InvalidEnvelopeHeader)
+        ));
+
+        // a field value that is longer than the header fields
+        let mut oversized = bytes;
+        oversized[9..11].copy_from_slice(&u16::MAX.to_be_bytes());
+        assert!(matches!(
+            Envelope::try_from(oversized.as_ref()),
+            Err(Error::InvalidEnvelopeHeader)
+        ));
+    }
+
+    #[test]
+    fn test_envelope_field_wrong_length() {
+        let mut envelope = Envelope::new(b"data".to_vec());
+        envelope.set_id(Some(0x01020304));
+        let bytes = envelope.as_bytes().unwrap();
+        let start = bytes.windows(4).position(|w| w == [1, 2, 3, 4]).unwrap();
+        assert_eq!(bytes[start - 3], FIELD_ID);
+
+        // an id field of 3 bytes instead of 4
+        let mut short = bytes[..start - 2].to_vec();
+        short.extend_from_slice(&3u16.to_be_bytes());
+        short.extend_from_slice(&bytes[start..start + 3]);
+        short.extend_from_slice(&bytes[start + 4..]);
+        let fields_length = u16::from_be_bytes([short[6], short[7]]) - 1;
+        short[6..8].copy_from_slice(&fields_length.to_be_bytes());
+        assert!(matches!(
+            Envelope::try_from(short.as_ref()),
+            Err(Error::InvalidEnvelopeHeader)
+        ));
+    }
+
+    #[test]
+    fn test_envelope_decode_legacy() {
+        let envelope = Envelope::decode(b"This is where your secret message will be!").unwrap();
+        assert_eq!(envelope.mime_type(), Envelope::TEXT_MIME_TYPE);
+        assert_eq!(envelope.filename(), None);
+        assert_eq!(
+            envelope.to_string(),
+            "This is where your secret message will be!"
+        );
+    }
+}
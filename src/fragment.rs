@@ -0,0 +1,330 @@
+use crate::{Error, Result};
+use std::collections::hash_map::RandomState;
+use std::convert::TryFrom;
+use std::hash::BuildHasher;
+use std::io::{Cursor, Read};
+use std::time::SystemTime;
+
+/// Fragment holds one numbered piece of a payload that was split across multiple chunks.
+///
+/// All integers are stored in big-endian order and the fragment is laid out as follows:
+///
+/// | Field      | Size     | Description                                     |
+/// |------------|----------|-------------------------------------------------|
+/// | magic      | 4        | always `PMSF`                                   |
+/// | version    | 1        | version of the format, currently `1`            |
+/// | payload id | 4        | identifier shared by all pieces of a payload    |
+/// | index      | 4        | position of the piece, starting from zero       |
+/// | total      | 4        | number of pieces the payload was split into     |
+/// | data       | variable | the bytes of the piece                          |
+///
+/// Payloads are reassembled by concatenating the data of all the pieces that have the same
+/// payload id in the order of their indices, which allows one PNG file to carry several
+/// independent payloads.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::Fragment;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let fragments = Fragment::split(42, b"THE HIDDEN DATA", 4);
+///     assert_eq!(4, fragments.len());
+///
+///     let pieces: Vec<Vec<u8>> = fragments.iter().rev().map(|f| f.as_bytes()).collect();
+///     let payloads = Fragment::reassemble(&pieces)?;
+///     assert_eq!(vec![b"THE HIDDEN DATA".to_vec()], payloads);
+///     Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    id: u32,
+    index: u32,
+    total: u32,
+    data: Vec<u8>,
+}
+
+impl Fragment {
+    pub const MAGIC: [u8; 4] = *b"PMSF";
+    pub const VERSION: u8 = 1;
+    pub const HEADER_LENGTH: usize = 17;
+
+    /// Create a new fragment that is the piece at the given index of the payload with the given
+    /// id.
+    pub fn new(id: u32, index: u32, total: u32, data: Vec<u8>) -> Self {
+        Self {
+            id,
+            index,
+            total,
+            data,
+        }
+    }
+
+    /// Split the given payload into pieces which contain at most `fragment_size` bytes each.
+    pub fn split(id: u32, payload: &[u8], fragment_size: usize) -> Vec<Self> {
+        let fragment_size = fragment_size.max(1);
+        let total = payload.len().div_ceil(fragment_size).max(1) as u32;
+        if payload.is_empty() {
+            return vec![Self::new(id, 0, total, Vec::new())];
+        }
+        payload
+            .chunks(fragment_size)
+            .enumerate()
+            .map(|(index, data)| Self::new(id, index as u32, total, data.to_vec()))
+            .collect()
+    }
+
+    /// Group the given list of chunk data into payloads. Data that is not a fragment is returned
+    /// unchanged, while fragments are reassembled into the payload they were split from. The
+    /// payloads are returned in the order in which their first piece appears.
+    ///
+    /// An error is returned if a piece of a payload is missing or is given more than once.
+    pub fn reassemble<T: AsRef<[u8]>>(data: &[T]) -> Result<Vec<Vec<u8>>> {
+        enum Piece {
+            Whole(Vec<u8>),
+            Fragments(u32, Vec<Fragment>),
+        }
+
+        let mut pieces: Vec<Piece> = Vec::new();
+        for d in data {
+            let d = d.as_ref();
+            if !Self::is_fragment(d) {
+                pieces.push(Piece::Whole(d.to_vec()));
+                continue;
+            }
+
+            let fragment = Self::try_from(d)?;
+            let group = pieces.iter_mut().find_map(|p| match p {
+                Piece::Fragments(id, fragments) if *id == fragment.id => Some(fragments),
+                _ => None,
+            });
+            match group {
+                Some(fragments) => fragments.push(fragment),
+                None => pieces.push(Piece::Fragments(fragment.id, vec![fragment])),
+            }
+        }
+
+        pieces
+            .into_iter()
+            .map(|p| match p {
+                Piece::Whole(d) => Ok(d),
+                Piece::Fragments(id, fragments) => Self::join(id, fragments),
+            })
+            .collect()
+    }
+
+    /// Concatenate the data of the given pieces of the payload with the given id.
+    fn join(id: u32, mut fragments: Vec<Fragment>) -> Result<Vec<u8>> {
+        fragments.sort_by_key(|f| f.index);
+        let total = fragments.iter().map(|f| f.total).max().unwrap_or(0);
+        if fragments.iter().any(|f| f.total != total) {
+            return Err(Error::InconsistentFragments { id });
+        }
+
+        let mut payload = Vec::new();
+        let mut expected = 0;
+        for f in fragments {
+            if f.index < expected {
+                return Err(Error::DuplicateFragment { id, index: f.index });
+            }
+            if f.index > expected {
+                return Err(Error::MissingFragment {
+                    id,
+                    index: expected,
+                });
+            }
+            payload.extend_from_slice(&f.data);
+            expected += 1;
+        }
+        if expected < total {
+            return Err(Error::MissingFragment {
+                id,
+                index: expected,
+            });
+        }
+        Ok(payload)
+    }
+
+    /// Generate a new payload id. Ids are not guaranteed to be unique, callers should check that
+    /// the id is not used by other payloads in the same PNG file.
+    pub fn generate_id() -> u32 {
+        RandomState::new().hash_one(SystemTime::now()) as u32
+    }
+
+    /// Check whether the given data starts with the magic bytes of a fragment.
+    pub fn is_fragment(data: &[u8]) -> bool {
+        data.starts_with(&Self::MAGIC)
+    }
+
+    /// Get the id of the payload to which the fragment belongs.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Get the position of the fragment in the payload.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Get the number of fragments the payload was split into.
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    /// Get the bytes of the fragment.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Get the whole fragment in bytes.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::HEADER_LENGTH + self.data.len());
+        bytes.extend_from_slice(&Self::MAGIC);
+        bytes.push(Self::VERSION);
+        bytes.extend_from_slice(&self.id.to_be_bytes());
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+        bytes.extend_from_slice(&self.total.to_be_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+}
+
+impl std::convert::TryFrom<&[u8]> for Fragment {
+    type Error = Error;
+
+    fn try_from(raw: &[u8]) -> Result<Self> {
+        let mut r = Cursor::new(raw);
+
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if magic != Self::MAGIC {
+            return Err(Self::Error::InvalidFragmentHeader);
+        }
+
+        let mut version = [0u8; 1];
+        r.read_exact(&mut version)?;
+        if version[0] != Self::VERSION {
+            return Err(Self::Error::InvalidFragmentHeader);
+        }
+
+        let mut buf = [0u8; 4];
+        r.read_exact(&mut buf)?;
+        let id = u32::from_be_bytes(buf);
+        r.read_exact(&mut buf)?;
+        let index = u32::from_be_bytes(buf);
+        r.read_exact(&mut buf)?;
+        let total = u32::from_be_bytes(buf);
+        if index >= total {
+            return Err(Self::Error::InvalidFragmentHeader);
+        }
+
+        Ok(Self::new(
+            id,
+            index,
+            total,
+            raw[Self::HEADER_LENGTH..].to_vec(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_payload() -> Vec<u8> {
+        b"This is where your secret message will be!".to_vec()
+    }
+
+    fn testing_fragments(id: u32) -> Vec<Vec<u8>> {
+        Fragment::split(id, &testing_payload(), 10)
+            .iter()
+            .map(|f| f.as_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_split() {
+        let fragments = Fragment::split(7, &testing_payload(), 10);
+        assert_eq!(fragments.len(), 5);
+        assert!(fragments.iter().all(|f| f.id() == 7 && f.total() == 5));
+        assert_eq!(fragments[4].data(), b"e!");
+    }
+
+    #[test]
+    fn test_split_empty() {
+        let fragments = Fragment::split(7, &[], 10);
+        assert_eq!(fragments.len(), 1);
+        assert_eq!(
+            Fragment::reassemble(&[fragments[0].as_bytes()]).unwrap(),
+            vec![Vec::<u8>::new()]
+        );
+    }
+
+    #[test]
+    fn test_fragment_roundtrip() {
+        let fragment = Fragment::new(1, 2, 3, b"data".to_vec());
+        let parsed = Fragment::try_from(fragment.as_bytes().as_ref()).unwrap();
+        assert_eq!(fragment, parsed);
+    }
+
+    #[test]
+    fn test_reassemble_out_of_order() {
+        let mut data = testing_fragments(1);
+        data.reverse();
+        assert_eq!(
+            Fragment::reassemble(&data).unwrap(),
+            vec![testing_payload()]
+        );
+    }
+
+    #[test]
+    fn test_reassemble_multiple_payloads() {
+        let mut data = Vec::new();
+        for (a, b) in testing_fragments(1).into_iter().zip(testing_fragments(2)) {
+            data.push(a);
+            data.push(b);
+        }
+        data.insert(3, b"plain message".to_vec());
+
+        let payloads = Fragment::reassemble(&data).unwrap();
+        assert_eq!(payloads.len(), 3);
+        assert_eq!(payloads[0], testing_payload());
+        assert_eq!(payloads[1], testing_payload());
+        assert_eq!(payloads[2], b"plain message");
+    }
+
+    #[test]
+    fn test_reassemble_missing_fragment() {
+        let mut data = testing_fragments(1);
+        data.remove(2);
+        match Fragment::reassemble(&data) {
+            Err(Error::MissingFragment { id: 1, index: 2 }) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        let mut data = testing_fragments(1);
+        data.pop();
+        match Fragment::reassemble(&data) {
+            Err(Error::MissingFragment { id: 1, index: 4 }) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_reassemble_duplicate_fragment() {
+        let mut data = testing_fragments(1);
+        data.push(data[1].clone());
+        match Fragment::reassemble(&data) {
+            Err(Error::DuplicateFragment { id: 1, index: 1 }) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_invalid_fragment_index() {
+        let bytes = Fragment::new(1, 3, 3, Vec::new()).as_bytes();
+        assert!(Fragment::try_from(bytes.as_ref()).is_err());
+    }
+}
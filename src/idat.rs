@@ -0,0 +1,246 @@
+use crate::{ChunkType, Error, Image, Png, Result};
+use std::convert::TryFrom;
+use std::io::{Cursor, Read};
+
+/// IdatPayload holds data that is hidden after the end of the zlib stream of the image data,
+/// instead of inside a chunk of its own.
+///
+/// Decoders stop reading the image data at the Adler-32 checksum that ends the zlib stream, so
+/// anything that follows it is never displayed, while the PNG file keeps the usual sequence of
+/// "IHDR", "IDAT" and "IEND" chunks. The hidden payloads are stored one after another and each of
+/// them is laid out as follows:
+///
+/// | Field      | Size     | Description                                     |
+/// |------------|----------|-------------------------------------------------|
+/// | chunk type | 4        | the chunk type code that labels the payload     |
+/// | length     | 4        | number of bytes of the data, in big-endian      |
+/// | data       | variable | the hidden bytes                                |
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::{Chunk, ChunkType, IdatPayload, Ihdr, Image, Png};
+/// # use std::convert::TryFrom;
+/// # use std::str::FromStr;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let ihdr = Chunk::new(ChunkType::from_str("IHDR")?, vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0])?;
+///     let idat = Image::new(Ihdr::try_from(&ihdr)?, vec![0])?.encode()?;
+///     let png = Png::from_chunks(vec![
+///         ihdr,
+///         Chunk::new(ChunkType::from_str("IDAT")?, idat)?,
+///         Chunk::new(ChunkType::from_str("IEND")?, Vec::new())?,
+///     ]);
+///
+///     let payload = IdatPayload::new(ChunkType::from_str("ruSt")?, b"THE HIDDEN DATA".to_vec());
+///     let png = IdatPayload::append(&png, std::slice::from_ref(&payload))?;
+///     assert_eq!(vec![payload], IdatPayload::read_all(&png)?);
+///     Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdatPayload {
+    chunk_type: ChunkType,
+    data: Vec<u8>,
+}
+
+impl IdatPayload {
+    /// Create a new payload with the given chunk type code and data.
+    pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Self {
+        Self { chunk_type, data }
+    }
+
+    /// Get the chunk type code that labels the payload.
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    /// Get the hidden bytes.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Get the whole payload in bytes.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + self.data.len());
+        bytes.extend_from_slice(&self.chunk_type.bytes());
+        bytes.extend_from_slice(&(self.data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// Get all the payloads that are hidden in the image data of the given PNG image.
+    ///
+    /// An error is returned if the zlib stream is corrupted or if it is followed by data that
+    /// does not consist of payloads.
+    pub fn read_all(png: &Png) -> Result<Vec<Self>> {
+        let compressed = Image::compressed_data(png);
+        if compressed.is_empty() {
+            return Ok(Vec::new());
+        }
+        let length = Image::stream_length(&compressed).ok_or(Error::InvalidImageData)?;
+        let hidden = &compressed[length..];
+
+        let mut r = Cursor::new(hidden);
+        let mut payloads = Vec::new();
+        while (r.position() as usize) < hidden.len() {
+            let mut chunk_type = [0u8; 4];
+            let mut length = [0u8; 4];
+            r.read_exact(&mut chunk_type)
+                .and_then(|_| r.read_exact(&mut length))
+                .map_err(|_| Error::InvalidImageData)?;
+            // the length is checked before anything is allocated for it
+            let length = u32::from_be_bytes(length) as usize;
+            if length > hidden.len() - r.position() as usize {
+                return Err(Error::InvalidImageData);
+            }
+            let mut data = vec![0u8; length];
+            r.read_exact(&mut data)
+                .map_err(|_| Error::InvalidImageData)?;
+            payloads.push(Self::new(ChunkType::try_from(chunk_type)?, data));
+        }
+        Ok(payloads)
+    }
+
+    /// Hide the given payloads after the ones that are already hidden in the image data of the
+    /// given PNG image.
+    pub fn append(png: &Png, payloads: &[Self]) -> Result<Png> {
+        let mut all = Self::read_all(png)?;
+        all.extend_from_slice(payloads);
+        Self::write_all(png, &all)
+    }
+
+    /// Remove the payloads with the given chunk type code from the image data of the given PNG
+    /// image. The new PNG image is returned together with the removed payloads. The "IDAT" chunks
+    /// are left as they are if nothing is removed.
+    pub fn remove(png: &Png, chunk_type: &ChunkType) -> Result<(Png, Vec<Self>)> {
+        let (removed, kept): (Vec<Self>, Vec<Self>) = Self::read_all(png)?
+            .into_iter()
+            .partition(|p| &p.chunk_type == chunk_type);
+        if removed.is_empty() {
+            return Ok((png.clone(), removed));
+        }
+        Ok((Self::write_all(png, &kept)?, removed))
+    }
+
+    /// Replace the payloads that are hidden in the image data of the given PNG image with the
+    /// given ones.
+    pub fn write_all(png: &Png, payloads: &[Self]) -> Result<Png> {
+        let mut compressed = Image::compressed_data(png);
+        let length = Image::stream_length(&compressed).ok_or(Error::InvalidImageData)?;
+        compressed.truncate(length);
+        for payload in payloads {
+            compressed.extend_from_slice(&payload.as_bytes());
+        }
+        Image::with_compressed_data(png, &compressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chunk;
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        Png::try_from(&crate::png::tests::PNG_FILE[..]).unwrap()
+    }
+
+    fn testing_payload(chunk_type: &str, data: &[u8]) -> IdatPayload {
+        IdatPayload::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_append_and_read() {
+        let png = testing_png();
+        assert!(IdatPayload::read_all(&png).unwrap().is_empty());
+
+        let first = testing_payload("ruSt", b"first");
+        let second = testing_payload("ruSt", b"second");
+        let png = IdatPayload::append(&png, std::slice::from_ref(&first)).unwrap();
+        let png = IdatPayload::append(&png, std::slice::from_ref(&second)).unwrap();
+        assert_eq!(IdatPayload::read_all(&png).unwrap(), vec![first, second]);
+
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        let original: Vec<String> = testing_png()
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, original);
+        assert_eq!(
+            Image::from_png(&png).unwrap(),
+            Image::from_png(&testing_png()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_remove() {
+        let png = IdatPayload::append(
+            &testing_png(),
+            &[
+                testing_payload("ruSt", b"first"),
+                testing_payload("abCd", b"second"),
+            ],
+        )
+        .unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let (png, removed) = IdatPayload::remove(&png, &chunk_type).unwrap();
+        assert_eq!(removed, vec![testing_payload("ruSt", b"first")]);
+        assert_eq!(
+            IdatPayload::read_all(&png).unwrap(),
+            vec![testing_payload("abCd", b"second")]
+        );
+    }
+
+    #[test]
+    fn test_remove_nothing_keeps_chunks() {
+        let png = testing_png();
+        let compressed = Image::compressed_data(&png);
+        let data_type = ChunkType::try_from(Image::DATA_CHUNK_TYPE).unwrap();
+        let (first, second) = compressed.split_at(compressed.len() / 2);
+        let mut chunks: Vec<Chunk> = png
+            .chunks()
+            .iter()
+            .filter(|c| c.chunk_type() != &data_type)
+            .cloned()
+            .collect();
+        let end = chunks.len() - 1;
+        chunks.insert(end, Chunk::new(data_type.clone(), second.to_vec()).unwrap());
+        chunks.insert(end, Chunk::new(data_type, first.to_vec()).unwrap());
+        let png = Png::from_chunks(chunks);
+
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let (stripped, removed) = IdatPayload::remove(&png, &chunk_type).unwrap();
+        assert!(removed.is_empty());
+        assert_eq!(stripped.as_bytes(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_oversized_payload_length() {
+        let png = testing_png();
+        let mut compressed = Image::compressed_data(&png);
+        compressed.extend_from_slice(b"ruSt");
+        compressed.extend_from_slice(&u32::MAX.to_be_bytes());
+        compressed.extend_from_slice(b"short");
+        let png = Image::with_compressed_data(&png, &compressed).unwrap();
+        assert!(matches!(
+            IdatPayload::read_all(&png),
+            Err(Error::InvalidImageData)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_hidden_data() {
+        let png = testing_png();
+        let mut compressed = Image::compressed_data(&png);
+        compressed.extend_from_slice(b"garbage");
+        let png = Image::with_compressed_data(&png, &compressed).unwrap();
+        assert!(IdatPayload::read_all(&png).is_err());
+    }
+}
@@ -0,0 +1,726 @@
+use crate::{Chunk, ChunkType, Error, Png, Result};
+use std::convert::{TryFrom, TryInto};
+use std::io::{Read, Write};
+
+/// The image header ("IHDR" chunk) which describes the dimensions and the pixel format of a PNG
+/// image ([IHDR Image header](http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.IHDR)).
+///
+/// Only headers with a valid combination of color type and bit depth can be parsed.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::{Chunk, ChunkType, Ihdr};
+/// # use std::convert::TryFrom;
+/// # use std::str::FromStr;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let data = vec![0, 0, 0, 50, 0, 0, 0, 30, 8, 6, 0, 0, 0];
+///     let chunk = Chunk::new(ChunkType::from_str("IHDR")?, data)?;
+///     let ihdr = Ihdr::try_from(&chunk)?;
+///     assert_eq!((50, 30), (ihdr.width(), ihdr.height()));
+///     assert_eq!(4, ihdr.channels());
+///     assert_eq!(200, ihdr.row_length(ihdr.width()));
+///     Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: u8,
+    interlace_method: u8,
+}
+
+impl Ihdr {
+    pub const COLOR_GRAYSCALE: u8 = 0;
+    pub const COLOR_RGB: u8 = 2;
+    pub const COLOR_INDEXED: u8 = 3;
+    pub const COLOR_GRAYSCALE_ALPHA: u8 = 4;
+    pub const COLOR_RGBA: u8 = 6;
+
+    /// Get the width of the image in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of the image in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the number of bits per sample, or per palette index for indexed-color images.
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    /// Get the color type of the image.
+    pub fn color_type(&self) -> u8 {
+        self.color_type
+    }
+
+    /// Check whether the image uses the Adam7 interlace method.
+    pub fn is_interlaced(&self) -> bool {
+        self.interlace_method == 1
+    }
+
+    /// Get the number of samples per pixel.
+    pub fn channels(&self) -> usize {
+        match self.color_type {
+            Self::COLOR_RGB => 3,
+            Self::COLOR_GRAYSCALE_ALPHA => 2,
+            Self::COLOR_RGBA => 4,
+            _ => 1,
+        }
+    }
+
+    /// Get the number of bytes of a row with the given number of pixels, without the filter
+    /// type byte.
+    pub fn row_length(&self, width: u32) -> usize {
+        (width as usize * self.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+
+    /// Get the number of bytes of the image data after decompression, which includes the filter
+    /// type byte of every row. An error is returned if the length does not fit in memory.
+    pub fn filtered_length(&self) -> Result<usize> {
+        self.passes()
+            .iter()
+            .filter(|&&(w, h)| w > 0 && h > 0)
+            .try_fold(0usize, |total, &(w, h)| {
+                (self.row_length(w) + 1)
+                    .checked_mul(h as usize)
+                    .and_then(|length| total.checked_add(length))
+            })
+            .ok_or(Error::InvalidImageData)
+    }
+
+    /// Get the number of bytes of the image data after the filters are reversed, without the
+    /// filter type bytes. An error is returned if the length does not fit in memory.
+    pub fn data_length(&self) -> Result<usize> {
+        self.passes()
+            .iter()
+            .try_fold(0usize, |total, &(w, h)| {
+                self.row_length(w)
+                    .checked_mul(h as usize)
+                    .and_then(|length| total.checked_add(length))
+            })
+            .ok_or(Error::InvalidImageData)
+    }
+
+    /// Get the distance in bytes between a byte and the corresponding byte of the previous
+    /// pixel, which is used by the filters.
+    fn filter_distance(&self) -> usize {
+        (self.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+
+    /// Get the dimensions of the sub-images in which the pixels are stored, there is only one
+    /// sub-image if the image is not interlaced and seven otherwise.
+    pub fn passes(&self) -> Vec<(u32, u32)> {
+        if !self.is_interlaced() {
+            return vec![(self.width, self.height)];
+        }
+        const ADAM7: [(u32, u32, u32, u32); 7] = [
+            (0, 0, 8, 8),
+            (4, 0, 8, 8),
+            (0, 4, 4, 8),
+            (2, 0, 4, 4),
+            (0, 2, 2, 4),
+            (1, 0, 2, 2),
+            (0, 1, 1, 2),
+        ];
+        ADAM7
+            .iter()
+            .map(|&(x0, y0, dx, dy)| {
+                let w = (self.width + dx - 1 - x0) / dx;
+                let h = (self.height + dy - 1 - y0) / dy;
+                if w == 0 || h == 0 {
+                    (0, 0)
+                } else {
+                    (w, h)
+                }
+            })
+            .collect()
+    }
+}
+
+impl std::convert::TryFrom<&Chunk> for Ihdr {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = chunk.data();
+        if chunk.chunk_type().bytes() != Png::START_CHUNK_TYPE || data.len() != 13 {
+            return Err(Error::InvalidImageHeader);
+        }
+
+        let ihdr = Self {
+            width: u32::from_be_bytes(data[0..4].try_into()?),
+            height: u32::from_be_bytes(data[4..8].try_into()?),
+            bit_depth: data[8],
+            color_type: data[9],
+            interlace_method: data[12],
+        };
+        let valid_depths: &[u8] = match ihdr.color_type {
+            Self::COLOR_GRAYSCALE => &[1, 2, 4, 8, 16],
+            Self::COLOR_INDEXED => &[1, 2, 4, 8],
+            Self::COLOR_RGB | Self::COLOR_GRAYSCALE_ALPHA | Self::COLOR_RGBA => &[8, 16],
+            _ => &[],
+        };
+        // the dimensions are limited to 2^31 - 1 by the specifications
+        if ihdr.width == 0
+            || ihdr.height == 0
+            || ihdr.width > i32::MAX as u32
+            || ihdr.height > i32::MAX as u32
+            || !valid_depths.contains(&ihdr.bit_depth)
+            || data[10] != 0
+            || data[11] != 0
+            || ihdr.interlace_method > 1
+        {
+            return Err(Error::InvalidImageHeader);
+        }
+        Ok(ihdr)
+    }
+}
+
+/// The decoded pixels of a PNG image.
+///
+/// The image data of all the "IDAT" chunks is decompressed and the filters of every row are
+/// reversed. Rows are kept in the order in which they are stored, so the rows of interlaced images
+/// are grouped by pass, and samples are kept in their stored bit depth.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    header: Ihdr,
+    data: Vec<u8>,
+}
+
+impl Image {
+    pub const DATA_CHUNK_TYPE: [u8; 4] = *b"IDAT";
+    /// The largest number of bytes that is stored in a single "IDAT" chunk when the image data is
+    /// encoded.
+    pub const DATA_CHUNK_SIZE: usize = 64 * 1024;
+
+    /// Create an image from the given header and unfiltered rows.
+    pub fn new(header: Ihdr, data: Vec<u8>) -> Result<Self> {
+        if data.len() != header.data_length()? {
+            return Err(Error::InvalidImageData);
+        }
+        Ok(Self { header, data })
+    }
+
+    /// Decode the pixels of the given PNG image.
+    pub fn from_png(png: &Png) -> Result<Self> {
+        let header = png
+            .chunks()
+            .first()
+            .ok_or(Error::InvalidImageHeader)
+            .and_then(Ihdr::try_from)?;
+        Self::decode(header, &Self::compressed_data(png))
+    }
+
+    /// Concatenate the data of all the "IDAT" chunks of the given PNG image, which is a single
+    /// zlib stream.
+    pub fn compressed_data(png: &Png) -> Vec<u8> {
+        png.chunks()
+            .iter()
+            .filter(|c| c.chunk_type().bytes() == Self::DATA_CHUNK_TYPE)
+            .flat_map(|c| c.data().iter().copied())
+            .collect()
+    }
+
+    /// Get the number of bytes at the beginning of the given data that belong to a zlib stream, or
+    /// `None` if the stream is corrupted or incomplete. Data after the Adler-32 checksum of the
+    /// stream is not counted.
+    pub fn stream_length(compressed: &[u8]) -> Option<usize> {
+        let mut decompress = flate2::Decompress::new(true);
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let (total_in, total_out) = (decompress.total_in(), decompress.total_out());
+            let status = decompress
+                .decompress(
+                    &compressed[total_in as usize..],
+                    &mut buf,
+                    flate2::FlushDecompress::None,
+                )
+                .ok()?;
+            if status == flate2::Status::StreamEnd {
+                return Some(decompress.total_in() as usize);
+            }
+            if decompress.total_in() == total_in && decompress.total_out() == total_out {
+                return None;
+            }
+        }
+    }
+
+    /// Decompress the given zlib stream of image data that is described by the given header. The
+    /// rows are returned with their filter type bytes and any data after the expected length of
+    /// the image data is ignored. The buffer grows with the data that is actually decompressed,
+    /// so a header with huge dimensions can not make it allocate more.
+    pub fn decompress(header: &Ihdr, compressed: &[u8]) -> Result<Vec<u8>> {
+        let filtered_length = header.filtered_length()?;
+        let mut filtered = Vec::new();
+        flate2::read::ZlibDecoder::new(compressed)
+            .take(filtered_length as u64)
+            .read_to_end(&mut filtered)
+            .map_err(|_| Error::InvalidImageData)?;
+        if filtered.len() != filtered_length {
+            return Err(Error::InvalidImageData);
+        }
+        Ok(filtered)
+    }
+
+    /// Decode the given zlib stream of image data that is described by the given header.
+    pub fn decode(header: Ihdr, compressed: &[u8]) -> Result<Self> {
+        let filtered = Self::decompress(&header, compressed)?;
+        let rows: Vec<(usize, u32)> = header
+            .passes()
+            .iter()
+            .filter(|&&(w, h)| w > 0 && h > 0)
+            .map(|&(w, h)| (header.row_length(w), h))
+            .collect();
+
+        let distance = header.filter_distance();
+        let mut data = Vec::with_capacity(filtered.len());
+        let mut pos = 0;
+        for (len, h) in rows {
+            let mut previous = vec![0u8; len];
+            for _ in 0..h {
+                let filter = filtered[pos];
+                let mut row = filtered[pos + 1..pos + 1 + len].to_vec();
+                unfilter(filter, &mut row, &previous, distance)?;
+                data.extend_from_slice(&row);
+                previous = row;
+                pos += len + 1;
+            }
+        }
+        Ok(Self { header, data })
+    }
+
+    /// Get the header of the image.
+    pub fn header(&self) -> &Ihdr {
+        &self.header
+    }
+
+    /// Get the unfiltered rows of the image without their filter type bytes.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Get the unfiltered rows of the image for modification.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Filter the rows of the image and compress them into a zlib stream with the best compression
+    /// level. Every row is filtered with the filter type that gives the smallest sum of absolute
+    /// differences, as recommended by the specifications.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let header = self.header;
+        let distance = header.filter_distance();
+        let mut filtered = Vec::with_capacity(self.data.len() + self.data.len() / 8);
+        let mut rest = self.data.as_slice();
+        for (w, h) in header.passes() {
+            let zeros = vec![0u8; header.row_length(w)];
+            let mut previous: &[u8] = &zeros;
+            for _ in 0..h {
+                let (row, tail) = rest.split_at(header.row_length(w));
+                rest = tail;
+                let (filter, bytes) = (0..5)
+                    .map(|f| (f, filter(f, row, previous, distance)))
+                    .min_by_key(|(_, bytes)| {
+                        bytes
+                            .iter()
+                            .map(|&b| (b as i8).unsigned_abs() as u64)
+                            .sum::<u64>()
+                    })
+                    .unwrap();
+                filtered.push(filter);
+                filtered.extend_from_slice(&bytes);
+                previous = row;
+            }
+        }
+
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(&filtered)?;
+        Ok(encoder.finish()?)
+    }
+
+    /// Replace the "IDAT" chunks of the given PNG image with the given zlib stream of image data.
+    /// The new image data is stored in "IDAT" chunks that take the place of the first original
+    /// one.
+    pub fn with_compressed_data(png: &Png, compressed: &[u8]) -> Result<Png> {
+        let data_type = ChunkType::try_from(Self::DATA_CHUNK_TYPE)?;
+        let data_chunks = compressed
+            .chunks(Self::DATA_CHUNK_SIZE)
+            .map(|data| Chunk::new(data_type.clone(), data.to_vec()))
+            .collect::<Result<Vec<Chunk>>>()?;
+
+        let first = png
+            .chunks()
+            .iter()
+            .position(|c| c.chunk_type() == &data_type);
+        let mut png = png.clone();
+        png.retain(|c| c.chunk_type() != &data_type)?;
+        if let Some(first) = first {
+            for (i, chunk) in data_chunks.into_iter().enumerate() {
+                png.insert_chunk(first + i, chunk)?;
+            }
+        }
+        Ok(png)
+    }
+
+    /// Encode the image and store it in place of the image data of the given PNG image.
+    pub fn to_png(&self, png: &Png) -> Result<Png> {
+        Self::with_compressed_data(png, &self.encode()?)
+    }
+
+    /// Get the samples of the image in the order in which they are stored. For indexed-color
+    /// images, the samples are the palette indices.
+    pub fn samples(&self) -> Vec<u16> {
+        let depth = self.header.bit_depth as usize;
+        let mut samples = Vec::new();
+        for (row, width) in self.rows() {
+            let count = width as usize * self.header.channels();
+            match depth {
+                16 => samples.extend(
+                    row.chunks(2)
+                        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                        .take(count),
+                ),
+                8 => samples.extend(row.iter().map(|&b| b as u16).take(count)),
+                _ => {
+                    let mask = (1u16 << depth) - 1;
+                    samples.extend((0..count).map(|i| {
+                        let bit = i * depth;
+                        let shift = 8 - depth - bit % 8;
+                        (row[bit / 8] as u16 >> shift) & mask
+                    }))
+                }
+            }
+        }
+        samples
+    }
+
+    /// Replace the samples of the image, which are given in the same order as they are returned by
+    /// `samples`. An error is returned if the number of samples does not match the image.
+    pub fn set_samples(&mut self, samples: &[u16]) -> Result<()> {
+        let header = self.header;
+        let depth = header.bit_depth as usize;
+        let widths: Vec<u32> = self.rows().map(|(_, w)| w).collect();
+        let count: usize = widths.iter().map(|&w| w as usize).sum::<usize>() * header.channels();
+        if samples.len() != count {
+            return Err(Error::InvalidImageData);
+        }
+
+        let mut data = Vec::with_capacity(self.data.len());
+        let mut samples = samples.iter();
+        for w in widths {
+            let count = w as usize * header.channels();
+            match depth {
+                16 => {
+                    for s in samples.by_ref().take(count) {
+                        data.extend_from_slice(&s.to_be_bytes());
+                    }
+                }
+                8 => data.extend(samples.by_ref().take(count).map(|&s| s as u8)),
+                _ => {
+                    let mut row = vec![0u8; header.row_length(w)];
+                    for (i, &s) in samples.by_ref().take(count).enumerate() {
+                        let bit = i * depth;
+                        let shift = 8 - depth - bit % 8;
+                        row[bit / 8] |= ((s & ((1 << depth) - 1)) << shift) as u8;
+                    }
+                    data.extend_from_slice(&row);
+                }
+            }
+        }
+        self.data = data;
+        Ok(())
+    }
+
+    /// Iterate over the unfiltered rows of the image together with their width in pixels.
+    fn rows(&self) -> impl Iterator<Item = (&[u8], u32)> {
+        let header = self.header;
+        let mut rest = self.data.as_slice();
+        header
+            .passes()
+            .into_iter()
+            .filter(|&(w, h)| w > 0 && h > 0)
+            .flat_map(|(w, h)| std::iter::repeat(w).take(h as usize))
+            .map(move |w| {
+                let (row, tail) = rest.split_at(header.row_length(w));
+                rest = tail;
+                (row, w)
+            })
+    }
+}
+
+/// Apply the filter of the given type to the given row.
+fn filter(filter: u8, row: &[u8], previous: &[u8], distance: usize) -> Vec<u8> {
+    (0..row.len())
+        .map(|i| {
+            let (left, upper_left) = if i >= distance {
+                (row[i - distance], previous[i - distance])
+            } else {
+                (0, 0)
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => previous[i],
+                3 => ((left as u16 + previous[i] as u16) / 2) as u8,
+                _ => paeth(left, previous[i], upper_left),
+            };
+            row[i].wrapping_sub(predicted)
+        })
+        .collect()
+}
+
+/// Reverse the filter of the given row in place.
+fn unfilter(filter: u8, row: &mut [u8], previous: &[u8], distance: usize) -> Result<()> {
+    match filter {
+        0 => {}
+        1 => {
+            for i in distance..row.len() {
+                row[i] = row[i].wrapping_add(row[i - distance]);
+            }
+        }
+        2 => {
+            for i in 0..row.len() {
+                row[i] = row[i].wrapping_add(previous[i]);
+            }
+        }
+        3 => {
+            for i in 0..row.len() {
+                let left = if i >= distance { row[i - distance] } else { 0 };
+                let average = ((left as u16 + previous[i] as u16) / 2) as u8;
+                row[i] = row[i].wrapping_add(average);
+            }
+        }
+        4 => {
+            for i in 0..row.len() {
+                let (left, upper_left) = if i >= distance {
+                    (row[i - distance], previous[i - distance])
+                } else {
+                    (0, 0)
+                };
+                row[i] = row[i].wrapping_add(paeth(left, previous[i], upper_left));
+            }
+        }
+        _ => return Err(Error::InvalidImageData),
+    }
+    Ok(())
+}
+
+/// The Paeth predictor as described by the specifications.
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+impl std::convert::TryFrom<&Png> for Image {
+    type Error = Error;
+
+    fn try_from(png: &Png) -> Result<Self> {
+        Self::from_png(png)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::ChunkType;
+    use std::io::Write;
+    use std::str::FromStr;
+
+    /// Build a PNG image from the given header fields and raw rows, each row is filtered with
+    /// the given filter type.
+    pub(crate) fn testing_png(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: u8,
+        rows: &[Vec<u8>],
+        filter: u8,
+    ) -> Png {
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+        let header = Ihdr::try_from(&chunk("IHDR", ihdr.clone())).unwrap();
+        let distance = header.filter_distance();
+
+        let mut filtered = Vec::new();
+        let mut previous = vec![0u8; rows[0].len()];
+        for row in rows {
+            filtered.push(filter);
+            filtered.extend(super::filter(filter, row, &previous, distance));
+            previous = row.clone();
+        }
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&filtered).unwrap();
+
+        Png::from_chunks(vec![
+            chunk("IHDR", ihdr),
+            chunk("IDAT", encoder.finish().unwrap()),
+            chunk("IEND", Vec::new()),
+        ])
+    }
+
+    pub(crate) fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data).unwrap()
+    }
+
+    fn testing_rows() -> Vec<Vec<u8>> {
+        (0..4u8)
+            .map(|y| {
+                (0..12u8)
+                    .map(|x| x.wrapping_mul(37) ^ y.wrapping_mul(91))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ihdr() {
+        let png = testing_png(4, 4, 8, 2, &testing_rows(), 0);
+        let ihdr = Ihdr::try_from(&png.chunks()[0]).unwrap();
+        assert_eq!(ihdr.width(), 4);
+        assert_eq!(ihdr.height(), 4);
+        assert_eq!(ihdr.channels(), 3);
+        assert_eq!(ihdr.row_length(4), 12);
+        assert!(!ihdr.is_interlaced());
+    }
+
+    #[test]
+    fn test_invalid_ihdr() {
+        let data = vec![0, 0, 0, 4, 0, 0, 0, 4, 4, 2, 0, 0, 0];
+        assert!(Ihdr::try_from(&chunk("IHDR", data)).is_err());
+        let data = vec![0, 0, 0, 0, 0, 0, 0, 4, 8, 2, 0, 0, 0];
+        assert!(Ihdr::try_from(&chunk("IHDR", data)).is_err());
+    }
+
+    #[test]
+    fn test_decode_filters() {
+        let rows = testing_rows();
+        let expected: Vec<u8> = rows.iter().flatten().copied().collect();
+        for filter in 0..5 {
+            let png = testing_png(4, 4, 8, 2, &rows, filter);
+            let image = Image::from_png(&png).unwrap();
+            assert_eq!(image.data(), expected.as_slice());
+            assert_eq!(image.samples().len(), 48);
+        }
+    }
+
+    #[test]
+    fn test_sub_byte_samples() {
+        let rows = vec![
+            vec![0b0001_1011, 0b1100_0000],
+            vec![0b1110_0100, 0b0100_0000],
+        ];
+        let png = testing_png(5, 2, 2, 3, &rows, 0);
+        let image = Image::from_png(&png).unwrap();
+        assert_eq!(image.samples(), vec![0, 1, 2, 3, 3, 3, 2, 1, 0, 1]);
+    }
+
+    #[test]
+    fn test_set_samples() {
+        for &(bit_depth, color_type) in [(2, 3), (8, 2), (16, 0)].iter() {
+            let data = vec![0, 0, 0, 5, 0, 0, 0, 3, bit_depth, color_type, 0, 0, 0];
+            let header = Ihdr::try_from(&chunk("IHDR", data)).unwrap();
+            let mut image = Image::new(header, vec![0; header.data_length().unwrap()]).unwrap();
+            let max = (1u32 << bit_depth) - 1;
+            let samples: Vec<u16> = (0..image.samples().len() as u32)
+                .map(|i| (i * 7 % (max + 1)) as u16)
+                .collect();
+            image.set_samples(&samples).unwrap();
+            assert_eq!(image.samples(), samples);
+            assert!(image.set_samples(&samples[1..]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_interlaced_passes() {
+        let data = vec![0, 0, 0, 5, 0, 0, 0, 3, 8, 0, 0, 0, 1];
+        let ihdr = Ihdr::try_from(&chunk("IHDR", data)).unwrap();
+        let passes = ihdr.passes();
+        assert_eq!(passes.len(), 7);
+        let pixels: u32 = passes.iter().map(|(w, h)| w * h).sum();
+        assert_eq!(pixels, 15);
+    }
+
+    #[test]
+    fn test_image_from_png_file() {
+        let png = Png::try_from(&crate::png::tests::PNG_FILE[..]).unwrap();
+        let image = Image::from_png(&png).unwrap();
+        assert_eq!(image.data().len(), 50 * 50 * 4);
+    }
+
+    #[test]
+    fn test_encode_roundtrip() {
+        let png = Png::try_from(&crate::png::tests::PNG_FILE[..]).unwrap();
+        let image = Image::from_png(&png).unwrap();
+        let encoded = image.to_png(&png).unwrap();
+        assert_eq!(Image::from_png(&encoded).unwrap(), image);
+        assert_eq!(encoded.chunks().len(), png.chunks().len());
+    }
+
+    #[test]
+    fn test_new_image() {
+        let png = testing_png(4, 4, 8, 2, &testing_rows(), 0);
+        let image = Image::from_png(&png).unwrap();
+        let header = *image.header();
+        assert!(Image::new(header, image.data().to_vec()).is_ok());
+        assert!(Image::new(header, image.data()[1..].to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_stream_length() {
+        let png = testing_png(4, 4, 8, 2, &testing_rows(), 0);
+        let mut compressed = Image::compressed_data(&png);
+        let length = compressed.len();
+        assert_eq!(Image::stream_length(&compressed), Some(length));
+        assert_eq!(Image::stream_length(&compressed[..length - 1]), None);
+        compressed.extend_from_slice(b"hidden");
+        assert_eq!(Image::stream_length(&compressed), Some(length));
+    }
+
+    #[test]
+    fn test_oversized_ihdr() {
+        let png = testing_png(4, 4, 8, 2, &testing_rows(), 0);
+        let compressed = Image::compressed_data(&png);
+        // the length of the image data does not fit in memory, or is far larger than the data
+        for &(width, height) in
+            [(0x7fff_ffff, 0x7fff_ffff), (0x7fff_ffff, 1), (60000, 60000)].iter()
+        {
+            let mut data = u32::to_be_bytes(width).to_vec();
+            data.extend_from_slice(&u32::to_be_bytes(height));
+            data.extend_from_slice(&[16, 6, 0, 0, 0]);
+            let ihdr = Ihdr::try_from(&chunk("IHDR", data)).unwrap();
+            assert!(matches!(
+                Image::decode(ihdr, &compressed),
+                Err(Error::InvalidImageData)
+            ));
+        }
+        let data = vec![0xff, 0xff, 0xff, 0xff, 0, 0, 0, 1, 8, 2, 0, 0, 0];
+        assert!(Ihdr::try_from(&chunk("IHDR", data)).is_err());
+    }
+
+    #[test]
+    fn test_truncated_image_data() {
+        let png = testing_png(4, 4, 8, 2, &testing_rows(), 0);
+        let ihdr = Ihdr::try_from(&png.chunks()[0]).unwrap();
+        let compressed = Image::compressed_data(&png);
+        assert!(Image::decode(ihdr, &compressed[..compressed.len() / 2]).is_err());
+    }
+}
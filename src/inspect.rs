@@ -0,0 +1,405 @@
+use crate::{Chunk, Ihdr, Png};
+use std::convert::{TryFrom, TryInto};
+
+/// The number of characters of text chunks that are shown in summaries.
+const TEXT_SUMMARY_LIMIT: usize = 40;
+
+/// The layout of a chunk in a PNG file together with a short description of its content. Raw
+/// chunks that were kept by lenient parsing are described as well, but they have no index and no
+/// summary.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::{inspect, Chunk, ChunkType, Png};
+/// # use std::str::FromStr;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let png = Png::from_chunks(vec![
+///         Chunk::new(ChunkType::from_str("IHDR")?, vec![0, 0, 0, 50, 0, 0, 0, 30, 8, 6, 0, 0, 0])?,
+///         Chunk::new(ChunkType::from_str("ruSt")?, vec![0xde, 0xad, 0xbe, 0xef, 0x00])?,
+///         Chunk::new(ChunkType::from_str("IEND")?, Vec::new())?,
+///     ]);
+///
+///     let chunks = inspect(&png, 4);
+///     assert_eq!(33, chunks[1].offset());
+///     assert_eq!("CPR-", chunks[0].properties());
+///     assert_eq!(Some("50x30, 8-bit RGBA, non-interlaced"), chunks[0].summary());
+///     assert_eq!("de ad be ef ...", chunks[1].preview());
+///     assert!(chunks[1].is_crc_valid());
+///     Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkInfo {
+    index: Option<usize>,
+    offset: usize,
+    length: u32,
+    type_bytes: [u8; 4],
+    chunk_type: String,
+    crc: u32,
+    crc_valid: bool,
+    summary: Option<String>,
+    preview: String,
+}
+
+impl ChunkInfo {
+    /// Get the index of the chunk in the list of chunks of the PNG image, or `None` for a raw
+    /// chunk.
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    /// Get the byte offset of the chunk from the start of the PNG file.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Get the length of the data of the chunk.
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// Get the type code of the chunk, where bytes that are not ASCII letters are escaped.
+    pub fn chunk_type(&self) -> &str {
+        &self.chunk_type
+    }
+
+    /// Check whether the type bytes of the chunk are a valid type code.
+    pub fn is_type_valid(&self) -> bool {
+        self.type_bytes.iter().all(u8::is_ascii_alphabetic)
+    }
+
+    /// Get the checksum that is stored with the chunk.
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// Check whether the stored checksum matches the type code and the data of the chunk.
+    pub fn is_crc_valid(&self) -> bool {
+        self.crc_valid
+    }
+
+    /// Get the property bits of the type code as four characters, which are `C` for critical,
+    /// `P` for public, `R` for a valid reserved bit and `S` for safe to copy, or `-` if the
+    /// property does not hold.
+    pub fn properties(&self) -> String {
+        // the property bits are the lowercase bits of the type bytes, even if they are not letters
+        let lowercase: Vec<bool> = self.type_bytes.iter().map(|b| b & 0x20 != 0).collect();
+        [
+            (!lowercase[0], 'C'),
+            (!lowercase[1], 'P'),
+            (!lowercase[2], 'R'),
+            (lowercase[3], 'S'),
+        ]
+        .iter()
+        .map(|(set, c)| if *set { *c } else { '-' })
+        .collect()
+    }
+
+    /// Get the decoded content of the chunk, if its type is known.
+    pub fn summary(&self) -> Option<&str> {
+        self.summary.as_deref()
+    }
+
+    /// Get the leading part of the data of the chunk as quoted text if it is readable, or as hex
+    /// bytes otherwise.
+    pub fn preview(&self) -> &str {
+        &self.preview
+    }
+}
+
+/// Get the layout and a description of every chunk of the given PNG image, including its raw
+/// chunks. Previews show at most the given number of bytes of the data.
+pub fn inspect(png: &Png, preview_length: usize) -> Vec<ChunkInfo> {
+    let mut offset = Png::STANDARD_HEADER.len();
+    png.file_chunks()
+        .into_iter()
+        .map(|(index, raw)| {
+            let info = ChunkInfo {
+                index,
+                offset,
+                length: raw.length(),
+                type_bytes: raw.type_bytes(),
+                chunk_type: raw.type_name(),
+                crc: raw.crc(),
+                crc_valid: raw.is_crc_valid(),
+                summary: index.and_then(|index| summarize(&png.chunks()[index])),
+                preview: preview(raw.data(), preview_length),
+            };
+            offset += 12 + raw.data().len();
+            info
+        })
+        .collect()
+}
+
+/// Describe the content of the given chunk if it is an image header, a text chunk, a physical
+/// pixel dimensions chunk, a last modification time chunk or a gamma chunk. `None` is returned
+/// for other chunks and for chunks whose data can not be parsed.
+pub fn summarize(chunk: &Chunk) -> Option<String> {
+    let data = chunk.data();
+    match &chunk.chunk_type().bytes() {
+        b"IHDR" => {
+            let ihdr = Ihdr::try_from(chunk).ok()?;
+            let color = match ihdr.color_type() {
+                Ihdr::COLOR_GRAYSCALE => "grayscale",
+                Ihdr::COLOR_RGB => "RGB",
+                Ihdr::COLOR_INDEXED => "indexed",
+                Ihdr::COLOR_GRAYSCALE_ALPHA => "grayscale with alpha",
+                _ => "RGBA",
+            };
+            Some(format!(
+                "{}x{}, {}-bit {}, {}",
+                ihdr.width(),
+                ihdr.height(),
+                ihdr.bit_depth(),
+                color,
+                if ihdr.is_interlaced() {
+                    "interlaced"
+                } else {
+                    "non-interlaced"
+                }
+            ))
+        }
+        b"tEXt" => {
+            let (keyword, text) = split_keyword(data)?;
+            Some(format!(
+                "{}: {}",
+                keyword,
+                quote(&String::from_utf8_lossy(text))
+            ))
+        }
+        b"zTXt" => {
+            let (keyword, text) = split_keyword(data)?;
+            Some(format!(
+                "{}: {} compressed byte(s)",
+                keyword,
+                text.len().saturating_sub(1)
+            ))
+        }
+        b"iTXt" => {
+            let (keyword, rest) = split_keyword(data)?;
+            let (&compressed, rest) = rest.split_first()?;
+            let rest = rest.get(1..)?;
+            let end = rest.iter().position(|&b| b == 0)?;
+            let language = String::from_utf8_lossy(&rest[..end]);
+            let rest = &rest[end + 1..];
+            let text = &rest[rest.iter().position(|&b| b == 0)? + 1..];
+            let language = if language.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", language)
+            };
+            if compressed == 0 {
+                let text = std::str::from_utf8(text).ok()?;
+                Some(format!("{}{}: {}", keyword, language, quote(text)))
+            } else {
+                Some(format!(
+                    "{}{}: {} compressed byte(s)",
+                    keyword,
+                    language,
+                    text.len()
+                ))
+            }
+        }
+        b"pHYs" if data.len() == 9 => {
+            let x = u32::from_be_bytes(data[0..4].try_into().ok()?);
+            let y = u32::from_be_bytes(data[4..8].try_into().ok()?);
+            match data[8] {
+                1 => Some(format!(
+                    "{}x{} pixels per meter ({:.0}x{:.0} DPI)",
+                    x,
+                    y,
+                    f64::from(x) * 0.0254,
+                    f64::from(y) * 0.0254
+                )),
+                _ => Some(format!("aspect ratio {}:{}", x, y)),
+            }
+        }
+        b"tIME" if data.len() == 7 => Some(format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            u16::from_be_bytes([data[0], data[1]]),
+            data[2],
+            data[3],
+            data[4],
+            data[5],
+            data[6]
+        )),
+        b"gAMA" if data.len() == 4 => {
+            let gamma = u32::from_be_bytes(data.try_into().ok()?);
+            Some(format!("gamma {:.5}", f64::from(gamma) / 100_000.0))
+        }
+        _ => None,
+    }
+}
+
+/// Get the given data as quoted text if it is readable, or as hex bytes otherwise, cut off after
+/// the given number of bytes. Empty data has an empty preview.
+pub fn preview(data: &[u8], length: usize) -> String {
+    if data.is_empty() {
+        return String::new();
+    }
+    let shown = &data[..data.len().min(length)];
+    let more = if shown.len() < data.len() { " ..." } else { "" };
+    // a multi-byte character may be cut off at the end of the preview
+    let text = match std::str::from_utf8(shown) {
+        Ok(text) => Some(text),
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&shown[..e.valid_up_to()]).ok(),
+        Err(_) => None,
+    };
+    match text {
+        Some(text) if !text.chars().any(|c| c.is_control() && !c.is_whitespace()) => {
+            format!("{:?}{}", text, more)
+        }
+        _ => {
+            let hex: Vec<String> = shown.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("{}{}", hex.join(" "), more)
+        }
+    }
+}
+
+/// Format the given data as lines of 16 hex bytes followed by their printable ASCII characters,
+/// where every line starts with the offset of its first byte, counted from the given offset.
+///
+/// # Examples
+///
+/// ```rust
+/// # use pmsg::hex_lines;
+/// let lines = hex_lines(b"IEND", 37);
+/// assert_eq!(vec![format!("{:08x}  {:<47} |IEND|", 37, "49 45 4e 44")], lines);
+/// ```
+pub fn hex_lines(data: &[u8], offset: usize) -> Vec<String> {
+    data.chunks(16)
+        .enumerate()
+        .map(|(i, row)| {
+            let hex: Vec<String> = row.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = row
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:08x}  {:<47} |{}|", offset + i * 16, hex.join(" "), ascii)
+        })
+        .collect()
+}
+
+/// Split the data of a text chunk into its keyword and the data that follows the null separator.
+fn split_keyword(data: &[u8]) -> Option<(String, &[u8])> {
+    let end = data.iter().position(|&b| b == 0)?;
+    Some((
+        String::from_utf8_lossy(&data[..end]).into_owned(),
+        &data[end + 1..],
+    ))
+}
+
+/// Quote the given text and cut it off if it is too long for a summary.
+fn quote(text: &str) -> String {
+    match text.char_indices().nth(TEXT_SUMMARY_LIMIT) {
+        Some((end, _)) => format!("{:?} ...", &text[..end]),
+        None => format!("{:?}", text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::tests::chunk;
+
+    #[test]
+    fn test_inspect_png_file() {
+        let png = Png::try_from(&crate::png::tests::PNG_FILE[..]).unwrap();
+        let chunks = inspect(&png, 8);
+        assert_eq!(chunks.len(), png.chunks().len());
+        for (i, info) in chunks.iter().enumerate() {
+            assert_eq!(info.index(), Some(i));
+            assert_eq!(Some(info.offset()), png.chunk_offset(i));
+            assert_eq!(info.length(), png.chunks()[i].length());
+            assert!(info.is_crc_valid());
+        }
+        assert_eq!(chunks[4].chunk_type(), "IDAT");
+        assert!(chunks[4].preview().ends_with(" ..."));
+        assert_eq!(chunks[5].properties(), "C-RS");
+    }
+
+    #[test]
+    fn test_inspect_raw_chunks() {
+        let mut bytes = crate::png::tests::PNG_FILE.to_vec();
+        let offset = Png::try_from(&bytes[..]).unwrap().chunk_offset(1).unwrap();
+        // break the checksum of "sRGB" and the type code of "gAMA"
+        bytes[offset + 12] ^= 0xff;
+        bytes[offset + 13 + 4] = b'4';
+        let png = Png::from_bytes_lenient(&bytes).unwrap();
+        let chunks = inspect(&png, 8);
+        assert_eq!(chunks.len(), 7);
+        assert_eq!(chunks[1].index(), None);
+        assert!(!chunks[1].is_crc_valid());
+        assert!(chunks[1].is_type_valid());
+        assert_eq!(chunks[2].index(), None);
+        assert_eq!(chunks[2].chunk_type(), "\\x34AMA");
+        assert!(!chunks[2].is_type_valid());
+        assert_eq!(chunks[2].properties(), "-PR-");
+        assert!(chunks[2].summary().is_none());
+        assert_eq!(chunks[3].index(), Some(1));
+        assert_eq!(Some(chunks[3].offset()), png.chunk_offset(1));
+    }
+
+    #[test]
+    fn test_summaries() {
+        let text = chunk("tEXt", b"Comment\0hello".to_vec());
+        assert_eq!(summarize(&text).unwrap(), "Comment: \"hello\"");
+        let itxt = chunk("iTXt", b"Title\0\0\0en\0Titel\0hi".to_vec());
+        assert_eq!(summarize(&itxt).unwrap(), "Title [en]: \"hi\"");
+        let ztxt = chunk("zTXt", b"Software\0\0abc".to_vec());
+        assert_eq!(summarize(&ztxt).unwrap(), "Software: 3 compressed byte(s)");
+
+        let mut phys = 2835u32.to_be_bytes().repeat(2);
+        phys.push(1);
+        assert_eq!(
+            summarize(&chunk("pHYs", phys)).unwrap(),
+            "2835x2835 pixels per meter (72x72 DPI)"
+        );
+        let time = vec![0x07, 0xe8, 2, 29, 13, 5, 9];
+        assert_eq!(
+            summarize(&chunk("tIME", time)).unwrap(),
+            "2024-02-29 13:05:09 UTC"
+        );
+        assert_eq!(
+            summarize(&chunk("gAMA", 45455u32.to_be_bytes().to_vec())).unwrap(),
+            "gamma 0.45455"
+        );
+        assert!(summarize(&chunk("tIME", vec![0; 3])).is_none());
+        assert!(summarize(&chunk("ruSt", vec![0; 3])).is_none());
+    }
+
+    #[test]
+    fn test_preview() {
+        assert_eq!(preview(b"", 4), "");
+        assert_eq!(preview(b"text\n", 16), "\"text\\n\"");
+        assert_eq!(preview(b"long text", 4), "\"long\" ...");
+        assert_eq!(preview("é".as_bytes(), 1), "\"\" ...");
+        assert_eq!(preview(&[0, 1, 0xff], 2), "00 01 ...");
+    }
+
+    #[test]
+    fn test_hex_lines() {
+        let data: Vec<u8> = (0x40..0x54).collect();
+        let lines = hex_lines(&data, 0x10);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "00000010  40 41 42 43 44 45 46 47 48 49 4a 4b 4c 4d 4e 4f |@ABCDEFGHIJKLMNO|"
+        );
+        assert_eq!(lines[1], format!("00000020  {:<47} |PQRS|", "50 51 52 53"));
+        assert!(hex_lines(&[], 0).is_empty());
+        assert_eq!(
+            hex_lines(&[0x7f, b' '], 0)[0].rsplit('|').nth(1),
+            Some(". ")
+        );
+    }
+}
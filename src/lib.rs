@@ -1,10 +1,42 @@
+mod atomic;
 mod chunk;
 mod chunk_type;
+mod compression;
+mod diff;
+mod disguise;
+mod ecc;
+mod envelope;
+mod fragment;
+mod idat;
+mod image;
+mod inspect;
+mod message;
+mod palette;
 mod png;
+mod raw_chunk;
+mod sanitize;
+mod scan;
+mod strip;
 
+pub use atomic::*;
 pub use chunk::*;
 pub use chunk_type::*;
+pub use compression::*;
+pub use diff::*;
+pub use disguise::*;
+pub use ecc::*;
+pub use envelope::*;
+pub use fragment::*;
+pub use idat::*;
+pub use image::*;
+pub use inspect::*;
+pub use message::*;
+pub use palette::*;
 pub use png::*;
+pub use raw_chunk::*;
+pub use sanitize::*;
+pub use scan::*;
+pub use strip::*;
 
 /// The error type for operations on PNG files, and associated traits.
 pub type Error = PMSGError;
@@ -17,16 +49,88 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum PMSGError {
     /// Value for of the chunk length exceeds 2^31.
     InvalidChunkLength,
-    /// Chunk type code contains invalid byte.
-    InvalidChunkType,
-    /// The given checksum does not match the computed checksum.
-    InvalidCRC,
+    /// Chunk type code contains the given invalid byte at the given position.
+    InvalidChunkType { byte: u8, position: usize },
+    /// Chunk type code has the given number of bytes instead of four.
+    InvalidChunkTypeLength(usize),
+    /// The stored checksum does not match the checksum that is computed from the chunk.
+    InvalidCRC { expected: u32, actual: u32 },
+    /// The data ends before the expected number of bytes, of which only some are left.
+    UnexpectedEndOfData { expected: usize, actual: usize },
+    /// The chunk at the given byte offset and index of a PNG file can not be parsed. The type code
+    /// is given if it could be read, with the bytes that are not letters escaped.
+    InvalidChunk {
+        offset: usize,
+        index: usize,
+        chunk_type: Option<String>,
+        source: Box<PMSGError>,
+    },
     /// The given data for a PNG file contains an invalid header.
     InvalidPNGFileHeader,
     /// The first chunk of the PNG file is not valid.
     InvalidStartingChunk,
     /// The given chunk type can not be found from the png representation.
     ChunkTypeNotFound,
+    /// The given index is past the end of the list of chunks.
+    ChunkIndexOutOfRange(usize),
+    /// The given position of a chunk can not be parsed.
+    InvalidChunkPosition(String),
+    /// The change would put a chunk before the "IHDR" chunk or after the "IEND" chunk.
+    InvalidChunkOrder,
+    /// The given data does not start with a valid envelope header.
+    InvalidEnvelopeHeader,
+    /// The envelope was written with a version of the format that is not supported.
+    UnsupportedEnvelopeVersion(u8),
+    /// The checksum of the envelope does not match the checksum of its payload.
+    InvalidEnvelopeChecksum,
+    /// The given compression algorithm is not supported.
+    UnsupportedCompression(String),
+    /// The given compression level is not supported by the compression algorithm.
+    InvalidCompressionLevel(u32),
+    /// The decompressed data would be larger than the given number of bytes.
+    DecompressionLimitExceeded(usize),
+    /// The decompressed data does not have the size that was stored with it.
+    DecompressedSizeMismatch { expected: usize, actual: usize },
+    /// The given kind of metadata can not be used to disguise hidden data.
+    UnsupportedDisguise(String),
+    /// The given amount of error correction is not supported.
+    InvalidErrorCorrection(u32),
+    /// The given data does not start with a valid error correction header.
+    InvalidErrorCorrectionHeader,
+    /// The data contains more corrupted bytes than can be corrected.
+    UncorrectableErrors,
+    /// The given data does not start with a valid fragment header.
+    InvalidFragmentHeader,
+    /// A piece of the payload with the given id can not be found.
+    MissingFragment { id: u32, index: u32 },
+    /// A piece of the payload with the given id was found more than once.
+    DuplicateFragment { id: u32, index: u32 },
+    /// The pieces of the payload with the given id disagree on the number of pieces.
+    InconsistentFragments { id: u32 },
+    /// No message with the given id is hidden in the PNG file.
+    MessageNotFound(u32),
+    /// No message is hidden in the PNG file with the given chunk type code.
+    NoMessageFound(String),
+    /// The "IHDR" chunk of the PNG file is not valid.
+    InvalidImageHeader,
+    /// The image data of the PNG file can not be decoded.
+    InvalidImageData,
+    /// The "PLTE" chunk of the PNG file is missing or not valid.
+    InvalidPalette,
+    /// The palette entry at the given index has the same color as another entry.
+    DuplicatePaletteEntry(usize),
+    /// The data does not fit in the space that is available for hiding it.
+    InsufficientCapacity { needed: usize, capacity: usize },
+    /// The PNG file already has a chunk with the given type code, of which it can only have one.
+    DuplicateChunk(String),
+    /// More than one message is hidden with the given chunk type code where only one is expected.
+    MultipleMessagesFound(String),
+    /// The operation does not support images with the given color type.
+    UnsupportedColorType(u8),
+    /// The given number of bit planes is not valid for the bit depth of the image.
+    InvalidBitPlanes(u8),
+    /// The arguments given on the command line are invalid or can not be used together.
+    Usage(String),
     /// Errors that occured with I/O operations.
     IOError(std::io::Error),
     /// Errors that occured with UTF-8 encoding.
@@ -37,17 +141,116 @@ pub enum PMSGError {
     NumFromIntError(std::num::TryFromIntError),
 }
 
-impl std::error::Error for PMSGError {}
+impl std::error::Error for PMSGError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PMSGError::InvalidChunk { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 impl std::fmt::Display for PMSGError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            PMSGError::InvalidChunkType => write!(f, "invalid chunk type"),
+            PMSGError::InvalidChunkType { byte, position } => write!(
+                f,
+                "invalid chunk type, byte {} is 0x{:02x} instead of an ASCII letter",
+                position, byte
+            ),
+            PMSGError::InvalidChunkTypeLength(length) => write!(
+                f,
+                "invalid chunk type, it has {} bytes instead of 4",
+                length
+            ),
             PMSGError::InvalidChunkLength => write!(f, "invalid chunk length"),
-            PMSGError::InvalidCRC => write!(f, "invalid CRC"),
+            PMSGError::InvalidCRC { expected, actual } => write!(
+                f,
+                "invalid CRC, expected {:08x} but found {:08x}",
+                expected, actual
+            ),
+            PMSGError::UnexpectedEndOfData { expected, actual } => write!(
+                f,
+                "unexpected end of data, expected {} bytes but only {} are left",
+                expected, actual
+            ),
+            PMSGError::InvalidChunk {
+                offset,
+                index,
+                chunk_type,
+                source,
+            } => {
+                write!(f, "chunk {}", index)?;
+                if let Some(chunk_type) = chunk_type {
+                    write!(f, " ({})", chunk_type)?;
+                }
+                write!(f, " at offset {}: {}", offset, source)
+            }
             PMSGError::InvalidPNGFileHeader => write!(f, "invalid PNG file header"),
             PMSGError::InvalidStartingChunk => write!(f, "invalid starting chunk"),
             PMSGError::ChunkTypeNotFound => write!(f, "chunk type not found"),
+            PMSGError::ChunkIndexOutOfRange(i) => write!(f, "chunk index {} out of range", i),
+            PMSGError::InvalidChunkPosition(p) => write!(f, "invalid chunk position {}", p),
+            PMSGError::InvalidChunkOrder => {
+                write!(f, "IHDR must be the first chunk and IEND the last chunk")
+            }
+            PMSGError::InvalidEnvelopeHeader => write!(f, "invalid envelope header"),
+            PMSGError::UnsupportedEnvelopeVersion(v) => {
+                write!(f, "unsupported envelope version {}", v)
+            }
+            PMSGError::InvalidEnvelopeChecksum => write!(f, "invalid envelope checksum"),
+            PMSGError::UnsupportedCompression(c) => write!(f, "unsupported compression {}", c),
+            PMSGError::InvalidCompressionLevel(l) => write!(f, "invalid compression level {}", l),
+            PMSGError::DecompressionLimitExceeded(limit) => {
+                write!(f, "decompressed data exceeds the limit of {} bytes", limit)
+            }
+            PMSGError::DecompressedSizeMismatch { expected, actual } => write!(
+                f,
+                "decompressed data has {} bytes instead of {}",
+                actual, expected
+            ),
+            PMSGError::UnsupportedDisguise(d) => write!(f, "unsupported disguise {}", d),
+            PMSGError::InvalidErrorCorrection(v) => write!(f, "invalid error correction {}", v),
+            PMSGError::InvalidErrorCorrectionHeader => {
+                write!(f, "invalid error correction header")
+            }
+            PMSGError::UncorrectableErrors => write!(f, "too many errors to correct"),
+            PMSGError::InvalidFragmentHeader => write!(f, "invalid fragment header"),
+            PMSGError::MissingFragment { id, index } => {
+                write!(f, "fragment {} of payload {:08x} is missing", index, id)
+            }
+            PMSGError::DuplicateFragment { id, index } => {
+                write!(f, "fragment {} of payload {:08x} is duplicated", index, id)
+            }
+            PMSGError::InconsistentFragments { id } => {
+                write!(f, "fragments of payload {:08x} are inconsistent", id)
+            }
+            PMSGError::MessageNotFound(id) => write!(f, "message {:08x} not found", id),
+            PMSGError::NoMessageFound(chunk_type) => {
+                write!(f, "no message is hidden with chunk type {}", chunk_type)
+            }
+            PMSGError::InvalidImageHeader => write!(f, "invalid image header"),
+            PMSGError::InvalidImageData => write!(f, "invalid image data"),
+            PMSGError::InvalidPalette => write!(f, "invalid palette"),
+            PMSGError::DuplicatePaletteEntry(i) => {
+                write!(f, "palette entry {} is a duplicate of another entry", i)
+            }
+            PMSGError::InsufficientCapacity { needed, capacity } => write!(
+                f,
+                "{} bytes do not fit in the available capacity of {} bytes",
+                needed, capacity
+            ),
+            PMSGError::DuplicateChunk(chunk_type) => {
+                write!(f, "the PNG file already has a {} chunk", chunk_type)
+            }
+            PMSGError::MultipleMessagesFound(chunk_type) => write!(
+                f,
+                "more than one message is hidden with chunk type {}",
+                chunk_type
+            ),
+            PMSGError::UnsupportedColorType(t) => write!(f, "unsupported color type {}", t),
+            PMSGError::InvalidBitPlanes(n) => write!(f, "invalid number of bit planes {}", n),
+            PMSGError::Usage(message) => write!(f, "{}", message),
             PMSGError::IOError(e) => write!(f, "{}", e),
             PMSGError::StringFromUtf8Error(e) => write!(f, "{}", e),
             PMSGError::ArrayFromSliceError(e) => write!(f, "{}", e),
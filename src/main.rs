@@ -1,41 +1,75 @@
+mod batch;
 mod commands;
+mod printer;
+mod report;
+mod watch;
 
-use clap::{App, Arg, SubCommand};
+use clap::{App, Arg, ArgGroup, SubCommand};
 use pmsg::*;
 
-fn main() -> Result<()> {
+fn main() {
     let version = "1.0.1";
     let matches = App::new("PMSG")
         .version(version)
         .about("Hide message(s) inside PNG file.")
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .global(true)
+                .takes_value(true)
+                .value_name("FORMAT")
+                .possible_values(&printer::Format::NAMES)
+                .default_value("text")
+                .help("Sets the output format"),
+        )
+        .arg(
+            Arg::with_name("lenient")
+                .long("lenient")
+                .global(true)
+                .help("Keeps chunks with bad CRCs or invalid type codes instead of failing"),
+        )
         .subcommand(
             SubCommand::with_name("encode")
                 .about("Encode the message to the PNG file.")
                 .version(version)
                 .arg(
                     Arg::with_name("file")
-                        .help("PNG file")
-                        .required(true)
+                        .help("PNG files, directories or glob patterns")
+                        .required_unless("files_from")
+                        .multiple(true)
                         .index(1),
                 )
+                .args(&batch_args())
+                .arg(type_arg().required(true))
+                .args(&payload_args())
                 .arg(
-                    Arg::with_name("chunk_type")
-                        .help("Chunk type code of message")
-                        .required(true)
-                        .index(2),
-                )
-                .arg(
-                    Arg::with_name("message")
-                        .help("Hidden message")
-                        .required(true)
-                        .index(3),
+                    Arg::with_name("mode")
+                        .help(
+                            "Hide the data in chunks of the given type, after the compressed \
+                             image data, in the order of the palette entries, or in chunks that \
+                             look like a text comment, XMP or Exif metadata, where it is labeled \
+                             with the given type",
+                        )
+                        .long("mode")
+                        .takes_value(true)
+                        .value_name("MODE")
+                        .possible_values(&["chunk", "idat", "palette", "comment", "xmp", "exif"])
+                        .default_value("chunk"),
                 )
+                .arg(at_arg().help(
+                    "Insert the new chunks at an index, before:TYPE or after:TYPE, \
+                     before the IEND chunk by default",
+                ))
                 .arg(
                     Arg::with_name("output")
                         .help("Output file")
-                        .required(false)
-                        .index(4),
-                ),
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("PATH"),
+                )
+                .arg(backup_arg())
+                .arg(dry_run_arg()),
         )
         .subcommand(
             SubCommand::with_name("decode")
@@ -43,15 +77,36 @@ fn main() -> Result<()> {
                 .version(version)
                 .arg(
                     Arg::with_name("file")
-                        .help("PNG file")
-                        .required(true)
+                        .help("PNG files, directories or glob patterns")
+                        .required_unless("files_from")
+                        .multiple(true)
                         .index(1),
                 )
+                .args(&batch_args())
+                .arg(type_arg().required(true))
                 .arg(
-                    Arg::with_name("chunk_type")
-                        .help("Chunk type code of message")
-                        .required(true)
-                        .index(2),
+                    Arg::with_name("output")
+                        .help(
+                            "Write the hidden data to the given file, or restore the hidden \
+                             files under their original names if a directory is given",
+                        )
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .conflicts_with("raw"),
+                )
+                .arg(
+                    Arg::with_name("raw")
+                        .help("Write the exact hidden bytes to the standard output")
+                        .long("raw"),
+                )
+                .arg(
+                    Arg::with_name("max_size")
+                        .help("Refuse to decompress hidden data larger than the given size")
+                        .long("max-size")
+                        .takes_value(true)
+                        .value_name("BYTES"),
                 ),
         )
         .subcommand(
@@ -60,15 +115,214 @@ fn main() -> Result<()> {
                 .version(version)
                 .arg(
                     Arg::with_name("file")
-                        .help("PNG file")
+                        .help("PNG files, directories or glob patterns")
+                        .required_unless("files_from")
+                        .multiple(true)
+                        .index(1),
+                )
+                .args(&batch_args())
+                .arg(type_arg().required_unless("id").conflicts_with("id"))
+                .arg(
+                    Arg::with_name("id")
+                        .help("Remove only the message with the given id")
+                        .long("id")
+                        .takes_value(true)
+                        .value_name("ID"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Output file")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("PATH"),
+                )
+                .arg(backup_arg())
+                .arg(dry_run_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("update")
+                .about("Replace a hidden message in the PNG file.")
+                .version(version)
+                .arg(
+                    Arg::with_name("file")
+                        .help("PNG files, directories or glob patterns")
+                        .required_unless("files_from")
+                        .multiple(true)
+                        .index(1),
+                )
+                .args(&batch_args())
+                .arg(
+                    Arg::with_name("id")
+                        .help("Id of the message, as shown by the list subcommand")
+                        .long("id")
+                        .takes_value(true)
+                        .value_name("ID")
+                        .required(true),
+                )
+                .args(&payload_args())
+                .arg(
+                    Arg::with_name("output")
+                        .help("Output file")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("PATH"),
+                )
+                .arg(backup_arg())
+                .arg(dry_run_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("List hidden messages in the PNG file.")
+                .version(version)
+                .arg(
+                    Arg::with_name("file")
+                        .help("PNG files, directories or glob patterns")
+                        .required_unless("files_from")
+                        .multiple(true)
+                        .index(1),
+                )
+                .args(&batch_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Compare the chunks of two PNG files.")
+                .version(version)
+                .arg(
+                    Arg::with_name("old")
+                        .help("Old PNG file")
                         .required(true)
                         .index(1),
                 )
                 .arg(
-                    Arg::with_name("chunk_type")
-                        .help("Chunk type code of message")
+                    Arg::with_name("new")
+                        .help("New PNG file")
                         .required(true)
                         .index(2),
+                )
+                .arg(
+                    Arg::with_name("limit")
+                        .help("Number of lines of differences to print for every modified chunk")
+                        .long("limit")
+                        .takes_value(true)
+                        .value_name("LINES")
+                        .default_value("16"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("extract")
+                .about("Extract the raw data of a chunk from the PNG file.")
+                .version(version)
+                .arg(
+                    Arg::with_name("file")
+                        .help("PNG files, directories or glob patterns")
+                        .required_unless("files_from")
+                        .multiple(true)
+                        .index(1),
+                )
+                .args(&batch_args())
+                .args(&chunk_selection_args())
+                .group(chunk_selection_group().required(true))
+                .arg(
+                    Arg::with_name("framed")
+                        .help("Extract the length, type code and CRC together with the data")
+                        .long("framed"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Write the data to the given file instead of the standard output")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("PATH"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("hexdump")
+                .about("Print the bytes of the PNG file with the chunk boundaries.")
+                .version(version)
+                .arg(
+                    Arg::with_name("file")
+                        .help("PNG files, directories or glob patterns")
+                        .required_unless("files_from")
+                        .multiple(true)
+                        .index(1),
+                )
+                .args(&batch_args())
+                .args(&chunk_selection_args())
+                .group(chunk_selection_group())
+                .arg(
+                    Arg::with_name("limit")
+                        .help("Number of bytes to print for every chunk")
+                        .long("limit")
+                        .takes_value(true)
+                        .value_name("BYTES"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("insert")
+                .about("Insert a raw chunk into the PNG file.")
+                .version(version)
+                .arg(
+                    Arg::with_name("file")
+                        .help("PNG files, directories or glob patterns")
+                        .required_unless("files_from")
+                        .multiple(true)
+                        .index(1),
+                )
+                .args(&batch_args())
+                .arg(
+                    Arg::with_name("type")
+                        .help("Chunk type code of the new chunk")
+                        .long("type")
+                        .takes_value(true)
+                        .value_name("TYPE")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("data_file")
+                        .help("File with the data of the new chunk")
+                        .long("data-file")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .required(true),
+                )
+                .arg(
+                    at_arg()
+                        .help("Insert the chunk at an index, before:TYPE or after:TYPE")
+                        .default_value("before:IEND"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Output file")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("PATH"),
+                )
+                .arg(backup_arg())
+                .arg(dry_run_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("Show the layout and the content of the chunks of the PNG file.")
+                .version(version)
+                .arg(
+                    Arg::with_name("file")
+                        .help("PNG files, directories or glob patterns")
+                        .required_unless("files_from")
+                        .multiple(true)
+                        .index(1),
+                )
+                .args(&batch_args())
+                .arg(
+                    Arg::with_name("preview")
+                        .help("Number of bytes of the data to preview for every chunk")
+                        .long("preview")
+                        .takes_value(true)
+                        .value_name("BYTES")
+                        .default_value("16"),
                 ),
         )
         .subcommand(
@@ -77,24 +331,353 @@ fn main() -> Result<()> {
                 .version(version)
                 .arg(
                     Arg::with_name("file")
-                        .help("PNG file")
+                        .help("PNG files, directories or glob patterns")
+                        .required_unless("files_from")
+                        .multiple(true)
+                        .index(1),
+                )
+                .args(&batch_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("sanitize")
+                .about("Destroy data hidden in the pixels of the PNG file.")
+                .version(version)
+                .arg(
+                    Arg::with_name("file")
+                        .help("PNG files, directories or glob patterns")
+                        .required_unless("files_from")
+                        .multiple(true)
+                        .index(1),
+                )
+                .args(&batch_args())
+                .arg(
+                    Arg::with_name("pixels")
+                        .help("Overwrite the low bit planes of every sample")
+                        .long("pixels")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("depth")
+                        .help("Number of bit planes to overwrite")
+                        .long("depth")
+                        .takes_value(true)
+                        .value_name("BITS")
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::with_name("zero")
+                        .help("Clear the bits instead of randomizing them")
+                        .long("zero"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Output file")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("PATH"),
+                )
+                .arg(backup_arg())
+                .arg(dry_run_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("scan")
+                .about("Scan PNG files for signs of hidden data.")
+                .version(version)
+                .arg(
+                    Arg::with_name("file")
+                        .help("PNG files, directories or glob patterns")
+                        .required_unless("files_from")
+                        .multiple(true)
+                        .index(1),
+                )
+                .args(&batch_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("strip")
+                .about("Remove all non-essential data from the PNG file.")
+                .version(version)
+                .arg(
+                    Arg::with_name("file")
+                        .help("PNG files, directories or glob patterns")
+                        .required_unless("files_from")
+                        .multiple(true)
+                        .index(1),
+                )
+                .args(&batch_args())
+                .arg(
+                    Arg::with_name("keep")
+                        .help("Chunk types to keep besides IHDR, PLTE, IDAT and IEND")
+                        .long("keep")
+                        .takes_value(true)
+                        .value_name("TYPES")
+                        .use_delimiter(true)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("recompress")
+                        .help("Re-compress the image data")
+                        .long("recompress"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Output file")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("PATH"),
+                )
+                .arg(backup_arg())
+                .arg(dry_run_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("watch")
+                .about("Tag the PNG files that are added to or changed in a directory.")
+                .version(version)
+                .arg(
+                    Arg::with_name("dir")
+                        .help("Directory to watch")
                         .required(true)
                         .index(1),
+                )
+                .arg(
+                    Arg::with_name("type")
+                        .help("Chunk type code of the message, files with such a chunk are skipped")
+                        .long("type")
+                        .takes_value(true)
+                        .value_name("TYPE")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("message_template")
+                        .help(
+                            "Message to hide, where {name}, {path} and {time} are replaced by \
+                             the file name, the path of the file and the current time",
+                        )
+                        .long("message-template")
+                        .takes_value(true)
+                        .value_name("TEMPLATE")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("label")
+                        .help("Describe the message with the given label")
+                        .long("label")
+                        .takes_value(true)
+                        .value_name("LABEL"),
+                )
+                .arg(
+                    Arg::with_name("recursive")
+                        .help("Also watch the subdirectories of the directory")
+                        .long("recursive")
+                        .short("r"),
+                )
+                .arg(
+                    Arg::with_name("interval")
+                        .help("Look for new or changed files every given number of seconds")
+                        .long("interval")
+                        .takes_value(true)
+                        .value_name("SECONDS")
+                        .default_value("2"),
+                )
+                .arg(
+                    Arg::with_name("once")
+                        .help("Tag the files that are in the directory and exit")
+                        .long("once"),
                 ),
         )
-        .get_matches();
+        .get_matches_safe()
+        .unwrap_or_else(|e| {
+            // help and version are printed to the standard output and are not errors
+            if !e.use_stderr() {
+                e.exit();
+            }
+            eprintln!("{}", e.message);
+            std::process::exit(report::EXIT_USAGE);
+        });
+
+    match run(&matches) {
+        Ok(0) => {}
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            report::report(&e);
+            std::process::exit(report::exit_code(&e));
+        }
+    }
+}
 
+/// Run the subcommand that was given on the command line and get the exit code. Every subcommand
+/// but diff and watch runs for each of the files that are given.
+fn run(matches: &clap::ArgMatches) -> Result<i32> {
     // You can handle information about subcommands by requesting their matches by name
     // (as below), requesting just the name used, or both at the same time
     if let Some(matches) = matches.subcommand_matches("encode") {
-        commands::encode(matches)?;
+        return batch::run(matches, commands::encode);
     } else if let Some(matches) = matches.subcommand_matches("decode") {
-        commands::decode(matches)?;
+        return batch::run(matches, commands::decode);
     } else if let Some(matches) = matches.subcommand_matches("remove") {
-        commands::remove(matches)?;
+        return batch::run(matches, commands::remove);
+    } else if let Some(matches) = matches.subcommand_matches("update") {
+        return batch::run(matches, commands::update);
+    } else if let Some(matches) = matches.subcommand_matches("list") {
+        return batch::run(matches, commands::list);
+    } else if let Some(matches) = matches.subcommand_matches("diff") {
+        commands::diff(matches)?;
+    } else if let Some(matches) = matches.subcommand_matches("extract") {
+        return batch::run(matches, commands::extract);
+    } else if let Some(matches) = matches.subcommand_matches("hexdump") {
+        return batch::run(matches, commands::hexdump);
+    } else if let Some(matches) = matches.subcommand_matches("insert") {
+        return batch::run(matches, commands::insert);
+    } else if let Some(matches) = matches.subcommand_matches("inspect") {
+        return batch::run(matches, commands::inspect);
     } else if let Some(matches) = matches.subcommand_matches("print") {
-        commands::print(matches)?;
+        return batch::run(matches, commands::print);
+    } else if let Some(matches) = matches.subcommand_matches("sanitize") {
+        return batch::run(matches, commands::sanitize);
+    } else if let Some(matches) = matches.subcommand_matches("scan") {
+        return batch::run(matches, commands::scan);
+    } else if let Some(matches) = matches.subcommand_matches("strip") {
+        return batch::run(matches, commands::strip);
+    } else if let Some(matches) = matches.subcommand_matches("watch") {
+        return watch::run(matches);
     }
 
-    Ok(())
+    Ok(0)
+}
+
+/// Get the arguments that select more files and control how they are processed, which are shared
+/// by every subcommand that works on PNG files.
+fn batch_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("recursive")
+            .help("Process the PNG files in the given directories and their subdirectories")
+            .long("recursive")
+            .short("r"),
+        Arg::with_name("files_from")
+            .help("Also process the files listed in the given file, one per line, or - for stdin")
+            .long("files-from")
+            .takes_value(true)
+            .value_name("PATH"),
+        Arg::with_name("continue_on_error")
+            .help("Keep processing the other files after a file fails")
+            .long("continue-on-error"),
+        Arg::with_name("jobs")
+            .help("Process at most the given number of files at the same time")
+            .long("jobs")
+            .short("j")
+            .takes_value(true)
+            .value_name("N"),
+    ]
+}
+
+/// Get the arguments that give the message to hide and how it is stored, which are shared by the
+/// encode and update subcommands.
+fn payload_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("message")
+            .help("Hidden message")
+            .long("message")
+            .short("m")
+            .takes_value(true)
+            .value_name("MESSAGE")
+            .required_unless_one(&["input_file", "stdin"])
+            .conflicts_with_all(&["input_file", "stdin"]),
+        Arg::with_name("input_file")
+            .help("Hide the content of the given file instead of a message")
+            .long("file")
+            .short("f")
+            .takes_value(true)
+            .value_name("PATH")
+            .conflicts_with("stdin"),
+        Arg::with_name("stdin")
+            .help("Hide the data read from the standard input instead of a message")
+            .long("stdin"),
+        Arg::with_name("label")
+            .help("Describe the message with the given label")
+            .long("label")
+            .takes_value(true)
+            .value_name("LABEL"),
+        Arg::with_name("compress")
+            .help("Compress the hidden data with the given algorithm")
+            .long("compress")
+            .takes_value(true)
+            .value_name("ALGORITHM")
+            .possible_values(&Compression::NAMES),
+        Arg::with_name("level")
+            .help("Compression level, defaults to the level of the algorithm")
+            .long("level")
+            .takes_value(true)
+            .value_name("LEVEL")
+            .requires("compress"),
+        Arg::with_name("ecc")
+            .help("Protect the hidden data with the given percentage of redundancy")
+            .long("ecc")
+            .takes_value(true)
+            .value_name("PERCENT"),
+        Arg::with_name("fragment_size")
+            .help("Split the hidden data into chunks of at most the given size")
+            .long("fragment-size")
+            .takes_value(true)
+            .value_name("BYTES"),
+    ]
+}
+
+/// Get the argument that gives the chunk type code of the hidden messages, which is shared by the
+/// encode, decode and remove subcommands.
+fn type_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("chunk_type")
+        .help("Chunk type code of message")
+        .long("type")
+        .short("t")
+        .takes_value(true)
+        .value_name("TYPE")
+}
+
+/// Get the argument that keeps a backup of the file that is overwritten.
+fn backup_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("backup")
+        .help("Keep the overwritten file under its name with the given suffix, \".bak\" by default")
+        .long("backup")
+        .takes_value(true)
+        .value_name("SUFFIX")
+        .min_values(0)
+        .require_equals(true)
+}
+
+/// Get the argument that prints out the changes instead of writing the file.
+fn dry_run_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("dry_run")
+        .help("Print the chunks that would be added or removed without writing anything")
+        .long("dry-run")
+}
+
+/// Get the arguments that select a chunk by its index or by its type code, which are shared by the
+/// extract and hexdump subcommands.
+fn chunk_selection_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("index")
+            .help("Index of the chunk")
+            .long("index")
+            .takes_value(true)
+            .value_name("INDEX"),
+        Arg::with_name("type")
+            .help("Chunk type code of the chunk, the first matching chunk is used")
+            .long("type")
+            .takes_value(true)
+            .value_name("TYPE"),
+    ]
+}
+
+/// Get the group that allows only one way of selecting a chunk.
+fn chunk_selection_group<'a>() -> ArgGroup<'a> {
+    ArgGroup::with_name("chunk").args(&["index", "type"])
+}
+
+/// Get the argument that gives the position of new chunks.
+fn at_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("at")
+        .long("at")
+        .takes_value(true)
+        .value_name("POSITION")
 }
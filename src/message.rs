@@ -0,0 +1,313 @@
+use crate::{
+    ChunkType, Disguise, Envelope, Fragment, IdatPayload, PalettePayload, Png, ReedSolomon, Result,
+};
+use std::convert::TryFrom;
+
+/// Location tells where a piece of a hidden message is stored in a PNG file. Locations are
+/// displayed with the name of the `encode` mode that stores data there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    /// The data of the chunk at the given index.
+    Chunk(usize),
+    /// The chunk at the given index, which disguises the data as metadata.
+    Disguised(usize, Disguise),
+    /// The payload at the given index after the compressed image data.
+    ImageData(usize),
+    /// The order of the palette entries.
+    Palette,
+}
+
+impl Location {
+    /// Get the index of the chunk that holds the piece, if the piece is stored in a chunk of its
+    /// own.
+    pub fn chunk_index(&self) -> Option<usize> {
+        match self {
+            Self::Chunk(i) | Self::Disguised(i, _) => Some(*i),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Chunk(_) => write!(f, "chunk"),
+            Self::Disguised(_, disguise) => write!(f, "{}", disguise),
+            Self::ImageData(_) => write!(f, "idat"),
+            Self::Palette => write!(f, "palette"),
+        }
+    }
+}
+
+/// Message is an envelope that is hidden in a PNG file together with the chunk type code that
+/// labels it and the locations of the pieces it is stored in.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::{Chunk, ChunkType, Envelope, Location, Message, Png};
+/// # use std::str::FromStr;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let mut envelope = Envelope::from_text("THE HIDDEN MESSAGE");
+///     envelope.set_id(Some(42));
+///     let png = Png::from_chunks(vec![
+///         Chunk::new(ChunkType::from_str("IHDR")?, vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0])?,
+///         Chunk::new(ChunkType::from_str("ruSt")?, envelope.as_bytes()?)?,
+///         Chunk::new(ChunkType::from_str("IEND")?, Vec::new())?,
+///     ]);
+///
+///     let messages = Message::find_all(&png)?;
+///     assert_eq!(1, messages.len());
+///     assert_eq!(Some(42), messages[0].id());
+///     assert_eq!(&[Location::Chunk(1)], messages[0].locations());
+///     assert_eq!(2, messages[0].remove(&png)?.chunks().len());
+///     Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    chunk_type: ChunkType,
+    envelope: Envelope,
+    locations: Vec<Location>,
+    corrected: usize,
+}
+
+impl Message {
+    /// Find all the messages that are hidden in the given PNG image, in the order in which their
+    /// first piece appears. Pieces that are protected by error correction are repaired and pieces
+    /// of fragmented messages are reassembled. Chunks that were written without an envelope are
+    /// not messages and are skipped.
+    pub fn find_all(png: &Png) -> Result<Vec<Self>> {
+        let mut pieces = Vec::new();
+        for (i, chunk) in png.chunks().iter().enumerate() {
+            let disguise = Disguise::from_chunk_type(chunk.chunk_type());
+            match (disguise, Disguise::unwrap(chunk)) {
+                (Some(disguise), Some((label, data))) => {
+                    pieces.push((label, Location::Disguised(i, disguise), data))
+                }
+                _ => pieces.push((
+                    chunk.chunk_type().clone(),
+                    Location::Chunk(i),
+                    chunk.data().to_vec(),
+                )),
+            }
+        }
+        // image data that is followed by anything else is treated as if nothing was hidden in it
+        for (i, payload) in IdatPayload::read_all(png)
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+        {
+            let chunk_type = payload.chunk_type().clone();
+            pieces.push((chunk_type, Location::ImageData(i), payload.data().to_vec()));
+        }
+        if let Ok(Some(payload)) = PalettePayload::read(png) {
+            let chunk_type = payload.chunk_type().clone();
+            pieces.push((chunk_type, Location::Palette, payload.data().to_vec()));
+        }
+
+        // pieces of the same fragmented message share the chunk type code and the fragment id
+        struct Group {
+            chunk_type: ChunkType,
+            fragment_id: Option<u32>,
+            locations: Vec<Location>,
+            data: Vec<Vec<u8>>,
+            corrected: usize,
+        }
+        let mut groups: Vec<Group> = Vec::new();
+        for (chunk_type, location, data) in pieces {
+            let (data, corrected) = if ReedSolomon::is_encoded(&data) {
+                ReedSolomon::decode(&data)?
+            } else {
+                (data, 0)
+            };
+            let fragment_id = if Fragment::is_fragment(&data) {
+                Some(Fragment::try_from(data.as_ref())?.id())
+            } else if Envelope::is_envelope(&data) {
+                None
+            } else {
+                continue;
+            };
+
+            let group = groups.iter_mut().find(|g| {
+                fragment_id.is_some() && g.fragment_id == fragment_id && g.chunk_type == chunk_type
+            });
+            match group {
+                Some(group) => {
+                    group.locations.push(location);
+                    group.data.push(data);
+                    group.corrected += corrected;
+                }
+                None => groups.push(Group {
+                    chunk_type,
+                    fragment_id,
+                    locations: vec![location],
+                    data: vec![data],
+                    corrected,
+                }),
+            }
+        }
+
+        let mut messages = Vec::new();
+        for group in groups {
+            for payload in Fragment::reassemble(&group.data)? {
+                messages.push(Self {
+                    chunk_type: group.chunk_type.clone(),
+                    envelope: Envelope::try_from(payload.as_ref())?,
+                    locations: group.locations.clone(),
+                    corrected: group.corrected,
+                });
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Get the chunk type code that labels the message.
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    /// Get the envelope of the message.
+    pub fn envelope(&self) -> &Envelope {
+        &self.envelope
+    }
+
+    /// Get the id of the message, messages that were written before ids existed have none.
+    pub fn id(&self) -> Option<u32> {
+        self.envelope.id()
+    }
+
+    /// Get the locations of the pieces of the message, in the order in which they appear.
+    pub fn locations(&self) -> &[Location] {
+        &self.locations
+    }
+
+    /// Get the number of corrupted bytes that were corrected in the pieces of the message.
+    pub fn corrected(&self) -> usize {
+        self.corrected
+    }
+
+    /// Remove every piece of the message from the given PNG image, which must be the image in
+    /// which the message was found.
+    pub fn remove(&self, png: &Png) -> Result<Png> {
+        let mut png = png.clone();
+        let mut index = 0;
+        png.retain(|_| {
+            let hidden = self
+                .locations
+                .iter()
+                .any(|l| l.chunk_index() == Some(index));
+            index += 1;
+            !hidden
+        })?;
+
+        let hidden: Vec<usize> = self
+            .locations
+            .iter()
+            .filter_map(|l| match l {
+                Location::ImageData(i) => Some(*i),
+                _ => None,
+            })
+            .collect();
+        if !hidden.is_empty() {
+            let kept: Vec<IdatPayload> = IdatPayload::read_all(&png)?
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| !hidden.contains(i))
+                .map(|(_, payload)| payload)
+                .collect();
+            png = IdatPayload::write_all(&png, &kept)?;
+        }
+
+        if self.locations.contains(&Location::Palette) {
+            png = PalettePayload::clear(&png)?;
+        }
+        Ok(png)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chunk;
+    use std::str::FromStr;
+
+    fn testing_envelope(id: u32, text: &str) -> Vec<u8> {
+        let mut envelope = Envelope::from_text(text);
+        envelope.set_id(Some(id));
+        envelope.as_bytes().unwrap()
+    }
+
+    fn testing_png() -> Png {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let end_type = ChunkType::from_str("IEND").unwrap();
+        let mut png = Png::try_from(&crate::png::tests::PNG_FILE[..]).unwrap();
+
+        // a plain message, a disguised message and a fragmented message with error correction
+        let mut chunks = vec![
+            Chunk::new(chunk_type.clone(), testing_envelope(1, "first")).unwrap(),
+            Disguise::Comment
+                .wrap(&chunk_type, &testing_envelope(2, "second"))
+                .unwrap(),
+        ];
+        let ecc = ReedSolomon::with_redundancy(20).unwrap();
+        for fragment in Fragment::split(7, &testing_envelope(3, "third"), 16) {
+            let data = ecc.encode(&fragment.as_bytes());
+            chunks.push(Chunk::new(chunk_type.clone(), data).unwrap());
+        }
+        for chunk in chunks {
+            png.insert_before(&end_type, chunk).unwrap();
+        }
+
+        let payload = IdatPayload::new(chunk_type, testing_envelope(4, "fourth"));
+        IdatPayload::append(&png, &[payload]).unwrap()
+    }
+
+    #[test]
+    fn test_find_all() {
+        let png = testing_png();
+        let messages = Message::find_all(&png).unwrap();
+        let ids: Vec<Option<u32>> = messages.iter().map(|m| m.id()).collect();
+        assert_eq!(ids, vec![Some(1), Some(2), Some(3), Some(4)]);
+        assert!(messages
+            .iter()
+            .all(|m| m.chunk_type().to_string() == "ruSt"));
+
+        let first = png.chunks().len() - 1 - messages[2].locations().len();
+        assert_eq!(messages[0].locations(), &[Location::Chunk(first - 2)]);
+        assert_eq!(
+            messages[1].locations(),
+            &[Location::Disguised(first - 1, Disguise::Comment)]
+        );
+        assert!(messages[2].locations().len() > 1);
+        assert_eq!(messages[2].envelope().to_string(), "third");
+        assert_eq!(messages[3].locations(), &[Location::ImageData(0)]);
+        assert_eq!(messages[3].locations()[0].to_string(), "idat");
+    }
+
+    #[test]
+    fn test_remove() {
+        let png = testing_png();
+        let messages = Message::find_all(&png).unwrap();
+        for (i, message) in messages.iter().enumerate() {
+            let stripped = message.remove(&png).unwrap();
+            let mut expected = messages.clone();
+            expected.remove(i);
+            let ids: Vec<Option<u32>> = Message::find_all(&stripped)
+                .unwrap()
+                .iter()
+                .map(|m| m.id())
+                .collect();
+            let expected: Vec<Option<u32>> = expected.iter().map(|m| m.id()).collect();
+            assert_eq!(ids, expected);
+        }
+    }
+
+    #[test]
+    fn test_plain_chunks_are_skipped() {
+        let png = Png::try_from(&crate::png::tests::PNG_FILE[..]).unwrap();
+        assert!(Message::find_all(&png).unwrap().is_empty());
+    }
+}
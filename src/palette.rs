@@ -0,0 +1,498 @@
+use crate::{Chunk, ChunkType, Error, Ihdr, Image, Png, Result};
+use std::convert::TryFrom;
+
+/// The palette ("PLTE" chunk) of an indexed-color PNG image
+/// ([PLTE Palette](http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.PLTE)).
+///
+/// A palette holds between 1 and 256 entries of red, green and blue samples.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::{Chunk, ChunkType, Plte};
+/// # use std::convert::TryFrom;
+/// # use std::str::FromStr;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let chunk = Chunk::new(ChunkType::from_str("PLTE")?, vec![255, 0, 0, 0, 0, 255])?;
+///     let plte = Plte::try_from(&chunk)?;
+///     assert_eq!(&[[255, 0, 0], [0, 0, 255]], plte.entries());
+///     assert_eq!(chunk.as_bytes(), plte.to_chunk()?.as_bytes());
+///     Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plte {
+    entries: Vec<[u8; 3]>,
+}
+
+impl Plte {
+    pub const CHUNK_TYPE: [u8; 4] = *b"PLTE";
+    pub const MAX_ENTRIES: usize = 256;
+
+    /// Create a palette from the given entries.
+    pub fn new(entries: Vec<[u8; 3]>) -> Result<Self> {
+        if entries.is_empty() || entries.len() > Self::MAX_ENTRIES {
+            return Err(Error::InvalidPalette);
+        }
+        Ok(Self { entries })
+    }
+
+    /// Get the entries of the palette.
+    pub fn entries(&self) -> &[[u8; 3]] {
+        &self.entries
+    }
+
+    /// Get the number of entries of the palette.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check whether the palette has no entries, which is never the case for a valid palette.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Get the "PLTE" chunk that holds the palette.
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        Chunk::new(
+            ChunkType::try_from(Self::CHUNK_TYPE)?,
+            self.entries.concat(),
+        )
+    }
+}
+
+impl std::convert::TryFrom<&Chunk> for Plte {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = chunk.data();
+        if chunk.chunk_type().bytes() != Self::CHUNK_TYPE || data.len() % 3 != 0 {
+            return Err(Error::InvalidPalette);
+        }
+        Self::new(data.chunks(3).map(|e| [e[0], e[1], e[2]]).collect())
+    }
+}
+
+/// PalettePayload holds data that is hidden in the order of the entries of the palette of an
+/// indexed-color image.
+///
+/// The entries of a palette can be stored in any order, as long as the palette indices of the
+/// pixels are changed accordingly, so the image looks exactly the same. A palette with `n`
+/// distinct entries can be ordered in `n!` ways, which gives `log2(n!)` bits to hide data in,
+/// about 210 bytes for a palette of 256 entries.
+///
+/// The entries are first sorted by their red, green, blue and alpha samples. The hidden bytes
+/// are read as a big-endian number which is written in the factorial number system, and the
+/// digits select the entries of the new order from the sorted ones (the Lehmer code of the
+/// permutation). The number starts with the chunk type code that labels the payload and the
+/// length of the data as 2 bytes in big-endian. The "tRNS", "hIST" and "bKGD" chunks are
+/// reordered together with the palette.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::{Chunk, ChunkType, Ihdr, Image, PalettePayload, Png};
+/// # use std::convert::TryFrom;
+/// # use std::str::FromStr;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let ihdr = Chunk::new(ChunkType::from_str("IHDR")?, vec![0, 0, 0, 64, 0, 0, 0, 1, 8, 3, 0, 0, 0])?;
+///     let idat = Image::new(Ihdr::try_from(&ihdr)?, (0..64).collect())?.encode()?;
+///     let png = Png::from_chunks(vec![
+///         ihdr,
+///         Chunk::new(ChunkType::from_str("PLTE")?, (0..192).collect())?,
+///         Chunk::new(ChunkType::from_str("IDAT")?, idat)?,
+///         Chunk::new(ChunkType::from_str("IEND")?, Vec::new())?,
+///     ]);
+///
+///     let payload = PalettePayload::new(ChunkType::from_str("ruSt")?, b"HIDDEN".to_vec());
+///     let hidden = payload.write(&png)?;
+///     assert_eq!(Some(payload), PalettePayload::read(&hidden)?);
+///     Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PalettePayload {
+    chunk_type: ChunkType,
+    data: Vec<u8>,
+}
+
+impl PalettePayload {
+    /// The number of bytes of the chunk type code and the length that precede the data.
+    pub const HEADER_LENGTH: usize = 6;
+
+    /// Create a new payload with the given chunk type code and data.
+    pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Self {
+        Self { chunk_type, data }
+    }
+
+    /// Get the chunk type code that labels the payload.
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    /// Get the hidden bytes.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Get the number of bytes of data that can be hidden in the palette of the given PNG image.
+    pub fn capacity(png: &Png) -> Result<usize> {
+        let palette = Palette::from_png(png)?;
+        Ok(number_capacity(palette.len()).saturating_sub(Self::HEADER_LENGTH))
+    }
+
+    /// Read the payload that is hidden in the order of the palette entries of the given PNG
+    /// image, `None` is returned if the order does not hold a payload.
+    pub fn read(png: &Png) -> Result<Option<Self>> {
+        let palette = Palette::from_png(png)?;
+        let sorted = palette.sorted()?;
+
+        // the Lehmer code of the permutation from the sorted entries to the stored ones
+        let mut remaining = sorted;
+        let mut digits = Vec::with_capacity(palette.len());
+        for entry in &palette.entries {
+            let pos = remaining.iter().position(|e| e == entry).unwrap();
+            digits.push(pos as u32);
+            remaining.remove(pos);
+        }
+
+        let width = number_capacity(palette.len());
+        let mut number = vec![0u8; width];
+        for (i, &digit) in digits.iter().enumerate() {
+            let radix = (palette.len() - i) as u32;
+            if multiply_add(&mut number, radix, digit) {
+                return Ok(None);
+            }
+        }
+        if number.len() < Self::HEADER_LENGTH {
+            return Ok(None);
+        }
+
+        let chunk_type = match ChunkType::try_from([number[0], number[1], number[2], number[3]]) {
+            Ok(chunk_type) => chunk_type,
+            Err(_) => return Ok(None),
+        };
+        let length = u16::from_be_bytes([number[4], number[5]]) as usize;
+        let data = &number[Self::HEADER_LENGTH..];
+        if length > data.len() || data[length..].iter().any(|&b| b != 0) {
+            return Ok(None);
+        }
+        Ok(Some(Self::new(chunk_type, data[..length].to_vec())))
+    }
+
+    /// Hide the payload in the order of the palette entries of the given PNG image. Any payload
+    /// that was hidden in the palette before is replaced.
+    pub fn write(&self, png: &Png) -> Result<Png> {
+        let palette = Palette::from_png(png)?;
+        let mut remaining = palette.sorted()?;
+        let width = number_capacity(palette.len());
+        let needed = Self::HEADER_LENGTH + self.data.len();
+        if needed > width {
+            return Err(Error::InsufficientCapacity {
+                needed: self.data.len(),
+                capacity: width.saturating_sub(Self::HEADER_LENGTH),
+            });
+        }
+
+        let mut number = Vec::with_capacity(width);
+        number.extend_from_slice(&self.chunk_type.bytes());
+        number.extend_from_slice(&(self.data.len() as u16).to_be_bytes());
+        number.extend_from_slice(&self.data);
+        number.resize(width, 0);
+
+        // digits of the number in the factorial number system, the least significant first
+        let mut digits: Vec<u32> = (1..=palette.len() as u32)
+            .map(|radix| divide(&mut number, radix))
+            .collect();
+        digits.reverse();
+
+        let order: Vec<usize> = digits
+            .iter()
+            .map(|&digit| {
+                let entry = remaining.remove(digit as usize);
+                palette.entries.iter().position(|e| *e == entry).unwrap()
+            })
+            .collect();
+        palette.reorder(png, &order)
+    }
+
+    /// Sort the palette entries of the given PNG image, which removes any payload that is hidden
+    /// in their order.
+    pub fn clear(png: &Png) -> Result<Png> {
+        let palette = Palette::from_png(png)?;
+        let sorted = palette.sorted()?;
+        let order: Vec<usize> = sorted
+            .iter()
+            .map(|entry| palette.entries.iter().position(|e| e == entry).unwrap())
+            .collect();
+        palette.reorder(png, &order)
+    }
+}
+
+/// The palette entries of an image together with their alpha samples.
+struct Palette {
+    entries: Vec<[u8; 4]>,
+}
+
+impl Palette {
+    const TRANSPARENCY_CHUNK_TYPE: [u8; 4] = *b"tRNS";
+    const HISTOGRAM_CHUNK_TYPE: [u8; 4] = *b"hIST";
+    const BACKGROUND_CHUNK_TYPE: [u8; 4] = *b"bKGD";
+
+    /// Read the palette of the given indexed-color PNG image.
+    fn from_png(png: &Png) -> Result<Self> {
+        let header = png
+            .chunks()
+            .first()
+            .ok_or(Error::InvalidImageHeader)
+            .and_then(Ihdr::try_from)?;
+        if header.color_type() != Ihdr::COLOR_INDEXED {
+            return Err(Error::UnsupportedColorType(header.color_type()));
+        }
+
+        let plte = png
+            .chunks()
+            .iter()
+            .find(|c| c.chunk_type().bytes() == Plte::CHUNK_TYPE)
+            .ok_or(Error::InvalidPalette)
+            .and_then(Plte::try_from)?;
+        let alphas = png
+            .chunks()
+            .iter()
+            .find(|c| c.chunk_type().bytes() == Self::TRANSPARENCY_CHUNK_TYPE)
+            .map(|c| c.data())
+            .unwrap_or(&[]);
+        let entries = plte
+            .entries()
+            .iter()
+            .enumerate()
+            .map(|(i, e)| [e[0], e[1], e[2], alphas.get(i).copied().unwrap_or(255)])
+            .collect();
+        Ok(Self { entries })
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Get the entries in sorted order, the order can only carry data if all entries are
+    /// distinct.
+    fn sorted(&self) -> Result<Vec<[u8; 4]>> {
+        let mut sorted = self.entries.clone();
+        sorted.sort_unstable();
+        if let Some(pos) = sorted.windows(2).position(|w| w[0] == w[1]) {
+            let index = self.entries.iter().position(|e| *e == sorted[pos]).unwrap();
+            return Err(Error::DuplicatePaletteEntry(index));
+        }
+        Ok(sorted)
+    }
+
+    /// Store the entries in the given order, `order[i]` is the current index of the entry that is
+    /// moved to index `i`. The palette indices of the pixels and the chunks that refer to the
+    /// palette entries are changed accordingly.
+    fn reorder(&self, png: &Png, order: &[usize]) -> Result<Png> {
+        let mut new_index = vec![0u16; order.len()];
+        for (i, &old) in order.iter().enumerate() {
+            new_index[old] = i as u16;
+        }
+
+        let mut reordered = png.clone();
+        for mut chunk in reordered.chunks_mut() {
+            let data = chunk.data();
+            let data = match chunk.chunk_type().bytes() {
+                Plte::CHUNK_TYPE => order
+                    .iter()
+                    .flat_map(|&i| self.entries[i][..3].to_vec())
+                    .collect(),
+                Self::TRANSPARENCY_CHUNK_TYPE => {
+                    let mut alphas: Vec<u8> = order.iter().map(|&i| self.entries[i][3]).collect();
+                    while alphas.last() == Some(&255) {
+                        alphas.pop();
+                    }
+                    alphas
+                }
+                Self::HISTOGRAM_CHUNK_TYPE if data.len() == 2 * order.len() => order
+                    .iter()
+                    .flat_map(|&i| data[2 * i..2 * i + 2].to_vec())
+                    .collect(),
+                Self::BACKGROUND_CHUNK_TYPE
+                    if data.len() == 1 && (data[0] as usize) < order.len() =>
+                {
+                    vec![new_index[data[0] as usize] as u8]
+                }
+                _ => continue,
+            };
+            chunk.set_data(data)?;
+        }
+
+        let mut image = Image::from_png(png)?;
+        let samples = image
+            .samples()
+            .iter()
+            .map(|&s| {
+                new_index
+                    .get(s as usize)
+                    .copied()
+                    .ok_or(Error::InvalidImageData)
+            })
+            .collect::<Result<Vec<u16>>>()?;
+        image.set_samples(&samples)?;
+        image.to_png(&reordered)
+    }
+}
+
+/// Get the number of whole bytes of a number that is always smaller than `n!`.
+fn number_capacity(n: usize) -> usize {
+    let mut factorial = vec![1u8];
+    for k in 2..=n as u32 {
+        factorial.insert(0, 0);
+        multiply_add(&mut factorial, k, 0);
+    }
+    let leading = factorial.iter().take_while(|&&b| b == 0).count();
+    let bits = (factorial.len() - leading) * 8
+        - factorial
+            .get(leading)
+            .map_or(8, |b| b.leading_zeros() as usize);
+    bits.saturating_sub(1) / 8
+}
+
+/// Multiply the given big-endian number by `factor` and add `term` to it in place. Returns
+/// whether the result overflowed the width of the number.
+fn multiply_add(number: &mut [u8], factor: u32, term: u32) -> bool {
+    let mut carry = term;
+    for byte in number.iter_mut().rev() {
+        let value = *byte as u32 * factor + carry;
+        *byte = value as u8;
+        carry = value >> 8;
+    }
+    carry != 0
+}
+
+/// Divide the given big-endian number by `divisor` in place and return the remainder.
+fn divide(number: &mut [u8], divisor: u32) -> u32 {
+    let mut remainder = 0;
+    for byte in number.iter_mut() {
+        let value = (remainder << 8) | *byte as u32;
+        *byte = (value / divisor) as u8;
+        remainder = value % divisor;
+    }
+    remainder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::tests::chunk;
+    use std::str::FromStr;
+
+    /// An indexed-color image with a palette of the given number of entries that uses every
+    /// entry, together with a "tRNS" chunk.
+    fn testing_png(entries: usize) -> Png {
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&16u32.to_be_bytes());
+        ihdr.extend_from_slice(&16u32.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 3, 0, 0, 0]);
+        let header = Ihdr::try_from(&chunk("IHDR", ihdr.clone())).unwrap();
+        let pixels = (0..256).map(|i| (i * 7 % entries) as u8).collect();
+        let idat = Image::new(header, pixels).unwrap().encode().unwrap();
+        let palette = (0..entries)
+            .flat_map(|i| vec![(i * 37) as u8, (i * 11) as u8, (i / 2) as u8])
+            .collect();
+
+        Png::from_chunks(vec![
+            chunk("IHDR", ihdr),
+            chunk("PLTE", palette),
+            chunk("tRNS", vec![0, 128]),
+            chunk("IDAT", idat),
+            chunk("IEND", Vec::new()),
+        ])
+    }
+
+    /// The colors of the pixels of the given image.
+    fn colors(png: &Png) -> Vec<[u8; 4]> {
+        let palette = Palette::from_png(png).unwrap();
+        Image::from_png(png)
+            .unwrap()
+            .samples()
+            .iter()
+            .map(|&s| palette.entries[s as usize])
+            .collect()
+    }
+
+    fn testing_payload(data: &[u8]) -> PalettePayload {
+        PalettePayload::new(ChunkType::from_str("ruSt").unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_plte() {
+        let plte = Plte::try_from(&chunk("PLTE", vec![1, 2, 3, 4, 5, 6])).unwrap();
+        assert_eq!(plte.len(), 2);
+        assert_eq!(plte.entries(), &[[1, 2, 3], [4, 5, 6]]);
+        assert!(Plte::try_from(&chunk("PLTE", vec![1, 2])).is_err());
+        assert!(Plte::try_from(&chunk("PLTE", Vec::new())).is_err());
+    }
+
+    #[test]
+    fn test_capacity() {
+        assert_eq!(number_capacity(1), 0);
+        assert_eq!(number_capacity(8), 1);
+        // 256! is slightly below 2^1684
+        assert_eq!(number_capacity(256), 210);
+        assert_eq!(PalettePayload::capacity(&testing_png(256)).unwrap(), 204);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let png = testing_png(256);
+        let data: Vec<u8> = (0..204).map(|i| (i * 13) as u8).collect();
+        let hidden = testing_payload(&data).write(&png).unwrap();
+        assert_eq!(
+            PalettePayload::read(&hidden).unwrap(),
+            Some(testing_payload(&data))
+        );
+        assert_eq!(colors(&hidden), colors(&png));
+    }
+
+    #[test]
+    fn test_clear() {
+        let png = testing_png(64);
+        assert_eq!(PalettePayload::read(&png).unwrap(), None);
+        let hidden = testing_payload(b"hidden").write(&png).unwrap();
+        let cleared = PalettePayload::clear(&hidden).unwrap();
+        assert_eq!(PalettePayload::read(&cleared).unwrap(), None);
+        assert_eq!(colors(&cleared), colors(&png));
+    }
+
+    #[test]
+    fn test_insufficient_capacity() {
+        let png = testing_png(16);
+        let capacity = PalettePayload::capacity(&png).unwrap();
+        let data = vec![1u8; capacity + 1];
+        match testing_payload(&data).write(&png) {
+            Err(Error::InsufficientCapacity {
+                needed,
+                capacity: c,
+            }) => {
+                assert_eq!((needed, c), (capacity + 1, capacity));
+            }
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_duplicate_entries() {
+        let png = testing_png(16);
+        let mut chunks = png.chunks().to_vec();
+        let mut palette = chunks[1].data().to_vec();
+        palette.copy_within(42..45, 45);
+        chunks[1] = chunk("PLTE", palette);
+        match testing_payload(b"x").write(&Png::from_chunks(chunks)) {
+            Err(Error::DuplicatePaletteEntry(14)) => {}
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+    }
+}
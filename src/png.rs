@@ -1,16 +1,26 @@
-use crate::{Chunk, Error, Result};
-use std::convert::TryInto;
-use std::io::{Cursor, Read};
+use crate::raw_chunk::type_name;
+use crate::{Chunk, ChunkDataMut, ChunkType, Error, RawChunk, Result};
+use std::convert::TryFrom;
+use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "PngFields")
+)]
 pub struct Png {
     chunks: Vec<Chunk>,
+    // chunks that could not be parsed, each with the index of the chunk that follows it
+    raw_chunks: Vec<(usize, RawChunk)>,
 }
 
 /// Png represents a PNG file as a list of chunks, the details can be found in the specifications
 /// ([PNG Structure](http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html)).
 ///
-/// Only valid PNG files can be parsed.
+/// Only valid PNG files can be parsed, unless they are parsed leniently, which keeps chunks with
+/// an invalid type code or checksum as raw chunks. Raw chunks stay between the chunks that
+/// surround them, and are written back unchanged, so that the rest of the file can be edited.
 ///
 /// The first eight bytes of a PNG file always contain the same signature. This is used to indicate
 /// the remainder of the file contains a PNG image, which consists of a series of chunks begining
@@ -23,7 +33,50 @@ impl Png {
 
     /// Construct the `Png` object from the list of input chunks.
     pub fn from_chunks(chunks: Vec<Chunk>) -> Self {
-        Self { chunks }
+        Self {
+            chunks,
+            raw_chunks: Vec::new(),
+        }
+    }
+
+    /// Parse a PNG file and keep the chunks whose type code or checksum is not valid as raw
+    /// chunks instead of failing. The signature, the framing of every chunk and the type of the
+    /// first chunk must still be valid, and so must the checksums of the "IHDR" and "IEND" chunks,
+    /// which can not be kept as raw chunks.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use pmsg::{Chunk, ChunkType, Png};
+    /// # use std::convert::TryFrom;
+    /// # use std::str::FromStr;
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    ///     let mut bytes = Png::from_chunks(vec![
+    ///         Chunk::new(ChunkType::from_str("IHDR")?, vec![0; 13])?,
+    ///         Chunk::new(ChunkType::from_str("ruSt")?, b"data".to_vec())?,
+    ///         Chunk::new(ChunkType::from_str("IEND")?, Vec::new())?,
+    ///     ])
+    ///     .as_bytes();
+    ///     // corrupt the checksum of the "ruSt" chunk
+    ///     bytes[8 + 25 + 12 + 3] ^= 1;
+    ///     assert!(Png::try_from(bytes.as_ref()).is_err());
+    ///
+    ///     let png = Png::from_bytes_lenient(&bytes)?;
+    ///     assert_eq!(2, png.chunks().len());
+    ///     assert_eq!(1, png.raw_chunks()[0].0);
+    ///     assert_eq!(bytes, png.as_bytes());
+    ///
+    ///     // corrupt the checksum of the "IEND" chunk
+    ///     let end = bytes.len() - 1;
+    ///     bytes[end] ^= 1;
+    ///     assert!(Png::from_bytes_lenient(&bytes).is_err());
+    ///     Ok(())
+    /// # }
+    /// ```
+    pub fn from_bytes_lenient(data: &[u8]) -> Result<Self> {
+        Self::parse(data, true)
     }
 
     /// Add a chunk to the end of the list of chunk contained in the object.
@@ -31,13 +84,176 @@ impl Png {
         self.chunks.push(chunk);
     }
 
-    /// Remove the first encountered chunk with the given chunk type code.
+    /// Insert a chunk at the given index, shifting the chunks after it. The index may be equal to
+    /// the number of chunks, which adds the chunk to the end.
+    ///
+    /// Nothing can be inserted before the "IHDR" chunk or after the "IEND" chunk, and an "IHDR"
+    /// or "IEND" chunk can only be inserted where it belongs.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use pmsg::{Chunk, ChunkType, Png};
+    /// # use std::str::FromStr;
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    ///     let mut png = Png::from_chunks(vec![
+    ///         Chunk::new(ChunkType::from_str("IHDR")?, vec![0; 13])?,
+    ///         Chunk::new(ChunkType::from_str("IEND")?, Vec::new())?,
+    ///     ]);
+    ///     let chunk = Chunk::new(ChunkType::from_str("ruSt")?, b"data".to_vec())?;
+    ///     assert!(png.insert_chunk(0, chunk.clone()).is_err());
+    ///     assert!(png.insert_chunk(2, chunk.clone()).is_err());
+    ///     png.insert_chunk(1, chunk)?;
+    ///     assert_eq!(3, png.chunks().len());
+    ///     Ok(())
+    /// # }
+    /// ```
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) -> Result<()> {
+        if index > self.chunks.len() {
+            return Err(Error::ChunkIndexOutOfRange(index));
+        }
+        self.chunks.insert(index, chunk);
+        if let Err(e) = self.check_order() {
+            self.chunks.remove(index);
+            return Err(e);
+        }
+        // raw chunks that follow the chunk before the index stay in front of the new chunk
+        for (next, _) in &mut self.raw_chunks {
+            if *next > index {
+                *next += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Insert a chunk right before the first chunk with the given chunk type code.
+    pub fn insert_before(&mut self, chunk_type: &ChunkType, chunk: Chunk) -> Result<()> {
+        let index = self
+            .chunks
+            .iter()
+            .position(|c| c.chunk_type() == chunk_type)
+            .ok_or(Error::ChunkTypeNotFound)?;
+        self.insert_chunk(index, chunk)
+    }
+
+    /// Insert a chunk right after the last chunk with the given chunk type code, so that a chunk
+    /// that is inserted after "IDAT" follows all the image data.
+    pub fn insert_after(&mut self, chunk_type: &ChunkType, chunk: Chunk) -> Result<()> {
+        let index = self
+            .chunks
+            .iter()
+            .rposition(|c| c.chunk_type() == chunk_type)
+            .ok_or(Error::ChunkTypeNotFound)?;
+        self.insert_chunk(index + 1, chunk)
+    }
+
+    /// Replace the chunk at the given index and return the replaced chunk. The "IHDR" and "IEND"
+    /// chunks can only be replaced by chunks of the same type.
+    pub fn replace_chunk(&mut self, index: usize, chunk: Chunk) -> Result<Chunk> {
+        if index >= self.chunks.len() {
+            return Err(Error::ChunkIndexOutOfRange(index));
+        }
+        let old_type = self.chunks[index].chunk_type().bytes();
+        if (old_type == Self::START_CHUNK_TYPE || old_type == Self::END_CHUNK_TYPE)
+            && chunk.chunk_type().bytes() != old_type
+        {
+            return Err(Error::InvalidChunkOrder);
+        }
+        let replaced = std::mem::replace(&mut self.chunks[index], chunk);
+        if let Err(e) = self.check_order() {
+            self.chunks[index] = replaced;
+            return Err(e);
+        }
+        Ok(replaced)
+    }
+
+    /// Remove every chunk with the given chunk type code and return the removed chunks. The
+    /// "IHDR" and "IEND" chunks can not be removed.
+    pub fn remove_all(&mut self, chunk_type: &ChunkType) -> Result<Vec<Chunk>> {
+        self.remove_where(|c| c.chunk_type() == chunk_type)
+    }
+
+    /// Keep only the chunks for which the given predicate returns `true`, in their order. Nothing
+    /// is removed if the predicate returns `false` for the "IHDR" or the "IEND" chunk.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use pmsg::{Chunk, ChunkType, Png};
+    /// # use std::str::FromStr;
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    ///     let mut png = Png::from_chunks(vec![
+    ///         Chunk::new(ChunkType::from_str("IHDR")?, vec![0; 13])?,
+    ///         Chunk::new(ChunkType::from_str("ruSt")?, b"data".to_vec())?,
+    ///         Chunk::new(ChunkType::from_str("IEND")?, Vec::new())?,
+    ///     ]);
+    ///     assert!(png.retain(|c| c.length() > 0).is_err());
+    ///     assert_eq!(3, png.chunks().len());
+    ///     png.retain(|c| c.chunk_type().is_critical())?;
+    ///     assert_eq!(2, png.chunks().len());
+    ///     Ok(())
+    /// # }
+    /// ```
+    pub fn retain<F: FnMut(&Chunk) -> bool>(&mut self, mut f: F) -> Result<()> {
+        self.remove_where(|c| !f(c)).map(|_| ())
+    }
+
+    /// Remove every chunk for which the given predicate returns `true` and return the removed
+    /// chunks. Raw chunks stay after the last chunk before them that is kept. Nothing is removed
+    /// if the "IHDR" or the "IEND" chunk would be.
+    fn remove_where<F: FnMut(&Chunk) -> bool>(&mut self, mut f: F) -> Result<Vec<Chunk>> {
+        let removed: Vec<bool> = self.chunks.iter().map(&mut f).collect();
+        if self
+            .chunks
+            .iter()
+            .zip(&removed)
+            .any(|(c, &r)| r && Self::is_boundary(c))
+        {
+            return Err(Error::InvalidChunkOrder);
+        }
+        for (next, _) in &mut self.raw_chunks {
+            *next -= removed[..*next].iter().filter(|&&r| r).count();
+        }
+        let (removed, kept) = self
+            .chunks
+            .drain(..)
+            .zip(removed)
+            .partition::<Vec<_>, _>(|(_, r)| *r);
+        self.chunks = kept.into_iter().map(|(c, _)| c).collect();
+        Ok(removed.into_iter().map(|(c, _)| c).collect())
+    }
+
+    /// Check whether the given chunk is an "IHDR" or an "IEND" chunk, which must stay in place.
+    fn is_boundary(chunk: &Chunk) -> bool {
+        Self::is_boundary_type(chunk.chunk_type())
+    }
+
+    /// Check whether the given chunk type code is the one of the "IHDR" or the "IEND" chunk.
+    fn is_boundary_type(chunk_type: &ChunkType) -> bool {
+        let bytes = chunk_type.bytes();
+        bytes == Self::START_CHUNK_TYPE || bytes == Self::END_CHUNK_TYPE
+    }
+
+    /// Remove the first encountered chunk with the given chunk type code. The "IHDR" and "IEND"
+    /// chunks can not be removed.
     pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
         let pos = self
             .chunks
             .iter()
             .position(|c| c.chunk_type().bytes() == chunk_type.as_bytes())
             .ok_or(Error::ChunkTypeNotFound)?;
+        if Self::is_boundary(&self.chunks[pos]) {
+            return Err(Error::InvalidChunkOrder);
+        }
+        for (next, _) in &mut self.raw_chunks {
+            if *next > pos {
+                *next -= 1;
+            }
+        }
         Ok(self.chunks.remove(pos))
     }
 
@@ -51,6 +267,107 @@ impl Png {
         &self.chunks
     }
 
+    /// Return the chunks that could not be parsed when the PNG file was parsed leniently, each
+    /// with the index of the chunk that follows it.
+    pub fn raw_chunks(&self) -> &[(usize, RawChunk)] {
+        &self.raw_chunks
+    }
+
+    /// Return every chunk of the PNG file in the order in which it is written, together with the
+    /// index of the chunk in `chunks()` if it was parsed.
+    pub fn file_chunks(&self) -> Vec<(Option<usize>, RawChunk)> {
+        let mut raw_chunks = self.raw_chunks.iter().peekable();
+        let mut file_chunks = Vec::new();
+        for index in 0..=self.chunks.len() {
+            while let Some((_, raw)) = raw_chunks.next_if(|(next, _)| *next == index) {
+                file_chunks.push((None, raw.clone()));
+            }
+            if let Some(chunk) = self.chunks.get(index) {
+                file_chunks.push((Some(index), RawChunk::from(chunk)));
+            }
+        }
+        file_chunks
+    }
+
+    /// Return an iterator over the chunks in the PNG image for changing them in place. The data
+    /// of every chunk can be changed, but the type code can only be changed to keep the "IHDR"
+    /// chunk first and the "IEND" chunk last.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use pmsg::{Chunk, ChunkType, Png};
+    /// # use std::str::FromStr;
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    ///     let mut png = Png::from_chunks(vec![
+    ///         Chunk::new(ChunkType::from_str("IHDR")?, vec![0; 13])?,
+    ///         Chunk::new(ChunkType::from_str("ruSt")?, b"data".to_vec())?,
+    ///         Chunk::new(ChunkType::from_str("IEND")?, Vec::new())?,
+    ///     ]);
+    ///     for mut chunk in png.chunks_mut() {
+    ///         if chunk.chunk_type().is_critical() {
+    ///             assert!(chunk.set_chunk_type(ChunkType::from_str("teXt")?).is_err());
+    ///         } else {
+    ///             chunk.set_data(b"new data".to_vec())?;
+    ///         }
+    ///     }
+    ///     assert_eq!(b"new data", png.chunks()[1].data());
+    ///     Ok(())
+    /// # }
+    /// ```
+    pub fn chunks_mut(&mut self) -> impl Iterator<Item = ChunkMut<'_>> {
+        self.chunks.iter_mut().map(|chunk| ChunkMut { chunk })
+    }
+
+    /// Return an iterator over the chunks with the given chunk type code, in their order.
+    pub fn chunks_by_type<'a>(
+        &'a self,
+        chunk_type: &'a ChunkType,
+    ) -> impl Iterator<Item = &'a Chunk> + 'a {
+        self.chunks
+            .iter()
+            .filter(move |c| c.chunk_type() == chunk_type)
+    }
+
+    /// Return the offset in the PNG file at which the chunk at the given index starts.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use pmsg::{Chunk, ChunkType, Png};
+    /// # use std::str::FromStr;
+    /// #
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    ///     let png = Png::from_chunks(vec![
+    ///         Chunk::new(ChunkType::from_str("IHDR")?, vec![0; 13])?,
+    ///         Chunk::new(ChunkType::from_str("IEND")?, Vec::new())?,
+    ///     ]);
+    ///     assert_eq!(Some(8), png.chunk_offset(0));
+    ///     assert_eq!(Some(33), png.chunk_offset(1));
+    ///     assert_eq!(None, png.chunk_offset(2));
+    ///     Ok(())
+    /// # }
+    /// ```
+    pub fn chunk_offset(&self, index: usize) -> Option<usize> {
+        if index >= self.chunks.len() {
+            return None;
+        }
+        let offset = self.chunks[..index]
+            .iter()
+            .map(|c| 12 + c.length() as usize)
+            .sum::<usize>();
+        let raw_offset = self
+            .raw_chunks
+            .iter()
+            .filter(|(next, _)| *next <= index)
+            .map(|(_, c)| 12 + c.length() as usize)
+            .sum::<usize>();
+        Some(Self::STANDARD_HEADER.len() + offset + raw_offset)
+    }
+
     /// Return the chunk with the given chunk type code.
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
         self.chunks
@@ -58,14 +375,34 @@ impl Png {
             .find(|c| c.chunk_type().bytes() == chunk_type.as_bytes())
     }
 
+    /// Check that an "IHDR" chunk can only be the first chunk and an "IEND" chunk can only be the
+    /// last chunk.
+    fn check_order(&self) -> Result<()> {
+        let last = self.chunks.len().saturating_sub(1);
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            let bytes = chunk.chunk_type().bytes();
+            if (bytes == Self::START_CHUNK_TYPE && i != 0)
+                || (bytes == Self::END_CHUNK_TYPE && i != last)
+            {
+                return Err(Error::InvalidChunkOrder);
+            }
+        }
+        Ok(())
+    }
+
     /// Return the PNG file as bytes.
     pub fn as_bytes(&self) -> Vec<u8> {
-        let chunks_bytes: Vec<u8> = self.chunks.iter().flat_map(|c| c.as_bytes()).collect();
-        Png::STANDARD_HEADER
-            .iter()
-            .chain(chunks_bytes.iter())
-            .copied()
-            .collect()
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        let mut raw_chunks = self.raw_chunks.iter().peekable();
+        for index in 0..=self.chunks.len() {
+            while let Some((_, raw)) = raw_chunks.next_if(|(next, _)| *next == index) {
+                bytes.extend(raw.as_bytes());
+            }
+            if let Some(chunk) = self.chunks.get(index) {
+                bytes.extend(chunk.as_bytes());
+            }
+        }
+        bytes
     }
 }
 
@@ -78,60 +415,232 @@ impl std::fmt::Display for Png {
     }
 }
 
+/// ChunkMut gives mutable access to a chunk of a PNG image, which is returned by
+/// `Png::chunks_mut`. The chunk can be read through it, and its data can be changed freely, but
+/// its type code can not be changed to or from "IHDR" or "IEND".
+pub struct ChunkMut<'a> {
+    chunk: &'a mut Chunk,
+}
+
+impl ChunkMut<'_> {
+    /// Replace the data of the chunk, the length and the checksum are updated to match.
+    pub fn set_data(&mut self, chunk_data: Vec<u8>) -> Result<()> {
+        self.chunk.set_data(chunk_data)
+    }
+
+    /// Get the data of the chunk for changing it in place, see `Chunk::data_mut`.
+    pub fn data_mut(&mut self) -> ChunkDataMut<'_> {
+        self.chunk.data_mut()
+    }
+
+    /// Replace the type code of the chunk, the checksum is updated to match. The type code of the
+    /// "IHDR" and the "IEND" chunk can not be changed, and no other chunk can become one of them.
+    pub fn set_chunk_type(&mut self, chunk_type: ChunkType) -> Result<()> {
+        if Png::is_boundary(self.chunk) || Png::is_boundary_type(&chunk_type) {
+            return Err(Error::InvalidChunkOrder);
+        }
+        self.chunk.set_chunk_type(chunk_type);
+        Ok(())
+    }
+}
+
+impl std::ops::Deref for ChunkMut<'_> {
+    type Target = Chunk;
+
+    fn deref(&self) -> &Chunk {
+        self.chunk
+    }
+}
+
+/// ChunkPosition tells where a chunk is inserted in a PNG image: at an index, before the first
+/// chunk with a type code, or after the last chunk with a type code, so that a chunk inserted
+/// after "IDAT" follows all the image data.
+///
+/// Positions are written as `3`, `before:IDAT` or `after:IHDR`.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::{Chunk, ChunkPosition, ChunkType, Png};
+/// # use std::str::FromStr;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let mut png = Png::from_chunks(vec![
+///         Chunk::new(ChunkType::from_str("IHDR")?, vec![0; 13])?,
+///         Chunk::new(ChunkType::from_str("IDAT")?, Vec::new())?,
+///         Chunk::new(ChunkType::from_str("IEND")?, Vec::new())?,
+///     ]);
+///     let position = ChunkPosition::from_str("before:IDAT")?;
+///     png.insert_chunk(position.index(&png)?, Chunk::new(ChunkType::from_str("ruSt")?, Vec::new())?)?;
+///     assert_eq!("ruSt", png.chunks()[1].chunk_type().to_string());
+///     assert_eq!(3, ChunkPosition::from_str("after:IDAT")?.index(&png)?);
+///     Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkPosition {
+    /// The given index in the list of chunks.
+    Index(usize),
+    /// Right before the first chunk with the given type code.
+    Before(ChunkType),
+    /// Right after the last chunk with the given type code.
+    After(ChunkType),
+}
+
+impl ChunkPosition {
+    /// Get the index in the list of chunks of the given PNG image that the position refers to.
+    pub fn index(&self, png: &Png) -> Result<usize> {
+        let chunks = png.chunks();
+        match self {
+            Self::Index(index) if *index <= chunks.len() => Ok(*index),
+            Self::Index(index) => Err(Error::ChunkIndexOutOfRange(*index)),
+            Self::Before(chunk_type) => chunks
+                .iter()
+                .position(|c| c.chunk_type() == chunk_type)
+                .ok_or(Error::ChunkTypeNotFound),
+            Self::After(chunk_type) => chunks
+                .iter()
+                .rposition(|c| c.chunk_type() == chunk_type)
+                .map(|i| i + 1)
+                .ok_or(Error::ChunkTypeNotFound),
+        }
+    }
+}
+
+impl std::fmt::Display for ChunkPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "{}", index),
+            Self::Before(chunk_type) => write!(f, "before:{}", chunk_type),
+            Self::After(chunk_type) => write!(f, "after:{}", chunk_type),
+        }
+    }
+}
+
+impl FromStr for ChunkPosition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidChunkPosition(s.to_string());
+        if let Some(chunk_type) = s.strip_prefix("before:") {
+            Ok(Self::Before(
+                ChunkType::from_str(chunk_type).map_err(|_| invalid())?,
+            ))
+        } else if let Some(chunk_type) = s.strip_prefix("after:") {
+            Ok(Self::After(
+                ChunkType::from_str(chunk_type).map_err(|_| invalid())?,
+            ))
+        } else {
+            s.parse().map(Self::Index).map_err(|_| invalid())
+        }
+    }
+}
+
 impl std::convert::TryFrom<&[u8]> for Png {
     type Error = Error;
 
     fn try_from(data: &[u8]) -> Result<Self> {
-        let mut r = Cursor::new(data);
-        let mut chunks = Vec::new();
+        Self::parse(data, false)
+    }
+}
 
-        let mut header = [0u8; 8];
-        r.read_exact(&mut header)?;
-        if header != Png::STANDARD_HEADER {
-            return Err(Self::Error::InvalidPNGFileHeader);
+impl Png {
+    /// Parse a PNG file, keeping the chunks that are not valid as raw chunks if it is lenient.
+    fn parse(data: &[u8], lenient: bool) -> Result<Self> {
+        if !data.starts_with(&Png::STANDARD_HEADER) {
+            return Err(Error::InvalidPNGFileHeader);
         }
+        let mut png = Self::from_chunks(Vec::new());
 
-        // 4-byte length buffer
-        let mut length_buf = [0u8; 4];
-        while r.position() < data.len().try_into()? {
-            r.read_exact(&mut length_buf)?;
-            let chunk_length = u32::from_be_bytes(length_buf);
-            let mut chunk_length: usize = chunk_length.try_into()?;
-            chunk_length += 4 * 2;
+        let mut offset = Png::STANDARD_HEADER.len();
+        let mut index = 0;
+        while offset < data.len() {
+            // errors are given with the location of the chunk and its type code if it was read
+            let context = move |e: Error| Error::InvalidChunk {
+                offset,
+                index,
+                chunk_type: data.get(offset + 4..offset + 8).map(type_name),
+                source: Box::new(e),
+            };
 
-            let mut chunk_data = vec![0u8; chunk_length];
-            r.read_exact(&mut chunk_data)?;
+            let raw = RawChunk::try_from(&data[offset..]).map_err(context)?;
+            let chunk_type = raw.type_bytes();
+            if index == 0 && chunk_type != Png::START_CHUNK_TYPE {
+                return Err(context(Error::InvalidStartingChunk));
+            }
+            offset += 12 + raw.data().len();
+            index += 1;
 
-            chunks.push(Chunk::try_from(
-                length_buf
-                    .iter()
-                    .chain(chunk_data.iter())
-                    .copied()
-                    .collect::<Vec<u8>>()
-                    .as_ref(),
-            )?);
-
-            if chunks[0].chunk_type().bytes() != Png::START_CHUNK_TYPE {
-                return Err(Self::Error::InvalidStartingChunk);
+            // the "IHDR" and "IEND" chunks must stay chunks, as the rest of the file relies on them
+            let boundary = chunk_type == Png::START_CHUNK_TYPE || chunk_type == Png::END_CHUNK_TYPE;
+            match Chunk::try_from(raw.clone()) {
+                Ok(chunk) => png.chunks.push(chunk),
+                Err(_) if lenient && !boundary => png.raw_chunks.push((png.chunks.len(), raw)),
+                Err(e) => return Err(context(e)),
             }
 
-            if chunks[chunks.len() - 1].chunk_type().bytes() == Png::END_CHUNK_TYPE {
+            if chunk_type == Png::END_CHUNK_TYPE {
                 break;
             }
         }
 
-        Ok(Self::from_chunks(chunks))
+        Ok(png)
+    }
+}
+
+/// The fields of a serialized PNG file, which are checked before the PNG file is deserialized:
+/// the "IHDR" chunk must come first and the "IEND" chunk last, and the raw chunks must be in
+/// order between them.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct PngFields {
+    chunks: Vec<Chunk>,
+    #[serde(default)]
+    raw_chunks: Vec<(usize, RawChunk)>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<PngFields> for Png {
+    type Error = Error;
+
+    fn try_from(fields: PngFields) -> Result<Self> {
+        let png = Self {
+            chunks: fields.chunks,
+            raw_chunks: fields.raw_chunks,
+        };
+        match png.chunks.first() {
+            Some(chunk) if chunk.chunk_type().bytes() == Self::START_CHUNK_TYPE => {}
+            _ => return Err(Error::InvalidStartingChunk),
+        }
+        if png.chunks.last().map(|c| c.chunk_type().bytes()) != Some(Self::END_CHUNK_TYPE) {
+            return Err(Error::InvalidChunkOrder);
+        }
+        png.check_order()?;
+        // raw chunks come after the "IHDR" chunk and before the "IEND" chunk
+        let mut previous = 1;
+        for (next, raw) in &png.raw_chunks {
+            if *next < previous || *next >= png.chunks.len() {
+                return Err(Error::ChunkIndexOutOfRange(*next));
+            }
+            if raw.data().len() > Chunk::MAX_LENGTH as usize {
+                return Err(Error::InvalidChunkLength);
+            }
+            previous = *next;
+        }
+        Ok(png)
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::chunk::Chunk;
     use crate::chunk_type::ChunkType;
     use std::convert::TryFrom;
     use std::str::FromStr;
 
+    #[allow(clippy::vec_init_then_push)]
     fn testing_chunks() -> Vec<Chunk> {
         let mut chunks = Vec::new();
 
@@ -147,6 +656,7 @@ mod tests {
         Png::from_chunks(chunks)
     }
 
+    #[allow(clippy::needless_question_mark)]
     fn chunk_from_strings(chunk_type: &str, data: &str) -> Result<Chunk> {
         let chunk_type = ChunkType::from_str(chunk_type)?;
         let data: Vec<u8> = data.bytes().collect();
@@ -220,6 +730,61 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_invalid_chunk_context() {
+        let mut bytes = testing_png().as_bytes();
+        let offset = testing_png().chunk_offset(1).unwrap();
+        let crc = offset + 12 + 18 - 1;
+        bytes[crc] ^= 0xff;
+        let err = Png::try_from(bytes.as_ref()).unwrap_err();
+        match &err {
+            Error::InvalidChunk {
+                offset: o,
+                index: 1,
+                chunk_type: Some(chunk_type),
+                source,
+            } => {
+                assert_eq!(*o, offset);
+                assert_eq!(chunk_type, "miDl");
+                assert!(matches!(**source, Error::InvalidCRC { .. }));
+            }
+            _ => panic!("unexpected error {:?}", err),
+        }
+        assert!(err.to_string().starts_with(&format!(
+            "chunk 1 (miDl) at offset {}: invalid CRC, expected",
+            offset
+        )));
+
+        bytes[offset + 6] = b'-';
+        let err = Png::try_from(bytes.as_ref()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "chunk 1 (mi\\x2dl) at offset {}: {}",
+                offset, "invalid chunk type, byte 2 is 0x2d instead of an ASCII letter"
+            )
+        );
+
+        let bytes = testing_png().as_bytes();
+        let end = bytes.len() - 10;
+        let err = Png::try_from(&bytes[..end]).unwrap_err();
+        let last = testing_png().chunk_offset(2).unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "chunk 2 (IEND) at offset {}: unexpected end of data, expected 31 bytes but only 21 are left",
+                last
+            )
+        );
+        assert_eq!(
+            Png::try_from(&bytes[..last + 2]).unwrap_err().to_string(),
+            format!(
+                "chunk 2 at offset {}: unexpected end of data, expected 4 bytes but only 2 are left",
+                last
+            )
+        );
+    }
+
     #[test]
     fn test_list_chunks() {
         let png = testing_png();
@@ -253,6 +818,255 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = testing_png();
+        png.insert_chunk(1, chunk_from_strings("TeSt", "Message").unwrap())
+            .unwrap();
+        assert_eq!(&png.chunks()[1].chunk_type().to_string(), "TeSt");
+        assert!(png
+            .insert_chunk(5, chunk_from_strings("TeSt", "Message").unwrap())
+            .is_err());
+        assert!(png
+            .insert_chunk(4, chunk_from_strings("TeSt", "Last").unwrap())
+            .is_err());
+        png.insert_chunk(3, chunk_from_strings("TeSt", "Last").unwrap())
+            .unwrap();
+        assert_eq!(png.chunks().len(), 5);
+    }
+
+    #[test]
+    fn test_insert_chunk_keeps_order() {
+        let mut png = testing_png();
+        let header = chunk_from_strings("IHDR", "I am another header").unwrap();
+        let end = chunk_from_strings("IEND", "I am another end").unwrap();
+        assert!(png
+            .insert_chunk(0, chunk_from_strings("TeSt", "").unwrap())
+            .is_err());
+        assert!(png
+            .insert_chunk(3, chunk_from_strings("TeSt", "").unwrap())
+            .is_err());
+        assert!(png.insert_chunk(1, header).is_err());
+        assert!(png.insert_chunk(2, end).is_err());
+        assert_eq!(png.chunks(), testing_png().chunks());
+    }
+
+    #[test]
+    fn test_insert_before_and_after() {
+        let mut png = testing_png();
+        let test_type = ChunkType::from_str("TeSt").unwrap();
+        let end_type = ChunkType::from_str("IEND").unwrap();
+        png.insert_before(&end_type, chunk_from_strings("TeSt", "1").unwrap())
+            .unwrap();
+        png.insert_before(&end_type, chunk_from_strings("TeSt", "2").unwrap())
+            .unwrap();
+        png.insert_after(&test_type, chunk_from_strings("TeSt", "3").unwrap())
+            .unwrap();
+        png.insert_before(&test_type, chunk_from_strings("TeSt", "0").unwrap())
+            .unwrap();
+        let data: Vec<String> = png
+            .chunks_by_type(&test_type)
+            .map(|c| c.data_as_string().unwrap())
+            .collect();
+        assert_eq!(data, vec!["0", "1", "2", "3"]);
+        assert_eq!(png.chunks()[2].data_as_string().unwrap(), "0");
+        assert!(png
+            .insert_after(&end_type, chunk_from_strings("TeSt", "4").unwrap())
+            .is_err());
+        assert!(png
+            .insert_before(
+                &ChunkType::from_str("miSs").unwrap(),
+                chunk_from_strings("TeSt", "4").unwrap()
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_replace_chunk() {
+        let mut png = testing_png();
+        let replaced = png
+            .replace_chunk(1, chunk_from_strings("TeSt", "Message").unwrap())
+            .unwrap();
+        assert_eq!(&replaced.chunk_type().to_string(), "miDl");
+        assert_eq!(&png.chunks()[1].chunk_type().to_string(), "TeSt");
+        assert!(png
+            .replace_chunk(0, chunk_from_strings("TeSt", "Message").unwrap())
+            .is_err());
+        assert!(png
+            .replace_chunk(1, chunk_from_strings("IEND", "").unwrap())
+            .is_err());
+        assert!(png
+            .replace_chunk(3, chunk_from_strings("TeSt", "").unwrap())
+            .is_err());
+        png.replace_chunk(2, chunk_from_strings("IEND", "").unwrap())
+            .unwrap();
+        assert_eq!(png.chunks()[2].length(), 0);
+    }
+
+    #[test]
+    fn test_remove_all_and_retain() {
+        let mut png = testing_png();
+        let test_type = ChunkType::from_str("TeSt").unwrap();
+        png.insert_chunk(1, chunk_from_strings("TeSt", "1").unwrap())
+            .unwrap();
+        png.insert_chunk(3, chunk_from_strings("TeSt", "2").unwrap())
+            .unwrap();
+        assert_eq!(png.remove_all(&test_type).unwrap().len(), 2);
+        assert_eq!(png.chunks(), testing_png().chunks());
+        assert!(png.remove_all(&test_type).unwrap().is_empty());
+
+        png.retain(|c| c.chunk_type().is_critical()).unwrap();
+        assert_eq!(png.chunks().len(), 2);
+    }
+
+    #[test]
+    fn test_remove_boundary_chunks() {
+        let mut png = testing_png();
+        for chunk_type in ["IHDR", "IEND"].iter() {
+            assert!(matches!(
+                png.remove_chunk(chunk_type),
+                Err(Error::InvalidChunkOrder)
+            ));
+            let chunk_type = ChunkType::from_str(chunk_type).unwrap();
+            assert!(matches!(
+                png.remove_all(&chunk_type),
+                Err(Error::InvalidChunkOrder)
+            ));
+            assert!(png.retain(|c| c.chunk_type() != &chunk_type).is_err());
+        }
+        assert!(png.retain(|_| false).is_err());
+        assert_eq!(png.chunks(), testing_png().chunks());
+    }
+
+    #[test]
+    fn test_chunks_mut() {
+        let mut png = testing_png();
+        let ihdr = ChunkType::from_str("IHDR").unwrap();
+        let test = ChunkType::from_str("TeSt").unwrap();
+        let mut chunks: Vec<ChunkMut> = png.chunks_mut().collect();
+        assert!(chunks[0].set_chunk_type(test.clone()).is_err());
+        assert!(chunks[1].set_chunk_type(ihdr).is_err());
+        assert!(chunks
+            .last_mut()
+            .unwrap()
+            .set_chunk_type(test.clone())
+            .is_err());
+        chunks[1].set_chunk_type(test).unwrap();
+        chunks[1].set_data(b"Message".to_vec()).unwrap();
+        drop(chunks);
+        assert_eq!(
+            png.chunk_by_type("TeSt").unwrap().as_bytes(),
+            chunk_from_strings("TeSt", "Message").unwrap().as_bytes()
+        );
+        assert_eq!(png.chunks()[0].chunk_type().to_string(), "IHDR");
+    }
+
+    #[test]
+    fn test_chunk_position() {
+        let png = testing_png();
+        for (position, index) in &[("0", 0), ("3", 3), ("before:miDl", 1), ("after:miDl", 2)] {
+            let position = ChunkPosition::from_str(position).unwrap();
+            assert_eq!(position.index(&png).unwrap(), *index);
+        }
+        assert_eq!(
+            ChunkPosition::from_str("after:IHDR").unwrap().to_string(),
+            "after:IHDR"
+        );
+        assert!(ChunkPosition::from_str("4").unwrap().index(&png).is_err());
+        assert!(ChunkPosition::from_str("after:TeSt")
+            .unwrap()
+            .index(&png)
+            .is_err());
+        assert!(ChunkPosition::from_str("inside:IDAT").is_err());
+        assert!(ChunkPosition::from_str("before:ID").is_err());
+    }
+
+    fn testing_quirky_bytes() -> Vec<u8> {
+        let mut bytes = testing_png().as_bytes();
+        let chunk_type = *b"mi\0l";
+        let data = b"I am a raw chunk";
+        let mut raw = (data.len() as u32).to_be_bytes().to_vec();
+        raw.extend_from_slice(&chunk_type);
+        raw.extend_from_slice(data);
+        raw.extend_from_slice(&Chunk::compute_crc_bytes(&chunk_type, data).to_be_bytes());
+        // a raw chunk with an invalid type code after "IHDR" and a bad checksum on "miDl"
+        let offset = testing_png().chunk_offset(1).unwrap();
+        bytes.splice(offset..offset, raw);
+        let crc = testing_png().chunk_offset(2).unwrap() + 28 - 1;
+        bytes[crc] ^= 0xff;
+        bytes
+    }
+
+    #[test]
+    fn test_lenient_parsing() {
+        let bytes = testing_quirky_bytes();
+        assert!(Png::try_from(bytes.as_ref()).is_err());
+
+        let png = Png::from_bytes_lenient(&bytes).unwrap();
+        assert_eq!(png.chunks().len(), 2);
+        assert_eq!(png.raw_chunks().len(), 2);
+        assert_eq!(png.raw_chunks()[0].0, 1);
+        assert_eq!(png.raw_chunks()[0].1.type_name(), "mi\\x00l");
+        assert!(!png.raw_chunks()[1].1.is_crc_valid());
+        assert_eq!(png.as_bytes(), bytes);
+
+        let file_chunks = png.file_chunks();
+        let indexes: Vec<Option<usize>> = file_chunks.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indexes, vec![Some(0), None, None, Some(1)]);
+        for (i, (index, _)) in file_chunks.iter().enumerate() {
+            if let Some(index) = index {
+                let offset = png.chunk_offset(*index).unwrap();
+                let before: usize = file_chunks[..i]
+                    .iter()
+                    .map(|(_, c)| 12 + c.data().len())
+                    .sum();
+                assert_eq!(offset, 8 + before);
+            }
+        }
+        assert!(Png::from_bytes_lenient(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_lenient_parsing_damaged_boundary_chunks() {
+        let mut bytes = testing_png().as_bytes();
+        // the last byte of the checksum of the "IHDR" chunk
+        let ihdr_end = 8 + 12 + testing_chunks()[0].data().len() - 1;
+        bytes[ihdr_end] ^= 1;
+        let error = Png::from_bytes_lenient(&bytes).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InvalidChunk { offset: 8, index: 0, ref source, .. }
+                if matches!(source.as_ref(), Error::InvalidCRC { .. })
+        ));
+    }
+
+    #[test]
+    fn test_edit_keeps_raw_chunks() {
+        let bytes = testing_quirky_bytes();
+        let mut png = Png::from_bytes_lenient(&bytes).unwrap();
+        let end_type = ChunkType::from_str("IEND").unwrap();
+        let test_type = ChunkType::from_str("TeSt").unwrap();
+
+        png.insert_before(&end_type, chunk_from_strings("TeSt", "1").unwrap())
+            .unwrap();
+        png.insert_chunk(1, chunk_from_strings("TeSt", "0").unwrap())
+            .unwrap();
+        assert_eq!(png.raw_chunks()[0].0, 1);
+        assert_eq!(png.raw_chunks()[1].0, 1);
+        // both new chunks follow the raw chunks, which are anchored in front of "IEND"
+        let mut expected = bytes.clone();
+        let end = expected.len() - png.chunks().last().unwrap().as_bytes().len();
+        let mut inserted = chunk_from_strings("TeSt", "0").unwrap().as_bytes();
+        inserted.extend(chunk_from_strings("TeSt", "1").unwrap().as_bytes());
+        expected.splice(end..end, inserted);
+        assert_eq!(png.as_bytes(), expected);
+
+        assert_eq!(png.remove_all(&test_type).unwrap().len(), 2);
+        assert_eq!(png.as_bytes(), bytes);
+        assert!(png.remove_chunk("IEND").is_err());
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -260,6 +1074,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::iter_cloned_collect)]
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
         let actual = png.as_bytes();
@@ -267,6 +1082,65 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_chunk_offset() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        for (i, chunk) in png.chunks().iter().enumerate() {
+            let offset = png.chunk_offset(i).unwrap();
+            assert_eq!(
+                &PNG_FILE[offset + 4..offset + 8],
+                &chunk.chunk_type().bytes()
+            );
+        }
+        assert_eq!(png.chunk_offset(png.chunks().len()), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_png_serde() {
+        let png = testing_png();
+        let json = serde_json::to_string(&png).unwrap();
+        assert!(json.contains("\"chunk_type\":\"miDl\""));
+        let parsed: Png = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.as_bytes(), png.as_bytes());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_png_serde_malformed() {
+        let chunk = |t: &str| serde_json::to_string(&chunk_from_strings(t, "").unwrap()).unwrap();
+        let raw = r#"{"chunk_type":[114,117,53,116],"chunk_data":[],"crc":0}"#;
+        let png = |chunks: &[&str], raw_chunks: &[usize]| {
+            let chunks: Vec<String> = chunks.iter().map(|t| chunk(t)).collect();
+            let raw_chunks: Vec<String> = raw_chunks
+                .iter()
+                .map(|next| format!("[{},{}]", next, raw))
+                .collect();
+            format!(
+                r#"{{"chunks":[{}],"raw_chunks":[{}]}}"#,
+                chunks.join(","),
+                raw_chunks.join(",")
+            )
+        };
+
+        let valid = png(&["IHDR", "miDl", "IEND"], &[1, 2, 2]);
+        let parsed: Png = serde_json::from_str(&valid).unwrap();
+        assert_eq!(parsed.file_chunks().len(), 6);
+        let malformed = [
+            png(&[], &[]),
+            png(&["miDl", "IEND"], &[]),
+            png(&["IHDR", "miDl"], &[]),
+            png(&["IHDR", "IEND", "miDl", "IEND"], &[]),
+            png(&["IHDR", "miDl", "IEND"], &[3]),
+            png(&["IHDR", "miDl", "IEND"], &[99]),
+            png(&["IHDR", "miDl", "IEND"], &[0]),
+            png(&["IHDR", "miDl", "IEND"], &[2, 1]),
+        ];
+        for json in malformed.iter() {
+            assert!(serde_json::from_str::<Png>(json).is_err(), "{}", json);
+        }
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -286,7 +1160,7 @@ mod tests {
     }
 
     // This is the raw bytes for a shrunken version of the `dice.png` image on Wikipedia
-    const PNG_FILE: [u8; 4803] = [
+    pub(crate) const PNG_FILE: [u8; 4803] = [
         137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 50, 0, 0, 0, 50, 8,
         6, 0, 0, 0, 30, 63, 136, 177, 0, 0, 0, 1, 115, 82, 71, 66, 0, 174, 206, 28, 233, 0, 0, 0,
         4, 103, 65, 77, 65, 0, 0, 177, 143, 11, 252, 97, 5, 0, 0, 0, 9, 112, 72, 89, 115, 0, 0, 14,
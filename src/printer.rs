@@ -0,0 +1,201 @@
+use pmsg::*;
+
+use base64::Engine;
+use serde_json::{json, Value};
+
+use std::io::{self, Write};
+
+/// The formats in which the results of a command can be printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Lines of text for people to read.
+    Text,
+    /// One JSON array of records that is printed when the command finishes.
+    Json,
+    /// One JSON record per line, printed as soon as it is known.
+    Ndjson,
+}
+
+impl Format {
+    pub const NAMES: [&'static str; 3] = ["text", "json", "ndjson"];
+}
+
+/// Printer prints the results of a command in the format that was given on the command line.
+/// Commands give every result both as a line of text and as a JSON record, and the printer only
+/// prints the one that matches the format.
+pub struct Printer {
+    format: Format,
+    records: Vec<Value>,
+    // the lines of text of one file of a batch, which are printed when the batch finishes
+    lines: Option<Vec<String>>,
+}
+
+impl Printer {
+    /// Create a printer for the format that was given on the command line.
+    pub fn new(matches: &clap::ArgMatches) -> Self {
+        let format = match matches.value_of("format") {
+            Some("json") => Format::Json,
+            Some("ndjson") => Format::Ndjson,
+            _ => Format::Text,
+        };
+        Self {
+            format,
+            records: Vec::new(),
+            lines: None,
+        }
+    }
+
+    /// Create a printer that keeps the results of one file of a batch instead of printing them, so
+    /// that the results of files that are processed at the same time are not mixed up.
+    pub fn buffered(matches: &clap::ArgMatches) -> Self {
+        Self {
+            lines: Some(Vec::new()),
+            ..Self::new(matches)
+        }
+    }
+
+    /// Check whether the results are printed as text.
+    pub fn is_text(&self) -> bool {
+        self.format == Format::Text
+    }
+
+    /// Print the given line if the results are printed as text.
+    pub fn text<T: std::fmt::Display>(&mut self, line: T) {
+        if self.is_text() {
+            match &mut self.lines {
+                Some(lines) => lines.push(line.to_string()),
+                None => println!("{}", line),
+            }
+        }
+    }
+
+    /// Print the given record if the results are printed as JSON.
+    pub fn record(&mut self, record: Value) {
+        match self.format {
+            Format::Text => {}
+            Format::Json => self.records.push(record),
+            Format::Ndjson if self.lines.is_some() => self.records.push(record),
+            Format::Ndjson => println!("{}", record),
+        }
+    }
+
+    /// Write the given bytes to the standard output as they are. This fails for a file of a
+    /// batch, whose output would be mixed up with the output of the other files.
+    pub fn raw(&mut self, data: &[u8]) -> Result<()> {
+        if self.lines.is_some() {
+            return Err(Error::Usage(
+                "raw data can not be written to the standard output for more than one file"
+                    .to_string(),
+            ));
+        }
+        let mut stdout = io::stdout();
+        stdout.write_all(data)?;
+        Ok(stdout.flush()?)
+    }
+
+    /// Print the given line or the given record, depending on the format.
+    pub fn emit<T: std::fmt::Display>(&mut self, line: T, record: Value) {
+        self.text(line);
+        self.record(record);
+    }
+
+    /// Print the records that were collected for the JSON format.
+    pub fn finish(self) {
+        if self.format == Format::Json {
+            println!("{:#}", Value::Array(self.records));
+        }
+    }
+
+    /// Take the lines of text and the records that a buffered printer kept.
+    pub fn into_results(self) -> (Vec<String>, Vec<Value>) {
+        (self.lines.unwrap_or_default(), self.records)
+    }
+}
+
+/// Get the JSON record of the given chunk, with its type, length, checksum, property bits, and
+/// data.
+pub fn chunk_record(chunk: &Chunk) -> Value {
+    let chunk_type = chunk.chunk_type();
+    let mut record = json!({
+        "type": chunk_type.to_string(),
+        "length": chunk.length(),
+        "crc": chunk.crc(),
+        "critical": chunk_type.is_critical(),
+        "public": chunk_type.is_public(),
+        "reserved_bit_valid": chunk_type.is_reserved_bit_valid(),
+        "safe_to_copy": chunk_type.is_safe_to_copy(),
+    });
+    add_data(&mut record, chunk.data());
+    record
+}
+
+/// Add the given data to the given JSON record as text if it is readable, or as base64
+/// otherwise. The "encoding" field tells which one was used.
+pub fn add_data(record: &mut Value, data: &[u8]) {
+    let (encoding, data) = match std::str::from_utf8(data) {
+        Ok(text)
+            if text
+                .chars()
+                .all(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t' | '\0')) =>
+        {
+            ("text", text.to_string())
+        }
+        _ => (
+            "base64",
+            base64::engine::general_purpose::STANDARD.encode(data),
+        ),
+    };
+    record["encoding"] = json!(encoding);
+    record["data"] = json!(data);
+}
+
+/// Get the JSON record of the given envelope, with its metadata and payload.
+pub fn envelope_record(chunk_type: &ChunkType, envelope: &Envelope) -> Value {
+    let mut record = json!({
+        "type": chunk_type.to_string(),
+        "id": envelope.id().map(|id| format!("{:08x}", id)),
+        "label": envelope.label(),
+        "timestamp": envelope.timestamp(),
+        "mime_type": envelope.mime_type(),
+        "filename": envelope.filename(),
+        "encrypted": envelope.is_encrypted(),
+        "signed": envelope.is_signed(),
+    });
+    add_data(&mut record, envelope.payload());
+    record
+}
+
+/// Get the JSON record of the given difference between the chunks of two PNG images.
+pub fn change_record(change: &ChunkChange) -> Value {
+    let chunk = change.chunk();
+    match change {
+        ChunkChange::Added { index, .. } => json!({
+            "change": "added",
+            "index": index,
+            "type": chunk.chunk_type().to_string(),
+            "length": chunk.length(),
+        }),
+        ChunkChange::Removed { index, .. } => json!({
+            "change": "removed",
+            "index": index,
+            "type": chunk.chunk_type().to_string(),
+            "length": chunk.length(),
+        }),
+        ChunkChange::Moved { from, to, .. } => json!({
+            "change": "moved",
+            "from": from,
+            "to": to,
+            "type": chunk.chunk_type().to_string(),
+            "length": chunk.length(),
+        }),
+        ChunkChange::Modified { from, to, old, new } => json!({
+            "change": "modified",
+            "from": from,
+            "to": to,
+            "type": chunk.chunk_type().to_string(),
+            "old_length": old.length(),
+            "length": new.length(),
+            "data_diff": diff_data(old.data(), new.data()),
+        }),
+    }
+}
@@ -0,0 +1,218 @@
+use crate::{Chunk, ChunkType, Error, Result};
+use std::convert::{TryFrom, TryInto};
+
+/// RawChunk is a chunk that is only parsed as far as its framing goes: the length, the four type
+/// bytes, the data and the stored checksum. Neither the type code nor the checksum have to be
+/// valid, so chunks of damaged or unusual files can be kept and written back byte for byte.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::{Chunk, RawChunk};
+/// # use std::convert::TryFrom;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let mut bytes = 4u32.to_be_bytes().to_vec();
+///     bytes.extend_from_slice(b"ru5tDATA");
+///     bytes.extend_from_slice(&[1, 2, 3, 4]);
+///
+///     let raw = RawChunk::try_from(&bytes[..])?;
+///     assert_eq!(b"DATA", raw.data());
+///     assert_eq!(0x01020304, raw.crc());
+///     assert!(!raw.is_crc_valid());
+///     assert!(raw.chunk_type().is_err());
+///     assert!(Chunk::try_from(raw.clone()).is_err());
+///     assert_eq!(bytes, raw.as_bytes());
+///     Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RawChunk {
+    chunk_type: [u8; 4],
+    chunk_data: Vec<u8>,
+    crc: u32,
+}
+
+impl RawChunk {
+    /// Get the length of the data contained in the chunk.
+    pub fn length(&self) -> u32 {
+        self.chunk_data.len() as u32
+    }
+
+    /// Get the four type bytes of the chunk as they are stored.
+    pub fn type_bytes(&self) -> [u8; 4] {
+        self.chunk_type
+    }
+
+    /// Get the type code of the chunk, which fails if the type bytes are not ASCII letters.
+    pub fn chunk_type(&self) -> Result<ChunkType> {
+        ChunkType::try_from(self.chunk_type)
+    }
+
+    /// Get the type bytes of the chunk as text, where bytes that are not ASCII letters are
+    /// escaped.
+    pub fn type_name(&self) -> String {
+        type_name(&self.chunk_type)
+    }
+
+    /// Get the data of the chunk in raw bytes.
+    pub fn data(&self) -> &[u8] {
+        &self.chunk_data
+    }
+
+    /// Get the checksum that is stored with the chunk.
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// Compute the checksum that the chunk should have.
+    pub fn computed_crc(&self) -> u32 {
+        Chunk::compute_crc_bytes(&self.chunk_type, &self.chunk_data)
+    }
+
+    /// Check whether the stored checksum matches the computed checksum.
+    pub fn is_crc_valid(&self) -> bool {
+        self.crc == self.computed_crc()
+    }
+
+    /// Get the whole chunk in bytes, exactly as it was parsed.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.length()
+            .to_be_bytes()
+            .iter()
+            .chain(self.chunk_type.iter())
+            .chain(self.chunk_data.iter())
+            .chain(self.crc.to_be_bytes().iter())
+            .copied()
+            .collect()
+    }
+}
+
+impl From<&Chunk> for RawChunk {
+    fn from(chunk: &Chunk) -> Self {
+        Self {
+            chunk_type: chunk.chunk_type().bytes(),
+            chunk_data: chunk.data().to_vec(),
+            crc: chunk.crc(),
+        }
+    }
+}
+
+impl TryFrom<&[u8]> for RawChunk {
+    type Error = Error;
+
+    /// Parse the chunk at the start of the given bytes, which may be followed by other data.
+    fn try_from(raw: &[u8]) -> Result<Self> {
+        let length = raw.get(..4).ok_or(Error::UnexpectedEndOfData {
+            expected: 4,
+            actual: raw.len(),
+        })?;
+        let length = u32::from_be_bytes(length.try_into()?);
+        if length > Chunk::MAX_LENGTH {
+            return Err(Error::InvalidChunkLength);
+        }
+
+        let end = 12 + usize::try_from(length)?;
+        if raw.len() < end {
+            return Err(Error::UnexpectedEndOfData {
+                expected: end,
+                actual: raw.len(),
+            });
+        }
+        Ok(Self {
+            chunk_type: raw[4..8].try_into()?,
+            chunk_data: raw[8..end - 4].to_vec(),
+            crc: u32::from_be_bytes(raw[end - 4..end].try_into()?),
+        })
+    }
+}
+
+/// Get the given type bytes as text, where bytes that are not ASCII letters are escaped.
+pub(crate) fn type_name(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| {
+            if b.is_ascii_alphabetic() {
+                (b as char).to_string()
+            } else {
+                format!("\\x{:02x}", b)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn framed(chunk_type: &[u8; 4], data: &[u8], crc: u32) -> Vec<u8> {
+        let mut bytes = (data.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(chunk_type);
+        bytes.extend_from_slice(data);
+        bytes.extend_from_slice(&crc.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_valid_raw_chunk() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"data".to_vec()).unwrap();
+        let raw = RawChunk::try_from(&chunk.as_bytes()[..]).unwrap();
+        assert_eq!(raw, RawChunk::from(&chunk));
+        assert!(raw.is_crc_valid());
+        assert_eq!(raw.computed_crc(), chunk.crc());
+        assert_eq!(raw.chunk_type().unwrap(), *chunk.chunk_type());
+        assert_eq!(Chunk::try_from(raw).unwrap(), chunk);
+    }
+
+    #[test]
+    fn test_bad_crc() {
+        let bytes = framed(b"RuSt", b"data", 42);
+        let raw = RawChunk::try_from(&bytes[..]).unwrap();
+        assert_eq!(raw.crc(), 42);
+        assert!(!raw.is_crc_valid());
+        assert_eq!(raw.type_name(), "RuSt");
+        assert_eq!(raw.as_bytes(), bytes);
+        assert!(matches!(
+            Chunk::try_from(raw),
+            Err(Error::InvalidCRC { actual: 42, .. })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_type() {
+        let crc = Chunk::compute_crc_bytes(b"R\0St", b"data");
+        let bytes = framed(b"R\0St", b"data", crc);
+        let raw = RawChunk::try_from(&bytes[..]).unwrap();
+        assert!(raw.is_crc_valid());
+        assert_eq!(raw.type_name(), "R\\x00St");
+        assert_eq!(raw.as_bytes(), bytes);
+        assert!(matches!(
+            Chunk::try_from(raw),
+            Err(Error::InvalidChunkType {
+                byte: 0,
+                position: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_truncated_raw_chunk() {
+        let bytes = framed(b"RuSt", b"data", 42);
+        assert!(matches!(
+            RawChunk::try_from(&bytes[..bytes.len() - 1]),
+            Err(Error::UnexpectedEndOfData {
+                expected: 16,
+                actual: 15
+            })
+        ));
+        let mut bytes = bytes;
+        bytes[0] = 0xff;
+        assert!(matches!(
+            RawChunk::try_from(&bytes[..]),
+            Err(Error::InvalidChunkLength)
+        ));
+    }
+}
@@ -0,0 +1,250 @@
+use pmsg::*;
+
+use std::io;
+
+/// The exit codes of the command line tool, which are listed in the README. Scripts can rely on
+/// them, so they must not be changed.
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_IO: i32 = 3;
+pub const EXIT_NOT_PNG: i32 = 4;
+pub const EXIT_CORRUPT: i32 = 5;
+pub const EXIT_NOT_FOUND: i32 = 6;
+pub const EXIT_VERIFICATION: i32 = 7;
+
+/// Get the exit code for the given error. Errors in the chunks of a PNG file are classified by
+/// their cause.
+pub fn exit_code(e: &Error) -> i32 {
+    match e {
+        Error::InvalidPNGFileHeader | Error::InvalidStartingChunk => EXIT_NOT_PNG,
+        Error::InvalidChunk { source, .. } => match source.as_ref() {
+            Error::InvalidStartingChunk => EXIT_NOT_PNG,
+            _ => EXIT_CORRUPT,
+        },
+        Error::InvalidChunkLength
+        | Error::InvalidCRC { .. }
+        | Error::UnexpectedEndOfData { .. }
+        | Error::InvalidEnvelopeHeader
+        | Error::UnsupportedEnvelopeVersion(_)
+        | Error::InvalidErrorCorrectionHeader
+        | Error::DecompressedSizeMismatch { .. }
+        | Error::UncorrectableErrors
+        | Error::InvalidFragmentHeader
+        | Error::MissingFragment { .. }
+        | Error::DuplicateFragment { .. }
+        | Error::InconsistentFragments { .. }
+        | Error::InvalidImageHeader
+        | Error::InvalidImageData
+        | Error::InvalidPalette => EXIT_CORRUPT,
+        Error::ChunkTypeNotFound
+        | Error::ChunkIndexOutOfRange(_)
+        | Error::MessageNotFound(_)
+        | Error::NoMessageFound(_) => EXIT_NOT_FOUND,
+        Error::InvalidEnvelopeChecksum => EXIT_VERIFICATION,
+        // values given on the command line
+        Error::InvalidChunkType { .. }
+        | Error::InvalidChunkTypeLength(_)
+        | Error::InvalidChunkPosition(_)
+        | Error::UnsupportedCompression(_)
+        | Error::InvalidCompressionLevel(_)
+        | Error::UnsupportedDisguise(_)
+        | Error::InvalidErrorCorrection(_)
+        | Error::InvalidBitPlanes(_)
+        | Error::Usage(_) => EXIT_USAGE,
+        Error::IOError(_) => EXIT_IO,
+        _ => EXIT_FAILURE,
+    }
+}
+
+/// Get a suggestion of what to do about the given error, if there is one.
+pub fn hint(e: &Error) -> Option<&'static str> {
+    match e {
+        Error::InvalidPNGFileHeader | Error::InvalidStartingChunk => {
+            Some("the file does not look like a PNG image")
+        }
+        Error::InvalidChunk {
+            source, chunk_type, ..
+        } => match source.as_ref() {
+            Error::InvalidStartingChunk => Some("the file does not look like a PNG image"),
+            // these chunks are needed to edit the file, so they are never kept as raw chunks
+            Error::InvalidCRC { .. }
+                if matches!(chunk_type.as_deref(), Some("IHDR") | Some("IEND")) =>
+            {
+                Some("damaged IHDR and IEND chunks can not be kept, not even with --lenient")
+            }
+            Error::InvalidCRC { .. } | Error::InvalidChunkType { .. } => Some(
+                "use --lenient to keep the damaged chunk as it is, or `pmsg inspect` to look at it",
+            ),
+            Error::UnexpectedEndOfData { .. } => {
+                Some("the file may have been cut off while it was copied or downloaded")
+            }
+            _ => None,
+        },
+        Error::ChunkTypeNotFound | Error::ChunkIndexOutOfRange(_) => {
+            Some("`pmsg inspect` lists the chunks of the file")
+        }
+        Error::MessageNotFound(_) | Error::NoMessageFound(_) => {
+            Some("`pmsg list` shows the messages that are hidden in the file")
+        }
+        Error::InvalidEnvelopeChecksum => Some("the hidden data was changed after it was written"),
+        Error::UncorrectableErrors => {
+            Some("encode the message with more error correction, see --ecc")
+        }
+        Error::DecompressionLimitExceeded(_) => Some("raise the limit with --max-size"),
+        Error::MultipleMessagesFound(_) => {
+            Some("give a directory to --output to restore every message")
+        }
+        Error::InvalidChunkType { .. } | Error::InvalidChunkTypeLength(_) => {
+            Some("chunk type codes are made of four ASCII letters, such as ruSt")
+        }
+        Error::IOError(e) => match e.kind() {
+            io::ErrorKind::NotFound => Some("check that the path is correct"),
+            io::ErrorKind::PermissionDenied => Some("check the permissions of the file"),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Print the given error and a hint about it, if there is one, to the standard error.
+pub fn report(e: &Error) {
+    eprintln!("error: {}", e);
+    if let Some(hint) = hint(e) {
+        eprintln!("hint: {}", hint);
+    }
+}
+
+/// Print the given error of the file at the given path and a hint about it, if there is one, to
+/// the standard error.
+pub fn report_file(path: &str, e: &Error) {
+    eprintln!("error: {}: {}", path, e);
+    if let Some(hint) = hint(e) {
+        eprintln!("hint: {}", hint);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk_error(source: Error) -> Error {
+        Error::InvalidChunk {
+            offset: 33,
+            index: 1,
+            chunk_type: Some("ruSt".to_string()),
+            source: Box::new(source),
+        }
+    }
+
+    fn io_error(kind: io::ErrorKind) -> Error {
+        Error::IOError(io::Error::new(kind, "testing"))
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let cases = vec![
+            (Error::InvalidChunkOrder, EXIT_FAILURE),
+            (Error::DuplicateChunk("eXIf".to_string()), EXIT_FAILURE),
+            (
+                Error::MultipleMessagesFound("ruSt".to_string()),
+                EXIT_FAILURE,
+            ),
+            (Error::InvalidChunkTypeLength(3), EXIT_USAGE),
+            (
+                Error::InvalidChunkType {
+                    byte: b'1',
+                    position: 2,
+                },
+                EXIT_USAGE,
+            ),
+            (Error::InvalidErrorCorrection(200), EXIT_USAGE),
+            (Error::Usage("testing".to_string()), EXIT_USAGE),
+            (io_error(io::ErrorKind::InvalidInput), EXIT_IO),
+            (io_error(io::ErrorKind::NotFound), EXIT_IO),
+            (io_error(io::ErrorKind::PermissionDenied), EXIT_IO),
+            (Error::InvalidPNGFileHeader, EXIT_NOT_PNG),
+            (chunk_error(Error::InvalidStartingChunk), EXIT_NOT_PNG),
+            (
+                chunk_error(Error::InvalidCRC {
+                    expected: 1,
+                    actual: 2,
+                }),
+                EXIT_CORRUPT,
+            ),
+            (
+                chunk_error(Error::InvalidChunkType {
+                    byte: 0,
+                    position: 0,
+                }),
+                EXIT_CORRUPT,
+            ),
+            (Error::UncorrectableErrors, EXIT_CORRUPT),
+            (
+                Error::DecompressedSizeMismatch {
+                    expected: 2,
+                    actual: 1,
+                },
+                EXIT_CORRUPT,
+            ),
+            (Error::MissingFragment { id: 1, index: 0 }, EXIT_CORRUPT),
+            (Error::ChunkTypeNotFound, EXIT_NOT_FOUND),
+            (Error::MessageNotFound(1), EXIT_NOT_FOUND),
+            (Error::NoMessageFound("ruSt".to_string()), EXIT_NOT_FOUND),
+            (Error::InvalidEnvelopeChecksum, EXIT_VERIFICATION),
+        ];
+        for (e, code) in cases {
+            assert_eq!(exit_code(&e), code, "{:?}", e);
+        }
+    }
+
+    #[test]
+    fn test_hints() {
+        let hinted = vec![
+            Error::InvalidPNGFileHeader,
+            chunk_error(Error::InvalidStartingChunk),
+            chunk_error(Error::InvalidCRC {
+                expected: 1,
+                actual: 2,
+            }),
+            chunk_error(Error::UnexpectedEndOfData {
+                expected: 12,
+                actual: 4,
+            }),
+            Error::ChunkIndexOutOfRange(9),
+            Error::NoMessageFound("ruSt".to_string()),
+            Error::InvalidEnvelopeChecksum,
+            Error::UncorrectableErrors,
+            Error::DecompressionLimitExceeded(1),
+            Error::InvalidChunkTypeLength(5),
+            Error::MultipleMessagesFound("ruSt".to_string()),
+            io_error(io::ErrorKind::NotFound),
+            io_error(io::ErrorKind::PermissionDenied),
+        ];
+        for e in hinted {
+            assert!(hint(&e).is_some(), "{:?}", e);
+        }
+        assert!(hint(&chunk_error(Error::InvalidChunkLength)).is_none());
+        let mut end_error = chunk_error(Error::InvalidCRC {
+            expected: 1,
+            actual: 2,
+        });
+        if let Error::InvalidChunk { chunk_type, .. } = &mut end_error {
+            *chunk_type = Some("IEND".to_string());
+        }
+        assert_ne!(
+            hint(&end_error),
+            hint(&chunk_error(Error::InvalidCRC {
+                expected: 1,
+                actual: 2,
+            }))
+        );
+        assert!(hint(&Error::Usage("testing".to_string())).is_none());
+        assert_eq!(
+            hint(&Error::InvalidChunkTypeLength(3)),
+            hint(&Error::InvalidChunkType {
+                byte: b'1',
+                position: 2
+            })
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{Error, Ihdr, Image, Result};
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::SystemTime;
+
+/// How the low bit planes of the samples are overwritten when sanitizing the pixels of an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitPlaneFill {
+    /// Replace the bits with random bits, which keeps the noise of the image natural.
+    Random,
+    /// Clear the bits.
+    Zero,
+}
+
+/// Overwrite the `planes` least significant bits of every sample of the given image, which
+/// destroys any data that was hidden in the pixels while keeping the changes invisible.
+///
+/// Alpha samples are sanitized as well. Indexed-color images are not supported since changing the
+/// palette indices changes the colors of the pixels.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::{sanitize_pixels, BitPlaneFill, Chunk, ChunkType, Ihdr, Image};
+/// # use std::convert::TryFrom;
+/// # use std::str::FromStr;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let data = vec![0, 0, 0, 2, 0, 0, 0, 1, 8, 0, 0, 0, 0];
+///     let header = Ihdr::try_from(&Chunk::new(ChunkType::from_str("IHDR")?, data)?)?;
+///     let mut image = Image::new(header, vec![0b1010_1011, 0b0101_0110])?;
+///     sanitize_pixels(&mut image, 2, BitPlaneFill::Zero)?;
+///     assert_eq!(&[0b1010_1000, 0b0101_0100], image.data());
+///     Ok(())
+/// # }
+/// ```
+pub fn sanitize_pixels(image: &mut Image, planes: u8, fill: BitPlaneFill) -> Result<()> {
+    let header = *image.header();
+    if header.color_type() == Ihdr::COLOR_INDEXED {
+        return Err(Error::UnsupportedColorType(header.color_type()));
+    }
+    let depth = header.bit_depth();
+    if planes == 0 || planes > depth {
+        return Err(Error::InvalidBitPlanes(planes));
+    }
+
+    // the mask of the bits to overwrite in every byte, samples with a depth of 16 bits span two
+    // bytes and samples with a depth below 8 bits are packed into one byte
+    let masks: Vec<u8> = match depth {
+        16 => {
+            let mask = ((1u32 << planes) - 1) as u16;
+            mask.to_be_bytes().to_vec()
+        }
+        _ => {
+            let sample_mask = ((1u16 << planes) - 1) as u8;
+            let mask = (0..8 / depth).fold(0u8, |m, i| m | sample_mask << (i * depth));
+            vec![mask]
+        }
+    };
+
+    let mut rng = Xorshift::new();
+    for (byte, mask) in image.data_mut().iter_mut().zip(masks.iter().cycle()) {
+        let bits = match fill {
+            BitPlaneFill::Random => rng.next_byte() & mask,
+            BitPlaneFill::Zero => 0,
+        };
+        *byte = (*byte & !mask) | bits;
+    }
+    Ok(())
+}
+
+/// A small pseudo-random number generator, the bits only need to look like noise.
+struct Xorshift(u64);
+
+impl Xorshift {
+    fn new() -> Self {
+        Self(RandomState::new().hash_one(SystemTime::now()) | 1)
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 32) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::tests::chunk;
+    use crate::Png;
+    use std::convert::TryFrom;
+
+    fn testing_image(bit_depth: u8, color_type: u8) -> Image {
+        let data = vec![0, 0, 0, 8, 0, 0, 0, 8, bit_depth, color_type, 0, 0, 0];
+        let header = Ihdr::try_from(&chunk("IHDR", data)).unwrap();
+        let data = (0..header.data_length().unwrap())
+            .map(|i| (i as u8).wrapping_mul(97))
+            .collect();
+        Image::new(header, data).unwrap()
+    }
+
+    #[test]
+    fn test_zero_planes() {
+        let mut image = testing_image(8, 6);
+        let original = image.clone();
+        sanitize_pixels(&mut image, 3, BitPlaneFill::Zero).unwrap();
+        for (a, b) in image.data().iter().zip(original.data()) {
+            assert_eq!(*a, b & !0b111);
+        }
+    }
+
+    #[test]
+    fn test_random_planes() {
+        let mut image = testing_image(8, 2);
+        let original = image.clone();
+        sanitize_pixels(&mut image, 1, BitPlaneFill::Random).unwrap();
+        let changed = image
+            .data()
+            .iter()
+            .zip(original.data())
+            .filter(|(a, b)| a != b)
+            .count();
+        assert!(image
+            .data()
+            .iter()
+            .zip(original.data())
+            .all(|(a, b)| a >> 1 == b >> 1));
+        assert!(changed > 0 && changed < image.data().len());
+    }
+
+    #[test]
+    fn test_16_bit_samples() {
+        let mut image = testing_image(16, 0);
+        let original = image.samples();
+        sanitize_pixels(&mut image, 10, BitPlaneFill::Zero).unwrap();
+        for (a, b) in image.samples().iter().zip(original) {
+            assert_eq!(*a, b & !0x3ff);
+        }
+    }
+
+    #[test]
+    fn test_sub_byte_samples() {
+        let mut image = testing_image(4, 0);
+        let original = image.samples();
+        sanitize_pixels(&mut image, 1, BitPlaneFill::Zero).unwrap();
+        for (a, b) in image.samples().iter().zip(original) {
+            assert_eq!(*a, b & !1);
+        }
+    }
+
+    #[test]
+    fn test_invalid_planes() {
+        let mut image = testing_image(8, 2);
+        assert!(sanitize_pixels(&mut image, 0, BitPlaneFill::Zero).is_err());
+        assert!(sanitize_pixels(&mut image, 9, BitPlaneFill::Zero).is_err());
+
+        let mut image = testing_image(8, 3);
+        assert!(sanitize_pixels(&mut image, 1, BitPlaneFill::Zero).is_err());
+    }
+
+    #[test]
+    fn test_sanitized_png() {
+        let png = Png::try_from(&crate::png::tests::PNG_FILE[..]).unwrap();
+        let mut image = Image::from_png(&png).unwrap();
+        sanitize_pixels(&mut image, 2, BitPlaneFill::Random).unwrap();
+        let sanitized = image.to_png(&png).unwrap();
+        assert_eq!(Image::from_png(&sanitized).unwrap(), image);
+    }
+}
@@ -0,0 +1,629 @@
+use crate::{Chunk, Envelope, Fragment, Ihdr, Image, Png, ReedSolomon, Result};
+use std::convert::TryFrom;
+
+/// Chunk types that are defined by the PNG specification and its registered extensions.
+pub const STANDARD_CHUNK_TYPES: [&str; 30] = [
+    "IHDR", "PLTE", "IDAT", "IEND", "cHRM", "cICP", "gAMA", "iCCP", "mDCV", "cLLI", "sBIT", "sRGB",
+    "bKGD", "hIST", "tRNS", "eXIf", "pHYs", "sPLT", "tIME", "iTXt", "tEXt", "zTXt", "acTL", "fcTL",
+    "fdAT", "oFFs", "pCAL", "sCAL", "sTER", "dSIG",
+];
+
+/// Private chunk types that are commonly written by image editors and operating systems.
+const COMMON_PRIVATE_CHUNK_TYPES: [&str; 1] = ["iDOT"];
+
+/// Text chunks larger than this number of bytes are reported.
+pub const TEXT_CHUNK_LIMIT: usize = 16 * 1024;
+
+/// Images with fewer color samples are too small for the statistics on the least significant bits
+/// to be meaningful.
+pub const MIN_LSB_SAMPLES: usize = 4096;
+
+/// How strongly a finding suggests that the file carries hidden data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+}
+
+impl Severity {
+    /// Get the number of points that a finding with this severity adds to the risk score.
+    pub fn weight(self) -> u32 {
+        match self {
+            Severity::Info => 0,
+            Severity::Low => 10,
+            Severity::Medium => 25,
+            Severity::High => 50,
+        }
+    }
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Severity::Info => "info",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A suspicious property of a PNG file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    severity: Severity,
+    chunk: Option<usize>,
+    message: String,
+}
+
+impl Finding {
+    fn new(severity: Severity, chunk: Option<usize>, message: String) -> Self {
+        Self {
+            severity,
+            chunk,
+            message,
+        }
+    }
+
+    /// Get the severity of the finding.
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Get the index of the chunk to which the finding refers, if any.
+    pub fn chunk(&self) -> Option<usize> {
+        self.chunk
+    }
+
+    /// Get the description of the finding.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.chunk {
+            Some(index) => write!(f, "[{}] chunk #{}: {}", self.severity, index, self.message),
+            None => write!(f, "[{}] {}", self.severity, self.message),
+        }
+    }
+}
+
+/// Statistics on the least significant bits of the pixel samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LsbAnalysis {
+    /// The highest probability given by the chi-square attack over the leading parts of the
+    /// samples, values close to 1 indicate that the least significant bits were replaced.
+    pub chi_square: f64,
+    /// The fraction of the samples over which `chi_square` was measured.
+    pub chi_square_extent: f64,
+    /// The fraction of the samples that carry a hidden bit, as estimated by the RS analysis.
+    pub rs_rate: f64,
+}
+
+/// The result of scanning a PNG file for hidden data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanReport {
+    findings: Vec<Finding>,
+    lsb: Option<LsbAnalysis>,
+}
+
+impl ScanReport {
+    /// Get the suspicious properties that were found, the most severe first.
+    pub fn findings(&self) -> &[Finding] {
+        &self.findings
+    }
+
+    /// Get the statistics on the least significant bits of the pixel samples, if the pixels
+    /// could be decoded.
+    pub fn lsb(&self) -> Option<&LsbAnalysis> {
+        self.lsb.as_ref()
+    }
+
+    /// Get a score between 0 and 100 that tells how likely the file carries hidden data.
+    pub fn risk(&self) -> u8 {
+        let score: u32 = self.findings.iter().map(|f| f.severity.weight()).sum();
+        score.min(100) as u8
+    }
+}
+
+/// Scan the given bytes of a PNG file for signs of hidden data.
+///
+/// The chunks are checked for private or unknown types, oversized text and payloads written by
+/// this crate. The file is checked for data after the "IEND" chunk and for data after the end of
+/// the zlib stream of the "IDAT" chunks. Finally, the least significant bits of the pixel samples
+/// are tested with the chi-square attack and the RS analysis.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::{scan, Chunk, ChunkType, Envelope, Png};
+/// # use std::convert::TryFrom;
+/// # use std::str::FromStr;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let png = Png::from_chunks(vec![
+///         Chunk::new(ChunkType::from_str("IHDR")?, vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0])?,
+///         Chunk::new(ChunkType::from_str("ruSt")?, Envelope::from_text("hidden").as_bytes().unwrap())?,
+///         Chunk::new(ChunkType::from_str("IEND")?, Vec::new())?,
+///     ]);
+///     let report = scan(&png.as_bytes())?;
+///     assert!(report.risk() >= 50);
+///     Ok(())
+/// # }
+/// ```
+pub fn scan(data: &[u8]) -> Result<ScanReport> {
+    let png = Png::try_from(data)?;
+    let mut findings = Vec::new();
+
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        if let Some(finding) = check_chunk(index, chunk) {
+            findings.push(finding);
+        }
+    }
+
+    let trailing = data.len().saturating_sub(png.as_bytes().len());
+    if trailing > 0 {
+        findings.push(Finding::new(
+            Severity::High,
+            None,
+            format!("{} byte(s) of data after the IEND chunk", trailing),
+        ));
+    }
+
+    let compressed = Image::compressed_data(&png);
+    match Image::stream_length(&compressed) {
+        Some(length) if length < compressed.len() => findings.push(Finding::new(
+            Severity::High,
+            None,
+            format!(
+                "{} byte(s) of data after the end of the IDAT zlib stream",
+                compressed.len() - length
+            ),
+        )),
+        Some(_) => {}
+        None => findings.push(Finding::new(
+            Severity::Medium,
+            None,
+            "the IDAT zlib stream is corrupted or incomplete".to_string(),
+        )),
+    }
+
+    let lsb = Image::from_png(&png)
+        .ok()
+        .and_then(|image| analyze_lsb(image.header(), &image.samples()));
+    if let Some(lsb) = &lsb {
+        if lsb.chi_square >= 0.99 {
+            findings.push(Finding::new(
+                Severity::High,
+                None,
+                format!(
+                    "chi-square attack detects LSB embedding (p = {:.3}) in the first {:.0}% of the samples",
+                    lsb.chi_square,
+                    lsb.chi_square_extent * 100.0
+                ),
+            ));
+        }
+        if lsb.rs_rate >= 0.1 {
+            let severity = if lsb.rs_rate >= 0.4 {
+                Severity::High
+            } else {
+                Severity::Medium
+            };
+            findings.push(Finding::new(
+                severity,
+                None,
+                format!(
+                    "RS analysis estimates that {:.0}% of the samples carry hidden bits",
+                    lsb.rs_rate * 100.0
+                ),
+            ));
+        }
+    }
+
+    findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    Ok(ScanReport { findings, lsb })
+}
+
+/// Check a single chunk for a non-standard type, oversized text, and known payload formats.
+fn check_chunk(index: usize, chunk: &Chunk) -> Option<Finding> {
+    let chunk_type = chunk.chunk_type();
+    let name = chunk_type.to_string();
+    let data = chunk.data();
+
+    if Envelope::is_envelope(data) || Fragment::is_fragment(data) || ReedSolomon::is_encoded(data) {
+        return Some(Finding::new(
+            Severity::High,
+            Some(index),
+            format!(
+                "\"{}\" chunk contains a pmsg payload ({} bytes)",
+                name,
+                data.len()
+            ),
+        ));
+    }
+
+    if STANDARD_CHUNK_TYPES.contains(&name.as_str()) {
+        let is_text = ["tEXt", "zTXt", "iTXt"].contains(&name.as_str());
+        if is_text && data.len() > TEXT_CHUNK_LIMIT {
+            return Some(Finding::new(
+                Severity::Medium,
+                Some(index),
+                format!("oversized \"{}\" chunk ({} bytes)", name, data.len()),
+            ));
+        }
+        return None;
+    }
+
+    let (severity, kind) = if chunk_type.is_critical() {
+        (Severity::Low, "unknown critical")
+    } else if !chunk_type.is_public() {
+        if COMMON_PRIVATE_CHUNK_TYPES.contains(&name.as_str()) {
+            (Severity::Info, "common private ancillary")
+        } else {
+            (Severity::Medium, "private ancillary")
+        }
+    } else {
+        (Severity::Low, "unknown public ancillary")
+    };
+    Some(Finding::new(
+        severity,
+        Some(index),
+        format!("{} chunk \"{}\" ({} bytes)", kind, name, data.len()),
+    ))
+}
+
+/// Run the chi-square attack and the RS analysis on the color samples of the given image. Alpha
+/// samples are skipped since they are usually constant, and indexed-color images are skipped since
+/// the least significant bits of their indices carry no meaning on their own.
+fn analyze_lsb(header: &Ihdr, samples: &[u16]) -> Option<LsbAnalysis> {
+    if header.color_type() == Ihdr::COLOR_INDEXED || header.bit_depth() < 8 {
+        return None;
+    }
+
+    let channels = header.channels();
+    let colors = match header.color_type() {
+        Ihdr::COLOR_GRAYSCALE_ALPHA | Ihdr::COLOR_RGBA => channels - 1,
+        _ => channels,
+    };
+    let planes: Vec<Vec<i32>> = (0..colors)
+        .map(|c| {
+            samples
+                .iter()
+                .skip(c)
+                .step_by(channels)
+                .map(|&s| s as i32)
+                .collect()
+        })
+        .collect();
+    let values: Vec<i32> = samples
+        .chunks(channels)
+        .flat_map(|pixel| pixel[..colors].iter().map(|&s| s as i32 & 0xff))
+        .collect();
+    if values.len() < MIN_LSB_SAMPLES {
+        return None;
+    }
+
+    // sequential embedding only affects the leading part of the samples, so the probability is
+    // measured over growing prefixes and the longest prefix that is close to the highest
+    // probability is reported
+    let probabilities: Vec<f64> = (1..=10)
+        .map(|step| chi_square_probability(&values[..values.len() * step / 10]))
+        .collect();
+    let chi_square = probabilities.iter().cloned().fold(0.0, f64::max);
+    let chi_square_extent = probabilities
+        .iter()
+        .rposition(|&p| p >= chi_square - 0.01)
+        .map_or(0.0, |i| (i + 1) as f64 / 10.0);
+
+    let (total, weighted) = planes
+        .iter()
+        .filter_map(|plane| rs_rate(plane).map(|rate| (plane.len(), rate)))
+        .fold((0, 0.0), |(n, sum), (len, rate)| {
+            (n + len, sum + rate * len as f64)
+        });
+    let rs_rate = if total > 0 {
+        weighted / total as f64
+    } else {
+        0.0
+    };
+
+    Some(LsbAnalysis {
+        chi_square,
+        chi_square_extent,
+        rs_rate,
+    })
+}
+
+/// The probability that the pairs of values which only differ in the least significant bit have
+/// equal frequencies, as described by Westfeld and Pfitzmann in "Attacks on Steganographic
+/// Systems".
+fn chi_square_probability(values: &[i32]) -> f64 {
+    let mut histogram = [0u64; 256];
+    for &v in values {
+        histogram[v as usize & 0xff] += 1;
+    }
+
+    let mut chi = 0.0;
+    let mut categories = 0;
+    for pair in histogram.chunks(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected > 4.0 {
+            chi += (pair[0] as f64 - expected).powi(2) / expected;
+            categories += 1;
+        }
+    }
+    if categories < 2 {
+        return 0.0;
+    }
+    upper_incomplete_gamma((categories - 1) as f64 / 2.0, chi / 2.0)
+}
+
+/// Estimate the fraction of the given samples that carry a hidden bit in their least significant
+/// bit, as described by Fridrich, Goljan and Du in "Reliable Detection of LSB Steganography in
+/// Color and Grayscale Images".
+fn rs_rate(values: &[i32]) -> Option<f64> {
+    if values.len() < 16 {
+        return None;
+    }
+
+    let flipped: Vec<i32> = values.iter().map(|v| v ^ 1).collect();
+    let (rm, sm, rn, sn) = regular_singular(values);
+    let (rm1, sm1, rn1, sn1) = regular_singular(&flipped);
+
+    let d0 = rm - sm;
+    let d1 = rm1 - sm1;
+    let dn0 = rn - sn;
+    let dn1 = rn1 - sn1;
+
+    let a = 2.0 * (d1 + d0);
+    let b = dn0 - dn1 - d1 - 3.0 * d0;
+    let c = d0 - dn0;
+    let x = if a.abs() < 1e-12 {
+        if b.abs() < 1e-12 {
+            return Some(0.0);
+        }
+        -c / b
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return Some(0.0);
+        }
+        let r1 = (-b + discriminant.sqrt()) / (2.0 * a);
+        let r2 = (-b - discriminant.sqrt()) / (2.0 * a);
+        if r1.abs() < r2.abs() {
+            r1
+        } else {
+            r2
+        }
+    };
+    let rate = x / (x - 0.5);
+    Some(if rate.is_finite() {
+        rate.clamp(0.0, 1.0)
+    } else {
+        0.0
+    })
+}
+
+/// Get the fractions of regular and singular groups of four samples under the mask `0110` and its
+/// negation.
+fn regular_singular(values: &[i32]) -> (f64, f64, f64, f64) {
+    fn smoothness(g: &[i32; 4]) -> i32 {
+        g.windows(2).map(|w| (w[1] - w[0]).abs()).sum()
+    }
+
+    let (mut rm, mut sm, mut rn, mut sn) = (0, 0, 0, 0);
+    let mut groups = 0;
+    for g in values.chunks_exact(4) {
+        let g = [g[0], g[1], g[2], g[3]];
+        let f = smoothness(&g);
+        let positive = [g[0], g[1] ^ 1, g[2] ^ 1, g[3]];
+        let negative = [g[0], ((g[1] + 1) ^ 1) - 1, ((g[2] + 1) ^ 1) - 1, g[3]];
+
+        let fp = smoothness(&positive);
+        let fn_ = smoothness(&negative);
+        rm += (fp > f) as u32;
+        sm += (fp < f) as u32;
+        rn += (fn_ > f) as u32;
+        sn += (fn_ < f) as u32;
+        groups += 1;
+    }
+
+    let n = groups as f64;
+    (rm as f64 / n, sm as f64 / n, rn as f64 / n, sn as f64 / n)
+}
+
+/// The regularized upper incomplete gamma function Q(a, x).
+fn upper_incomplete_gamma(a: f64, x: f64) -> f64 {
+    const ITERATIONS: usize = 500;
+    const EPSILON: f64 = 1e-12;
+    const TINY: f64 = 1e-300;
+
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let prefix = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        // series representation of P(a, x)
+        let mut ap = a;
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        for _ in 0..ITERATIONS {
+            ap += 1.0;
+            term *= x / ap;
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON {
+                break;
+            }
+        }
+        (1.0 - sum * prefix).clamp(0.0, 1.0)
+    } else {
+        // continued fraction representation of Q(a, x)
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / TINY;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..ITERATIONS {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = b + an / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < EPSILON {
+                break;
+            }
+        }
+        (prefix * h).clamp(0.0, 1.0)
+    }
+}
+
+/// The natural logarithm of the gamma function, computed with the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000000000190015, |s, (i, c)| s + c / (x + 1.0 + i as f64));
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::tests::{chunk, testing_png};
+
+    /// A smooth surface with some noise, which looks like a natural image to the LSB tests.
+    fn natural_rows(width: usize, height: usize) -> Vec<Vec<u8>> {
+        let mut seed: u32 = 7;
+        (0..height)
+            .map(|y| {
+                (0..width * 3)
+                    .map(|x| {
+                        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                        let noise = ((seed >> 16) % 3) as f64 - 1.0;
+                        let (x, y) = ((x / 3) as f64, y as f64);
+                        (128.0 + 60.0 * (x / 17.0).sin() * (y / 23.0).cos() + noise) as u8
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn embed_lsb(rows: &mut [Vec<u8>]) {
+        let mut seed: u32 = 99;
+        for row in rows.iter_mut() {
+            for sample in row.iter_mut() {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                *sample = (*sample & !1) | ((seed >> 16) & 1) as u8;
+            }
+        }
+    }
+
+    #[test]
+    fn test_clean_image() {
+        let png = testing_png(128, 128, 8, 2, &natural_rows(128, 128), 1);
+        let report = scan(&png.as_bytes()).unwrap();
+        assert_eq!(report.risk(), 0, "{:?}", report);
+        assert!(report.lsb().unwrap().chi_square < 0.95, "{:?}", report);
+    }
+
+    #[test]
+    fn test_png_file() {
+        let report = scan(&crate::png::tests::PNG_FILE).unwrap();
+        assert_eq!(report.findings().len(), 1, "{:?}", report);
+        assert_eq!(report.findings()[0].chunk(), Some(5));
+        assert!(report.lsb().unwrap().chi_square < 0.01);
+    }
+
+    #[test]
+    fn test_lsb_embedding() {
+        let mut rows = natural_rows(128, 128);
+        embed_lsb(&mut rows);
+        let png = testing_png(128, 128, 8, 2, &rows, 1);
+        let report = scan(&png.as_bytes()).unwrap();
+        let lsb = report.lsb().unwrap();
+        assert!(lsb.chi_square > 0.95, "{:?}", lsb);
+        assert!(lsb.rs_rate > 0.5, "{:?}", lsb);
+        assert!(report.risk() >= 50);
+    }
+
+    #[test]
+    fn test_suspicious_chunks() {
+        let png = testing_png(16, 16, 8, 2, &natural_rows(16, 16), 0);
+        let mut chunks: Vec<Chunk> = png.chunks().to_vec();
+        let iend = chunks.pop().unwrap();
+        chunks.push(chunk("ruSt", b"plain".to_vec()));
+        chunks.push(chunk("tEXx", b"public".to_vec()));
+        chunks.push(chunk("tEXt", vec![b'a'; TEXT_CHUNK_LIMIT + 1]));
+        chunks.push(chunk(
+            "ruSt",
+            Envelope::from_text("hidden").as_bytes().unwrap(),
+        ));
+        chunks.push(iend);
+
+        let mut data = Png::from_chunks(chunks).as_bytes();
+        data.extend_from_slice(b"trailing");
+        let report = scan(&data).unwrap();
+
+        let severities: Vec<(Severity, Option<usize>)> = report
+            .findings()
+            .iter()
+            .map(|f| (f.severity(), f.chunk()))
+            .collect();
+        assert_eq!(
+            severities,
+            vec![
+                (Severity::High, Some(5)),
+                (Severity::High, None),
+                (Severity::Medium, Some(2)),
+                (Severity::Medium, Some(4)),
+                (Severity::Low, Some(3)),
+            ]
+        );
+        assert_eq!(report.risk(), 100);
+    }
+
+    #[test]
+    fn test_idat_trailing_data() {
+        let png = testing_png(16, 16, 8, 2, &natural_rows(16, 16), 0);
+        let mut chunks: Vec<Chunk> = png.chunks().to_vec();
+        let mut idat = chunks[1].data().to_vec();
+        idat.extend_from_slice(b"hidden");
+        chunks[1] = chunk("IDAT", idat);
+
+        let report = scan(&Png::from_chunks(chunks).as_bytes()).unwrap();
+        assert_eq!(report.findings().len(), 1);
+        assert!(report.findings()[0].message().contains("6 byte(s)"));
+    }
+
+    #[test]
+    fn test_upper_incomplete_gamma() {
+        // chi-square survival function with 2 degrees of freedom is exp(-x / 2)
+        for &x in [0.5, 1.0, 3.0, 10.0].iter() {
+            let q = upper_incomplete_gamma(1.0, x / 2.0);
+            assert!((q - (-x / 2.0f64).exp()).abs() < 1e-9);
+        }
+    }
+}
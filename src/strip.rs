@@ -0,0 +1,162 @@
+use crate::{ChunkType, Error, Ihdr, Image, Png, Result};
+use std::convert::TryFrom;
+use std::io::Write;
+
+/// Chunk types that are needed to display the image, these are always kept when stripping.
+pub const ESSENTIAL_CHUNK_TYPES: [[u8; 4]; 4] = [*b"IHDR", *b"PLTE", *b"IDAT", *b"IEND"];
+
+/// Rebuild the given PNG image with only the chunks that are needed to display it, together with
+/// the chunks whose types are given in `keep`. The order of the remaining chunks is unchanged.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use pmsg::{strip, Chunk, ChunkType, Png};
+/// # use std::str::FromStr;
+/// #
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     let png = Png::from_chunks(vec![
+///         Chunk::new(ChunkType::from_str("IHDR")?, vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0])?,
+///         Chunk::new(ChunkType::from_str("sRGB")?, vec![0])?,
+///         Chunk::new(ChunkType::from_str("ruSt")?, b"THE HIDDEN DATA".to_vec())?,
+///         Chunk::new(ChunkType::from_str("IEND")?, Vec::new())?,
+///     ]);
+///     let stripped = strip(&png, &[ChunkType::from_str("sRGB")?]);
+///     assert_eq!(3, stripped.chunks().len());
+///     assert!(stripped.chunk_by_type("ruSt").is_none());
+///     Ok(())
+/// # }
+/// ```
+pub fn strip(png: &Png, keep: &[ChunkType]) -> Png {
+    let chunks = png
+        .chunks()
+        .iter()
+        .filter(|c| {
+            ESSENTIAL_CHUNK_TYPES.contains(&c.chunk_type().bytes()) || keep.contains(c.chunk_type())
+        })
+        .cloned()
+        .collect();
+    Png::from_chunks(chunks)
+}
+
+/// Drop the data that follows the end of the zlib stream of the image data of the given PNG
+/// image, where image decoders never look, without re-compressing the image data. The new PNG
+/// image is returned together with the number of bytes that were dropped, and the "IDAT" chunks
+/// are left as they are if there are none.
+pub fn trim_image_data(png: &Png) -> Result<(Png, usize)> {
+    let compressed = Image::compressed_data(png);
+    if compressed.is_empty() {
+        return Ok((png.clone(), 0));
+    }
+    let length = Image::stream_length(&compressed).ok_or(Error::InvalidImageData)?;
+    let trailing = compressed.len() - length;
+    if trailing == 0 {
+        return Ok((png.clone(), 0));
+    }
+    Ok((
+        Image::with_compressed_data(png, &compressed[..length])?,
+        trailing,
+    ))
+}
+
+/// Decompress the image data of the given PNG image and compress it again with the best
+/// compression level. Any data that follows the end of the original zlib stream is dropped.
+pub fn recompress(png: &Png) -> Result<Png> {
+    let header = png
+        .chunks()
+        .first()
+        .ok_or(Error::InvalidImageHeader)
+        .and_then(Ihdr::try_from)?;
+    let filtered = Image::decompress(&header, &Image::compressed_data(png))?;
+
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(&filtered)?;
+    Image::with_compressed_data(png, &encoder.finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::tests::{chunk, testing_png};
+    use std::str::FromStr;
+
+    fn testing_rows() -> Vec<Vec<u8>> {
+        (0..8)
+            .map(|y| (0..24).map(|x| (x * y) as u8).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_strip() {
+        let png = testing_png(8, 8, 8, 2, &testing_rows(), 1);
+        let mut chunks = png.chunks().to_vec();
+        chunks.insert(1, chunk("gAMA", vec![0, 0, 177, 143]));
+        chunks.insert(1, chunk("tEXt", b"Comment\0hello".to_vec()));
+        chunks.insert(3, chunk("ruSt", b"hidden".to_vec()));
+        let png = Png::from_chunks(chunks);
+
+        let stripped = strip(&png, &[]);
+        let types: Vec<String> = stripped
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["IHDR", "IDAT", "IEND"]);
+
+        let stripped = strip(&png, &[ChunkType::from_str("gAMA").unwrap()]);
+        let types: Vec<String> = stripped
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["IHDR", "gAMA", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_recompress() {
+        let png = testing_png(8, 8, 8, 2, &testing_rows(), 1);
+        let mut chunks = png.chunks().to_vec();
+        let mut data = chunks[1].data().to_vec();
+        data.extend_from_slice(b"hidden");
+        let (first, second) = data.split_at(data.len() / 2);
+        chunks[1] = chunk("IDAT", first.to_vec());
+        chunks.insert(2, chunk("IDAT", second.to_vec()));
+        let png = Png::from_chunks(chunks);
+
+        let recompressed = recompress(&png).unwrap();
+        assert_eq!(recompressed.chunks().len(), 3);
+        assert_eq!(
+            Image::from_png(&recompressed).unwrap(),
+            Image::from_png(&png).unwrap()
+        );
+        let compressed = Image::compressed_data(&recompressed);
+        assert_eq!(Image::stream_length(&compressed), Some(compressed.len()));
+    }
+
+    #[test]
+    fn test_trim_image_data() {
+        let png = testing_png(8, 8, 8, 2, &testing_rows(), 1);
+        let (trimmed, trailing) = trim_image_data(&png).unwrap();
+        assert_eq!(trailing, 0);
+        assert_eq!(trimmed.as_bytes(), png.as_bytes());
+
+        let mut chunks = png.chunks().to_vec();
+        let mut data = chunks[1].data().to_vec();
+        let original = data.clone();
+        data.extend_from_slice(b"hidden");
+        chunks[1] = chunk("IDAT", data);
+        let (trimmed, trailing) = trim_image_data(&Png::from_chunks(chunks)).unwrap();
+        assert_eq!(trailing, 6);
+        assert_eq!(Image::compressed_data(&trimmed), original);
+    }
+
+    #[test]
+    fn test_recompress_invalid_data() {
+        let png = testing_png(8, 8, 8, 2, &testing_rows(), 1);
+        let mut chunks = png.chunks().to_vec();
+        let data = chunks[1].data()[..10].to_vec();
+        chunks[1] = chunk("IDAT", data);
+        assert!(recompress(&Png::from_chunks(chunks)).is_err());
+    }
+}
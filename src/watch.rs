@@ -0,0 +1,238 @@
+use pmsg::*;
+
+use crate::batch::{find_png_files, usage_error};
+use crate::commands;
+use crate::printer::Printer;
+use crate::report;
+use serde_json::json;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// The size and modification time of a file, which tell whether it was changed.
+type FileState = (u64, Option<SystemTime>);
+
+/// Watch the given directory and tag the PNG files that appear in it or are changed, by polling
+/// it at the given interval. A file is only tagged once it did not change between two polls, so
+/// that files that are still being copied are left alone, and it is tagged again only after it
+/// is changed. Files whose names start with a dot, such as the temporary files of atomic writes,
+/// are ignored. Errors of single files and errors of reading the directory are printed and do
+/// not stop the watch, which tries again at the next poll. With `--once`, the
+/// files that are in the directory are tagged right away, and the exit code is the one of the
+/// first file that failed.
+pub fn run(matches: &clap::ArgMatches) -> Result<i32> {
+    let dir = matches.value_of("dir").unwrap();
+    if !Path::new(dir).is_dir() {
+        return Err(Error::IOError(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not a directory", dir),
+        )));
+    }
+    let interval = matches.value_of("interval").unwrap();
+    let interval = interval
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| usage_error(format!("invalid interval '{}'", interval)))?;
+    let once = matches.is_present("once");
+    let recursive = matches.is_present("recursive");
+
+    let mut printer = Printer::new(matches);
+    let mut tracker = Tracker::default();
+    let mut exit_code = 0;
+    // the last error of reading the directory, which is printed once until it is read again
+    let mut read_error = None;
+    loop {
+        let mut paths = Vec::new();
+        match find_png_files(Path::new(dir), recursive, &mut paths) {
+            Ok(()) => read_error = None,
+            Err(e) if once => return Err(e),
+            Err(e) => {
+                if read_error.as_ref() != Some(&e.to_string()) {
+                    report::report(&e);
+                    read_error = Some(e.to_string());
+                }
+                thread::sleep(interval);
+                continue;
+            }
+        }
+        let states = paths
+            .into_iter()
+            .filter(|path| !is_hidden(path))
+            // files that were removed since the directory was read are left out
+            .filter_map(|path| file_state(&path).map(|state| (path, state)))
+            .collect();
+
+        for path in tracker.poll(states, !once) {
+            if let Err(e) = commands::tag(matches, &path, &mut printer) {
+                let code = report::exit_code(&e);
+                if exit_code == 0 {
+                    exit_code = code;
+                }
+                report::report_file(&path, &e);
+                printer.record(json!({ "path": path, "error": e.to_string(), "exit_code": code }));
+            }
+            // the state after tagging, so that the written file is not seen as changed
+            let state = file_state(&path);
+            tracker.handled(path, state);
+        }
+
+        if once {
+            break;
+        }
+        thread::sleep(interval);
+    }
+    printer.finish();
+    Ok(exit_code)
+}
+
+/// Tracker remembers the state of the files of the watched directory, to tell which of them are
+/// ready to be tagged.
+#[derive(Debug, Default)]
+struct Tracker {
+    // the last known state of every file, and whether the file was handled in that state
+    files: HashMap<String, (FileState, bool)>,
+}
+
+impl Tracker {
+    /// Take the states of the files that are in the directory now and get the paths of the files
+    /// that are ready to be tagged, in the given order. Files that were not handled in their
+    /// current state are ready if they did not change since the previous poll, or right away if
+    /// no waiting is asked. Files that are gone are forgotten.
+    fn poll(&mut self, states: Vec<(String, FileState)>, wait: bool) -> Vec<String> {
+        let present: HashSet<&String> = states.iter().map(|(path, _)| path).collect();
+        self.files.retain(|path, _| present.contains(path));
+
+        let mut ready = Vec::new();
+        for (path, state) in states {
+            match self.files.get(&path) {
+                Some(&(known, true)) if known == state => {}
+                Some(&(known, false)) if known == state => ready.push(path),
+                _ if !wait => ready.push(path),
+                _ => {
+                    self.files.insert(path, (state, false));
+                }
+            }
+        }
+        ready
+    }
+
+    /// Remember that the file at the given path was handled, with its state afterwards, so that it
+    /// is only ready again after it is changed.
+    fn handled(&mut self, path: String, state: Option<FileState>) {
+        match state {
+            Some(state) => self.files.insert(path, (state, true)),
+            None => self.files.remove(&path),
+        };
+    }
+}
+
+/// Get the state of the file at the given path, if it still exists.
+fn file_state(path: &str) -> Option<FileState> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()))
+}
+
+/// Check whether the name of the file at the given path starts with a dot.
+fn is_hidden(path: &str) -> bool {
+    Path::new(path)
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn state(size: u64, seconds: u64) -> FileState {
+        (size, Some(UNIX_EPOCH + Duration::from_secs(seconds)))
+    }
+
+    fn states(files: &[(&str, FileState)]) -> Vec<(String, FileState)> {
+        files
+            .iter()
+            .map(|(path, state)| (path.to_string(), *state))
+            .collect()
+    }
+
+    #[test]
+    fn test_new_files_wait_until_unchanged() {
+        let mut tracker = Tracker::default();
+        assert!(tracker
+            .poll(states(&[("a.png", state(10, 1))]), true)
+            .is_empty());
+        // still being copied
+        assert!(tracker
+            .poll(states(&[("a.png", state(20, 2))]), true)
+            .is_empty());
+        assert_eq!(
+            tracker.poll(states(&[("a.png", state(20, 2))]), true),
+            vec!["a.png"]
+        );
+    }
+
+    #[test]
+    fn test_handled_files_are_skipped_until_changed() {
+        let mut tracker = Tracker::default();
+        tracker.poll(states(&[("a.png", state(10, 1))]), true);
+        tracker.poll(states(&[("a.png", state(10, 1))]), true);
+        // tagging writes the file, which keeps its modification time
+        tracker.handled("a.png".to_string(), Some(state(90, 1)));
+        assert!(tracker
+            .poll(states(&[("a.png", state(90, 1))]), true)
+            .is_empty());
+        assert!(tracker
+            .poll(states(&[("a.png", state(90, 1))]), true)
+            .is_empty());
+
+        // replaced by a new image
+        assert!(tracker
+            .poll(states(&[("a.png", state(15, 5))]), true)
+            .is_empty());
+        assert_eq!(
+            tracker.poll(states(&[("a.png", state(15, 5))]), true),
+            vec!["a.png"]
+        );
+    }
+
+    #[test]
+    fn test_no_waiting() {
+        let mut tracker = Tracker::default();
+        let files = states(&[("b.png", state(1, 1)), ("a.png", state(2, 2))]);
+        assert_eq!(tracker.poll(files.clone(), false), vec!["b.png", "a.png"]);
+        tracker.handled("b.png".to_string(), Some(state(1, 1)));
+        assert_eq!(tracker.poll(files, false), vec!["a.png"]);
+    }
+
+    #[test]
+    fn test_removed_files_are_forgotten() {
+        let mut tracker = Tracker::default();
+        tracker.poll(states(&[("a.png", state(10, 1))]), true);
+        tracker.poll(states(&[("a.png", state(10, 1))]), true);
+        tracker.handled("a.png".to_string(), Some(state(10, 1)));
+        assert!(tracker.poll(Vec::new(), true).is_empty());
+        // the same file comes back and is tagged again once it is complete
+        assert!(tracker
+            .poll(states(&[("a.png", state(10, 1))]), true)
+            .is_empty());
+        assert_eq!(
+            tracker.poll(states(&[("a.png", state(10, 1))]), true),
+            vec!["a.png"]
+        );
+
+        tracker.handled("a.png".to_string(), None);
+        assert!(tracker.files.is_empty());
+    }
+
+    #[test]
+    fn test_hidden_files() {
+        assert!(is_hidden("dir/.image.png.42.tmp"));
+        assert!(!is_hidden(".dir/image.png"));
+    }
+}
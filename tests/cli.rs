@@ -0,0 +1,346 @@
+use pmsg::{Chunk, ChunkType, Ihdr, Image, Png};
+
+use std::convert::TryFrom;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::str::FromStr;
+
+/// Run the command line tool with the given arguments and the given standard input.
+fn pmsg(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pmsg"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Create an empty directory for the files of the given test.
+fn testing_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pmsg-cli-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Write a small RGB image to the given path and get the path as a string.
+fn testing_png(path: PathBuf) -> String {
+    let chunk = |t: &str, data: Vec<u8>| Chunk::new(ChunkType::from_str(t).unwrap(), data).unwrap();
+    let ihdr = chunk("IHDR", vec![0, 0, 0, 4, 0, 0, 0, 4, 8, 2, 0, 0, 0]);
+    let header = Ihdr::try_from(&ihdr).unwrap();
+    let png = Png::from_chunks(vec![
+        ihdr,
+        chunk("IDAT", Vec::new()),
+        chunk("IEND", Vec::new()),
+    ]);
+    let pixels = (0..48u8).map(|i| i.wrapping_mul(37)).collect();
+    let png = Image::new(header, pixels).unwrap().to_png(&png).unwrap();
+    fs::write(&path, png.as_bytes()).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn test_decode_repairs_damaged_chunk() {
+    let dir = testing_dir("ecc");
+    let file = testing_png(dir.join("image.png"));
+    let output = pmsg(
+        &[
+            "encode",
+            &file,
+            "-t",
+            "ruSt",
+            "-m",
+            "protected message",
+            "--ecc",
+            "50",
+        ],
+        b"",
+    );
+    assert!(output.status.success());
+
+    let mut bytes = fs::read(&file).unwrap();
+    let start = bytes.windows(4).position(|w| w == b"PMSR").unwrap();
+    bytes[start + 12] ^= 0xff;
+    fs::write(&file, &bytes).unwrap();
+
+    // the damaged chunk fails its CRC check unless it is kept as a raw chunk
+    let output = pmsg(&["decode", &file, "-t", "ruSt"], b"");
+    assert_eq!(output.status.code(), Some(5));
+    let output = pmsg(&["decode", &file, "-t", "ruSt", "--lenient"], b"");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Corrected 1 corrupted byte(s)\nruSt\"protected message\"\n"
+    );
+    let output = pmsg(&["decode", &file, "-t", "ruSt", "--lenient", "--raw"], b"");
+    assert_eq!(output.stdout, b"protected message");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// Bytes that are not valid text, to make sure that nothing is converted on the way.
+fn binary_data() -> Vec<u8> {
+    (0..=255u8)
+        .chain([0, 0xff, b'\n', b'\r', 0xc3].iter().copied())
+        .collect()
+}
+
+#[test]
+fn test_file_and_raw_roundtrip() {
+    let dir = testing_dir("file");
+    let file = testing_png(dir.join("image.png"));
+    let input = dir.join("data.bin");
+    fs::write(&input, binary_data()).unwrap();
+
+    let output = pmsg(
+        &[
+            "encode",
+            &file,
+            "-t",
+            "ruSt",
+            "--file",
+            &input.to_string_lossy(),
+        ],
+        b"",
+    );
+    assert!(output.status.success());
+    let output = pmsg(&["decode", &file, "-t", "ruSt", "--raw"], b"");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, binary_data());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_stdin_roundtrip() {
+    let dir = testing_dir("stdin");
+    let file = testing_png(dir.join("image.png"));
+    let output = pmsg(&["encode", &file, "-t", "ruSt", "--stdin"], &binary_data());
+    assert!(output.status.success());
+    let output = pmsg(&["decode", &file, "-t", "ruSt", "--raw"], b"");
+    assert_eq!(output.stdout, binary_data());
+
+    // the data can not be given twice
+    let output = pmsg(
+        &["encode", &file, "-t", "ruSt", "-m", "message", "--stdin"],
+        b"",
+    );
+    assert_eq!(output.status.code(), Some(2));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_decode_output() {
+    let dir = testing_dir("output");
+    let file = testing_png(dir.join("image.png"));
+    let input = dir.join("data.bin");
+    fs::write(&input, binary_data()).unwrap();
+    let output = pmsg(
+        &[
+            "encode",
+            &file,
+            "-t",
+            "ruSt",
+            "-f",
+            &input.to_string_lossy(),
+        ],
+        b"",
+    );
+    assert!(output.status.success());
+
+    // the data goes to the file instead of the standard output
+    let target = dir.join("decoded.bin");
+    let output = pmsg(
+        &[
+            "decode",
+            &file,
+            "-t",
+            "ruSt",
+            "--output",
+            &target.to_string_lossy(),
+        ],
+        b"",
+    );
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(fs::read(&target).unwrap(), binary_data());
+
+    // a directory gets the file under the name that was kept in the envelope
+    let restored = dir.join("restored");
+    fs::create_dir(&restored).unwrap();
+    let output = pmsg(
+        &[
+            "decode",
+            &file,
+            "-t",
+            "ruSt",
+            "-o",
+            &restored.to_string_lossy(),
+        ],
+        b"",
+    );
+    assert!(output.status.success());
+    assert_eq!(fs::read(restored.join("data.bin")).unwrap(), binary_data());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_encode_output() {
+    let dir = testing_dir("encode-output");
+    let file = testing_png(dir.join("image.png"));
+    let original = fs::read(&file).unwrap();
+    let target = dir.join("tagged.png");
+
+    let output = pmsg(
+        &[
+            "encode",
+            &file,
+            "-t",
+            "ruSt",
+            "-m",
+            "message",
+            "-o",
+            &target.to_string_lossy(),
+        ],
+        b"",
+    );
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(fs::read(&file).unwrap(), original);
+    let output = pmsg(
+        &["decode", &target.to_string_lossy(), "-t", "ruSt", "--raw"],
+        b"",
+    );
+    assert_eq!(output.stdout, b"message");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_encode_and_remove_several_files() {
+    let dir = testing_dir("several");
+    let files: Vec<String> = ["a.png", "b.png", "c.png"]
+        .iter()
+        .map(|name| testing_png(dir.join(name)))
+        .collect();
+    let mut args = vec!["encode"];
+    args.extend(files.iter().map(String::as_str));
+    args.extend(&["-t", "ruSt", "-m", "message"]);
+    let output = pmsg(&args, b"");
+    assert!(output.status.success());
+    for file in &files {
+        let output = pmsg(&["decode", file, "-t", "ruSt", "--raw"], b"");
+        assert_eq!(output.stdout, b"message");
+    }
+
+    // the files can also be given as a pattern
+    let pattern = dir.join("[ab].png");
+    let output = pmsg(&["remove", &pattern.to_string_lossy(), "-t", "ruSt"], b"");
+    assert!(output.status.success());
+    let output = pmsg(&["decode", &files[0], &files[1], "-t", "ruSt"], b"");
+    assert_eq!(output.status.code(), Some(6));
+    let output = pmsg(&["decode", &files[2], "-t", "ruSt", "--raw"], b"");
+    assert_eq!(output.stdout, b"message");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_dry_run_reports_changes_once() {
+    let dir = testing_dir("dry-run");
+    let file = testing_png(dir.join("image.png"));
+    for chunk_type in &["ruSt", "teXt"] {
+        let output = pmsg(&["encode", &file, "-t", chunk_type, "-m", "message"], b"");
+        assert!(output.status.success());
+    }
+    let original = fs::read(&file).unwrap();
+
+    for args in &[&["remove", &file, "-t", "ruSt"][..], &["strip", &file]] {
+        let mut args = args.to_vec();
+        args.push("--dry-run");
+        let output = pmsg(&args, b"");
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        let (size, changes) = lines.split_last().unwrap();
+        assert!(!changes.is_empty());
+        assert!(
+            changes.iter().all(|line| line.starts_with("- #")),
+            "{}",
+            stdout
+        );
+        assert!(size.ends_with(&format!("nothing was written to {}", file)));
+        assert!(!stdout.contains("message"));
+        assert_eq!(fs::read(&file).unwrap(), original);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_strip_drops_idat_payloads() {
+    let dir = testing_dir("strip-idat");
+    let file = testing_png(dir.join("image.png"));
+    let original = fs::read(&file).unwrap();
+    let output = pmsg(
+        &[
+            "encode", &file, "-t", "ruSt", "-m", "message", "--mode", "idat",
+        ],
+        b"",
+    );
+    assert!(output.status.success());
+
+    let output = pmsg(&["strip", &file], b"");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("after the end of the image data"));
+    let output = pmsg(&["decode", &file, "-t", "ruSt"], b"");
+    assert_eq!(output.status.code(), Some(6));
+    assert_eq!(fs::read(&file).unwrap(), original);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_decode_output_keeps_payloads_with_the_same_name() {
+    let dir = testing_dir("same-name");
+    let file = testing_png(dir.join("image.png"));
+    for (i, data) in [&b"first"[..], b"second"].iter().enumerate() {
+        let input = dir.join(i.to_string());
+        fs::create_dir(&input).unwrap();
+        let input = input.join("data.bin");
+        fs::write(&input, data).unwrap();
+        let output = pmsg(
+            &[
+                "encode",
+                &file,
+                "-t",
+                "ruSt",
+                "-f",
+                &input.to_string_lossy(),
+            ],
+            b"",
+        );
+        assert!(output.status.success());
+    }
+
+    let restored = dir.join("restored");
+    fs::create_dir(&restored).unwrap();
+    let output = pmsg(
+        &[
+            "decode",
+            &file,
+            "-t",
+            "ruSt",
+            "-o",
+            &restored.to_string_lossy(),
+        ],
+        b"",
+    );
+    assert!(output.status.success());
+    assert_eq!(fs::read(restored.join("data.bin")).unwrap(), b"first");
+    assert_eq!(
+        fs::read(restored.join("data-ruSt-1.bin")).unwrap(),
+        b"second"
+    );
+    assert_eq!(fs::read_dir(&restored).unwrap().count(), 2);
+    fs::remove_dir_all(dir).unwrap();
+}